@@ -0,0 +1,617 @@
+//! Threshold alert rules, e.g. `cpu.overall > 90 for 30s`.
+//!
+//! A rule compares a metric against a threshold and, with `for <duration>`,
+//! only fires once the condition has held that long. `[*]` (or `*` for names)
+//! makes one alert per core, GPU, interface or mount. `process.<filter>
+//! absent` fires while no process matches a filter expression, `present`
+//! while one does.
+//!
+//! Metrics: `cpu.overall`, `cpu[N]`, `mem.percent|used|available`,
+//! `swap.percent|used`, `gpu[N].usage|temp|mem|mem_percent`,
+//! `net.<iface>.rx|tx` (bytes/s) and `disk.<mount>.percent|used|free`.
+
+use crate::config::parse_duration;
+use crate::filter::{self, CmpOp, Filter};
+use crate::system::snapshot::Snapshot;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// A firing alert, as carried by each snapshot.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Alert {
+    /// Name of the rule in the config.
+    pub rule: String,
+    /// The metric instance, e.g. `gpu[1].temp`.
+    pub metric: String,
+    pub value: f64,
+    /// The rule as written.
+    pub condition: String,
+    /// Unix time in milliseconds the alert fired.
+    pub since: u64,
+}
+
+impl Alert {
+    fn same(&self, other: &Alert) -> bool {
+        self.rule == other.rule && self.metric == other.metric
+    }
+
+    pub fn panel(&self) -> Panel {
+        Panel::of(&self.metric)
+    }
+}
+
+/// The part of the screen a metric belongs to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Panel {
+    Cpu,
+    Memory,
+    Gpu,
+    Network,
+    Disk,
+    Processes,
+}
+
+impl Panel {
+    fn of(metric: &str) -> Panel {
+        let family = metric.split(['.', '[']).next().unwrap_or_default();
+        match family {
+            "cpu" => Panel::Cpu,
+            "mem" | "swap" => Panel::Memory,
+            "gpu" => Panel::Gpu,
+            "net" => Panel::Network,
+            "disk" => Panel::Disk,
+            _ => Panel::Processes,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Fired,
+    Resolved,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AlertEvent {
+    pub kind: EventKind,
+    /// Unix time in milliseconds.
+    pub at: u64,
+    pub alert: Alert,
+}
+
+/// Fired and resolved events between two consecutive sets of firing alerts.
+pub fn diff(before: &[Alert], after: &[Alert], at: u64) -> Vec<AlertEvent> {
+    let fired = after
+        .iter()
+        .filter(|a| !before.iter().any(|b| b.same(a)))
+        .map(|a| (EventKind::Fired, a));
+    let resolved = before
+        .iter()
+        .filter(|b| !after.iter().any(|a| a.same(b)))
+        .map(|b| (EventKind::Resolved, b));
+    fired
+        .chain(resolved)
+        .map(|(kind, alert)| AlertEvent {
+            kind,
+            at,
+            alert: alert.clone(),
+        })
+        .collect()
+}
+
+/// Which instances of a metric family a rule covers.
+#[derive(Debug)]
+enum Selector {
+    All,
+    One(String),
+}
+
+impl Selector {
+    fn parse(text: &str) -> Selector {
+        match text {
+            "*" => Selector::All,
+            _ => Selector::One(text.to_string()),
+        }
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        match self {
+            Selector::All => true,
+            Selector::One(want) => want == name,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Metric {
+    CpuOverall,
+    Core(Selector),
+    Mem(&'static str),
+    Swap(&'static str),
+    Gpu(Selector, &'static str),
+    Net(Selector, &'static str),
+    Disk(Selector, &'static str),
+}
+
+/// Returns the entry of `fields` equal to `field`, as a static string.
+fn field(fields: &[&'static str], field: &str, family: &str) -> Result<&'static str, String> {
+    fields.iter().copied().find(|f| *f == field).ok_or_else(|| {
+        format!(
+            "unknown {} field `{}` (expected {})",
+            family,
+            field,
+            fields.join(", ")
+        )
+    })
+}
+
+/// Splits `[sel]rest` into the selector text and `rest`.
+fn bracketed(text: &str) -> Option<(&str, &str)> {
+    let text = text.strip_prefix('[')?;
+    let end = text.find(']')?;
+    Some((&text[..end], &text[end + 1..]))
+}
+
+impl Metric {
+    fn parse(text: &str) -> Result<Metric, String> {
+        let unknown = || format!("unknown metric `{}`", text);
+        if text == "cpu" || text == "cpu.overall" {
+            return Ok(Metric::CpuOverall);
+        }
+        if let Some(rest) = text.strip_prefix("cpu") {
+            let (sel, rest) = bracketed(rest).ok_or_else(unknown)?;
+            if !rest.is_empty() && rest != ".usage" {
+                return Err(unknown());
+            }
+            return Ok(Metric::Core(Selector::parse(sel)));
+        }
+        if let Some(f) = text.strip_prefix("mem.") {
+            return Ok(Metric::Mem(field(
+                &["percent", "used", "available"],
+                f,
+                "mem",
+            )?));
+        }
+        if let Some(f) = text.strip_prefix("swap.") {
+            return Ok(Metric::Swap(field(&["percent", "used"], f, "swap")?));
+        }
+        if let Some(rest) = text.strip_prefix("gpu") {
+            let (sel, rest) = bracketed(rest).ok_or_else(unknown)?;
+            let f = rest.strip_prefix('.').ok_or_else(unknown)?;
+            let f = field(&["usage", "temp", "mem", "mem_percent"], f, "gpu")?;
+            return Ok(Metric::Gpu(Selector::parse(sel), f));
+        }
+        // Interface names and mount points may contain dots; the field is
+        // whatever follows the last one.
+        for (prefix, fields) in [
+            ("net.", &["rx", "tx"][..]),
+            ("disk.", &["percent", "used", "free"][..]),
+        ] {
+            if let Some(rest) = text.strip_prefix(prefix) {
+                let (sel, f) = rest.rsplit_once('.').ok_or_else(unknown)?;
+                let family = prefix.trim_end_matches('.');
+                let f = field(fields, f, family)?;
+                let sel = Selector::parse(sel);
+                return Ok(if family == "net" {
+                    Metric::Net(sel, f)
+                } else {
+                    Metric::Disk(sel, f)
+                });
+            }
+        }
+        Err(unknown())
+    }
+
+    /// Every instance of this metric in `snapshot`, with its label and value.
+    fn values(&self, snapshot: &Snapshot, interval: Duration) -> Vec<(String, f64)> {
+        let percent = |part: u64, whole: u64| {
+            if whole == 0 {
+                0.0
+            } else {
+                part as f64 * 100.0 / whole as f64
+            }
+        };
+        let mem = &snapshot.memory;
+        match self {
+            Metric::CpuOverall => vec![("cpu.overall".to_string(), snapshot.overall_cpu as f64)],
+            Metric::Core(sel) => (0..snapshot.cpus.len())
+                .filter(|i| sel.matches(&i.to_string()))
+                .map(|i| (format!("cpu[{}]", i), snapshot.cpus[i].usage as f64))
+                .collect(),
+            Metric::Mem(f) => {
+                let value = match *f {
+                    "percent" => percent(mem.used, mem.total),
+                    "used" => mem.used as f64,
+                    _ => mem.available as f64,
+                };
+                vec![(format!("mem.{}", f), value)]
+            }
+            Metric::Swap(f) => {
+                let value = match *f {
+                    "percent" => percent(mem.used_swap, mem.total_swap),
+                    _ => mem.used_swap as f64,
+                };
+                vec![(format!("swap.{}", f), value)]
+            }
+            Metric::Gpu(sel, f) => snapshot
+                .gpus
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| sel.matches(&i.to_string()))
+                .map(|(i, gpu)| {
+                    let value = match *f {
+                        "usage" => gpu.usage as f64,
+                        "temp" => gpu.temp_c as f64,
+                        "mem" => gpu.mem_used_mb,
+                        _ if gpu.mem_total_mb > 0.0 => gpu.mem_used_mb * 100.0 / gpu.mem_total_mb,
+                        _ => 0.0,
+                    };
+                    (format!("gpu[{}].{}", i, f), value)
+                })
+                .collect(),
+            Metric::Net(sel, f) => {
+                let secs = interval.as_secs_f64().max(0.001);
+                snapshot
+                    .networks
+                    .iter()
+                    .filter(|n| sel.matches(&n.name))
+                    .map(|n| {
+                        let bytes = if *f == "rx" { n.rx_bytes } else { n.tx_bytes };
+                        (format!("net.{}.{}", n.name, f), bytes as f64 / secs)
+                    })
+                    .collect()
+            }
+            Metric::Disk(sel, f) => snapshot
+                .disks
+                .iter()
+                .filter(|d| sel.matches(&d.mount))
+                .map(|d| {
+                    let used = d.total.saturating_sub(d.available);
+                    let value = match *f {
+                        "percent" => percent(used, d.total),
+                        "used" => used as f64,
+                        _ => d.available as f64,
+                    };
+                    (format!("disk.{}.{}", d.mount, f), value)
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Condition {
+    Threshold {
+        metric: Metric,
+        op: CmpOp,
+        threshold: f64,
+    },
+    Process {
+        filter: Filter,
+        present: bool,
+        label: String,
+    },
+}
+
+#[derive(Debug)]
+pub struct Rule {
+    name: String,
+    text: String,
+    condition: Condition,
+    /// How long the condition must hold before the alert fires.
+    hold: Duration,
+}
+
+fn threshold_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"^(\S+?)\s*(>=|<=|==|!=|>|<)\s*(\S+)$").unwrap())
+}
+
+impl Rule {
+    /// Parses `text`; `named` resolves `@filters` in process rules.
+    pub fn parse(name: &str, text: &str, named: &BTreeMap<String, String>) -> Result<Rule, String> {
+        let mut body = text.trim();
+        let mut hold = Duration::ZERO;
+        // Only a trailing `for <number><unit>` is a hold time, so `for` may
+        // appear in process filters such as `cmd ~ "wait for it"`.
+        if let Some((rest, duration)) = body.rsplit_once(" for ") {
+            let duration = duration.trim();
+            if duration.starts_with(|c: char| c.is_ascii_digit())
+                && !duration.contains(char::is_whitespace)
+            {
+                hold = parse_duration(duration)?;
+                body = rest.trim();
+            }
+        }
+
+        let condition = if let Some(rest) = body.strip_prefix("process.") {
+            let (expr, present) = if let Some(expr) = rest.strip_suffix("absent") {
+                (expr, false)
+            } else if let Some(expr) = rest.strip_suffix("present") {
+                (expr, true)
+            } else {
+                return Err("process rules end in `absent` or `present`".to_string());
+            };
+            let expr = expr.trim();
+            Condition::Process {
+                filter: Filter::parse(expr, named).map_err(|e| e.to_string())?,
+                present,
+                label: format!("process.{}", expr),
+            }
+        } else {
+            let caps = threshold_regex()
+                .captures(body)
+                .ok_or("expected `metric op value`, e.g. `cpu.overall > 90`")?;
+            let op = match &caps[2] {
+                ">=" => CmpOp::Ge,
+                "<=" => CmpOp::Le,
+                "==" => CmpOp::Eq,
+                "!=" => CmpOp::Ne,
+                ">" => CmpOp::Gt,
+                _ => CmpOp::Lt,
+            };
+            Condition::Threshold {
+                metric: Metric::parse(&caps[1])?,
+                op,
+                threshold: filter::parse_number(&caps[3])
+                    .ok_or_else(|| format!("invalid threshold `{}`", &caps[3]))?,
+            }
+        };
+
+        Ok(Rule {
+            name: name.to_string(),
+            text: text.trim().to_string(),
+            condition,
+            hold,
+        })
+    }
+
+    /// Each instance this rule covers, its value and whether the condition holds.
+    fn check(&self, snapshot: &Snapshot, interval: Duration) -> Vec<(String, f64, bool)> {
+        match &self.condition {
+            Condition::Threshold {
+                metric,
+                op,
+                threshold,
+            } => metric
+                .values(snapshot, interval)
+                .into_iter()
+                .map(|(label, value)| (label, value, op.compare(value, *threshold)))
+                .collect(),
+            Condition::Process {
+                filter,
+                present,
+                label,
+            } => {
+                let count = snapshot
+                    .processes
+                    .iter()
+                    .filter(|p| p.thread_of.is_none())
+                    .filter(|p| filter.matches(p, snapshot.memory.total))
+                    .count();
+                vec![(label.clone(), count as f64, (count > 0) == *present)]
+            }
+        }
+    }
+}
+
+/// Evaluates every rule against each new snapshot.
+#[derive(Default)]
+pub struct AlertEngine {
+    rules: Vec<Rule>,
+    interval: Duration,
+    /// When each (rule, metric) condition started holding, in Unix ms.
+    pending: HashMap<(String, String), u64>,
+    firing: Vec<Alert>,
+}
+
+impl AlertEngine {
+    /// Parses `rules` (name to rule text) from the config.
+    pub fn new(
+        rules: &BTreeMap<String, String>,
+        named: &BTreeMap<String, String>,
+        interval: Duration,
+    ) -> Result<Self, String> {
+        let rules = rules
+            .iter()
+            .map(|(name, text)| {
+                Rule::parse(name, text, named).map_err(|e| format!("alert `{}`: {}", name, e))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            rules,
+            interval,
+            ..Self::default()
+        })
+    }
+
+    /// Updates the firing set from `snapshot`, stores it in
+    /// `snapshot.alerts` and returns what fired or resolved.
+    pub fn evaluate(&mut self, snapshot: &mut Snapshot) -> Vec<AlertEvent> {
+        let now = snapshot.timestamp;
+        let mut pending = HashMap::new();
+        let mut firing = Vec::new();
+
+        for rule in &self.rules {
+            for (metric, value, holds) in rule.check(snapshot, self.interval) {
+                if !holds {
+                    continue;
+                }
+                let key = (rule.name.clone(), metric);
+                let started = self.pending.get(&key).copied().unwrap_or(now);
+                if now.saturating_sub(started) >= rule.hold.as_millis() as u64 {
+                    let since = self
+                        .firing
+                        .iter()
+                        .find(|a| a.rule == key.0 && a.metric == key.1)
+                        .map_or(now, |a| a.since);
+                    firing.push(Alert {
+                        rule: key.0.clone(),
+                        metric: key.1.clone(),
+                        value,
+                        condition: rule.text.clone(),
+                        since,
+                    });
+                }
+                pending.insert(key, started);
+            }
+        }
+
+        let events = diff(&self.firing, &firing, now);
+        self.pending = pending;
+        self.firing = firing;
+        snapshot.alerts = self.firing.clone();
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::ProcessInfo;
+    use crate::system::snapshot::{CpuSample, DiskSample, GpuSample, MemorySample};
+
+    fn snapshot(timestamp: u64, cpu: f32) -> Snapshot {
+        Snapshot {
+            timestamp,
+            overall_cpu: cpu,
+            cpus: vec![
+                CpuSample {
+                    name: "cpu0".to_string(),
+                    usage: cpu,
+                    ..CpuSample::default()
+                };
+                2
+            ],
+            memory: MemorySample {
+                total: 1000,
+                used: 960,
+                ..MemorySample::default()
+            },
+            gpus: vec![
+                GpuSample {
+                    temp_c: 70,
+                    ..GpuSample::default()
+                },
+                GpuSample {
+                    temp_c: 90,
+                    ..GpuSample::default()
+                },
+            ],
+            disks: vec![DiskSample {
+                mount: "/".to_string(),
+                total: 100,
+                available: 5,
+            }],
+            processes: vec![ProcessInfo {
+                pid: 1,
+                name: "init".to_string(),
+                ..ProcessInfo::default()
+            }],
+            ..Snapshot::default()
+        }
+    }
+
+    fn engine(rules: &[(&str, &str)]) -> AlertEngine {
+        let rules = rules
+            .iter()
+            .map(|(n, r)| (n.to_string(), r.to_string()))
+            .collect();
+        AlertEngine::new(&rules, &BTreeMap::new(), Duration::from_secs(1)).unwrap()
+    }
+
+    fn firing(engine: &mut AlertEngine, snapshot: Snapshot) -> Vec<String> {
+        let mut snapshot = snapshot;
+        engine.evaluate(&mut snapshot);
+        snapshot.alerts.iter().map(|a| a.metric.clone()).collect()
+    }
+
+    #[test]
+    fn simple_thresholds() {
+        let mut e = engine(&[
+            ("mem", "mem.percent > 95"),
+            ("disk", "disk./.percent > 90"),
+            ("idle", "cpu.overall < 5"),
+        ]);
+        assert_eq!(
+            firing(&mut e, snapshot(0, 50.0)),
+            ["disk./.percent", "mem.percent"]
+        );
+    }
+
+    #[test]
+    fn wildcards_alert_per_instance() {
+        let mut e = engine(&[("hot", "gpu[*].temp > 85"), ("core", "cpu[1] >= 50")]);
+        assert_eq!(firing(&mut e, snapshot(0, 50.0)), ["cpu[1]", "gpu[1].temp"]);
+    }
+
+    #[test]
+    fn hold_duration_delays_firing() {
+        let mut e = engine(&[("busy", "cpu.overall > 90 for 30s")]);
+        assert!(firing(&mut e, snapshot(0, 95.0)).is_empty());
+        assert!(firing(&mut e, snapshot(20_000, 95.0)).is_empty());
+        assert_eq!(firing(&mut e, snapshot(30_000, 95.0)), ["cpu.overall"]);
+        // Dropping below resets the clock.
+        assert!(firing(&mut e, snapshot(31_000, 10.0)).is_empty());
+        assert!(firing(&mut e, snapshot(32_000, 95.0)).is_empty());
+    }
+
+    #[test]
+    fn for_inside_a_process_filter_is_not_a_hold() {
+        let named = BTreeMap::new();
+        let rule = Rule::parse("r", r#"process.cmd ~ "wait for it" absent"#, &named).unwrap();
+        assert_eq!(rule.hold, Duration::ZERO);
+        let rule = Rule::parse("r", r#"process.cmd ~ "for 5s" absent for 1m"#, &named).unwrap();
+        assert_eq!(rule.hold, Duration::from_secs(60));
+        assert!(matches!(
+            rule.condition,
+            Condition::Process { ref label, present: false, .. } if label == r#"process.cmd ~ "for 5s""#
+        ));
+    }
+
+    #[test]
+    fn process_absent_and_present() {
+        let mut e = engine(&[
+            ("foo", r#"process.name == "foo" absent"#),
+            ("init", "process.name == init present"),
+        ]);
+        assert_eq!(
+            firing(&mut e, snapshot(0, 0.0)),
+            [r#"process.name == "foo""#, "process.name == init"]
+        );
+    }
+
+    #[test]
+    fn events_report_fire_and_resolve() {
+        let mut e = engine(&[("busy", "cpu.overall > 90")]);
+        let mut s = snapshot(1000, 95.0);
+        let events = e.evaluate(&mut s);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kind, EventKind::Fired);
+        assert_eq!(events[0].alert.since, 1000);
+
+        assert!(e.evaluate(&mut snapshot(2000, 96.0)).is_empty());
+        let events = e.evaluate(&mut snapshot(3000, 10.0));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kind, EventKind::Resolved);
+        assert_eq!(events[0].at, 3000);
+    }
+
+    #[test]
+    fn parse_errors() {
+        let named = BTreeMap::new();
+        let err = |text| Rule::parse("r", text, &named).unwrap_err();
+        assert!(err("cpux > 5").contains("unknown metric"));
+        assert!(err("gpu[0].fan > 5").contains("expected usage, temp"));
+        assert!(err("cpu.overall > lots").contains("invalid threshold"));
+        assert!(err("cpu.overall > 5 for 5y").contains("unknown duration unit"));
+        assert!(err("process.name == foo").contains("absent"));
+        assert_eq!(Panel::of("disk./.percent"), Panel::Disk);
+        assert_eq!(Panel::of("gpu[0].temp"), Panel::Gpu);
+    }
+}
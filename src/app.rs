@@ -1,106 +1,1733 @@
+use crate::alert::{self, AlertEvent};
+use crate::config::Config;
+use crate::filter::Filter;
+use crate::system::cgroups::CgroupSample;
+use crate::system::control;
+use crate::system::recording::ReplayStatus;
+use crate::system::rollup::{keys, Rollups, Zoom};
+use crate::system::services::{self, ServiceAction};
+use crate::system::snapshot::{History, Snapshot};
 use ratatui::widgets::TableState;
-use std::collections::VecDeque;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::Duration;
-use sysinfo::{Networks, System};
 
-#[derive(PartialEq)]
+#[derive(Clone, Copy, PartialEq)]
 pub enum SortColumn {
     Pid,
     Name,
     Cpu,
     Memory,
+    Virtual,
+    Pss,
+    Uss,
+    Swap,
 }
 
-pub struct CpuHistory {
-    pub core_name: String,
-    pub usage: f32,
-    pub history: VecDeque<u64>, // Sparkline data expects u64
+impl SortColumn {
+    /// Whether sorting needs `smaps_rollup` read for every process.
+    pub fn needs_smaps(self) -> bool {
+        matches!(self, SortColumn::Pss | SortColumn::Uss | SortColumn::Swap)
+    }
+}
+
+/// How the process table aggregates rows.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GroupBy {
+    None,
+    Name,
+    User,
+    Cgroup,
+}
+
+impl GroupBy {
+    pub fn next(self) -> GroupBy {
+        match self {
+            GroupBy::None => GroupBy::Name,
+            GroupBy::Name => GroupBy::User,
+            GroupBy::User => GroupBy::Cgroup,
+            GroupBy::Cgroup => GroupBy::None,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            GroupBy::None => "",
+            GroupBy::Name => "name",
+            GroupBy::User => "user",
+            GroupBy::Cgroup => "cgroup",
+        }
+    }
+
+    fn key(self, p: &ProcessInfo) -> &str {
+        match self {
+            GroupBy::None | GroupBy::Name => &p.name,
+            GroupBy::User => &p.user,
+            GroupBy::Cgroup => &p.cgroup,
+        }
+    }
+}
+
+/// How the CPU panel lists cores.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CoreGrouping {
+    Logical,
+    /// SMT siblings averaged into one row.
+    Physical,
+    Socket,
+}
+
+impl CoreGrouping {
+    pub fn next(self) -> CoreGrouping {
+        match self {
+            CoreGrouping::Logical => CoreGrouping::Physical,
+            CoreGrouping::Physical => CoreGrouping::Socket,
+            CoreGrouping::Socket => CoreGrouping::Logical,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            CoreGrouping::Logical => "logical CPU",
+            CoreGrouping::Physical => "physical core",
+            CoreGrouping::Socket => "socket",
+        }
+    }
+}
+
+/// How the CPU panel draws cores.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CpuView {
+    /// Gauges when every core fits on its own line, otherwise the heatmap.
+    Auto,
+    Gauges,
+    /// One colored cell per core.
+    Heatmap,
+    /// Cores down, time across.
+    Timeline,
+}
+
+impl CpuView {
+    pub fn next(self) -> CpuView {
+        match self {
+            CpuView::Auto => CpuView::Gauges,
+            CpuView::Gauges => CpuView::Heatmap,
+            CpuView::Heatmap => CpuView::Timeline,
+            CpuView::Timeline => CpuView::Auto,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            CpuView::Auto => "auto",
+            CpuView::Gauges => "gauges",
+            CpuView::Heatmap => "heatmap",
+            CpuView::Timeline => "timeline",
+        }
+    }
+}
+
+/// What the bottom half of the screen shows.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BottomView {
+    Processes,
+    /// Every cgroup, with containers and pods picked out.
+    Containers,
+    /// systemd services with the usage of their cgroups.
+    Services,
+}
+
+impl BottomView {
+    pub fn next(self) -> BottomView {
+        match self {
+            BottomView::Processes => BottomView::Containers,
+            BottomView::Containers => BottomView::Services,
+            BottomView::Services => BottomView::Processes,
+        }
+    }
+}
+
+/// Totals for a set of processes sharing a group key.
+pub struct ProcessGroup {
+    pub key: String,
+    pub count: usize,
+    pub cpu_usage: f32,
+    pub memory: u64,
+    pub virtual_memory: u64,
+    pub pss: u64,
+    pub uss: u64,
+    pub swap: u64,
+    pub read_rate: u64,
+    pub write_rate: u64,
+    pub expanded: bool,
+    /// Indices into `AppState::processes`, in sort order.
+    pub members: Vec<usize>,
 }
 
-pub struct NetworkHistory {
-    pub interface_name: String,
-    pub rx_bytes: u64,
-    pub tx_bytes: u64,
-    pub rx_history: VecDeque<u64>,
-    pub tx_history: VecDeque<u64>,
+/// One line of the process table.
+pub enum ProcessRow {
+    Process(usize),
+    Group(ProcessGroup),
+    /// A process listed under its expanded group.
+    Member(usize),
+    /// A thread listed under its expanded process; indexes `AppState::threads`.
+    Thread(usize),
 }
 
-use crate::system::gpu::GpuInfo;
+/// Identifies the selected row independently of its position.
+#[derive(Clone, PartialEq, Eq)]
+pub enum RowKey {
+    Pid(u32),
+    /// A thread, kept apart from its process since the main thread shares
+    /// the process's ID.
+    Thread(u32),
+    Group(String),
+}
+
+/// A column that can be shown in the process table.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProcessColumn {
+    Pid,
+    Ppid,
+    User,
+    Name,
+    Command,
+    Exe,
+    Cpu,
+    Mem,
+    MemPercent,
+    Virt,
+    Pss,
+    Uss,
+    Swap,
+    Threads,
+    State,
+    Nice,
+    Priority,
+    Start,
+    CpuTime,
+    Read,
+    Write,
+    GpuMem,
+    Cgroup,
+    Container,
+}
 
+impl ProcessColumn {
+    pub const ALL: [ProcessColumn; 24] = [
+        ProcessColumn::Pid,
+        ProcessColumn::Ppid,
+        ProcessColumn::User,
+        ProcessColumn::Name,
+        ProcessColumn::Command,
+        ProcessColumn::Exe,
+        ProcessColumn::Cpu,
+        ProcessColumn::Mem,
+        ProcessColumn::MemPercent,
+        ProcessColumn::Virt,
+        ProcessColumn::Pss,
+        ProcessColumn::Uss,
+        ProcessColumn::Swap,
+        ProcessColumn::Threads,
+        ProcessColumn::State,
+        ProcessColumn::Nice,
+        ProcessColumn::Priority,
+        ProcessColumn::Start,
+        ProcessColumn::CpuTime,
+        ProcessColumn::Read,
+        ProcessColumn::Write,
+        ProcessColumn::GpuMem,
+        ProcessColumn::Cgroup,
+        ProcessColumn::Container,
+    ];
+
+    pub const DEFAULT: [ProcessColumn; 5] = [
+        ProcessColumn::Pid,
+        ProcessColumn::Name,
+        ProcessColumn::Cpu,
+        ProcessColumn::Mem,
+        ProcessColumn::State,
+    ];
+
+    pub fn title(self) -> &'static str {
+        match self {
+            ProcessColumn::Pid => "PID",
+            ProcessColumn::Ppid => "PPID",
+            ProcessColumn::User => "User",
+            ProcessColumn::Name => "Name",
+            ProcessColumn::Command => "Command",
+            ProcessColumn::Exe => "Exe",
+            ProcessColumn::Cpu => "CPU%",
+            ProcessColumn::Mem => "MEM",
+            ProcessColumn::MemPercent => "MEM%",
+            ProcessColumn::Virt => "VIRT",
+            ProcessColumn::Pss => "PSS",
+            ProcessColumn::Uss => "USS",
+            ProcessColumn::Swap => "SWAP",
+            ProcessColumn::Threads => "Thr",
+            ProcessColumn::State => "Status",
+            ProcessColumn::Nice => "NI",
+            ProcessColumn::Priority => "PRI",
+            ProcessColumn::Start => "Start",
+            ProcessColumn::CpuTime => "Time",
+            ProcessColumn::Read => "Read/s",
+            ProcessColumn::Write => "Write/s",
+            ProcessColumn::GpuMem => "GPU MEM",
+            ProcessColumn::Cgroup => "Cgroup",
+            ProcessColumn::Container => "Container",
+        }
+    }
+
+    /// The sort order this column reflects, used for the ▲/▼ indicator.
+    pub fn sort_column(self) -> Option<SortColumn> {
+        match self {
+            ProcessColumn::Pid => Some(SortColumn::Pid),
+            ProcessColumn::Name => Some(SortColumn::Name),
+            ProcessColumn::Cpu => Some(SortColumn::Cpu),
+            ProcessColumn::Mem | ProcessColumn::MemPercent => Some(SortColumn::Memory),
+            ProcessColumn::Virt => Some(SortColumn::Virtual),
+            ProcessColumn::Pss => Some(SortColumn::Pss),
+            ProcessColumn::Uss => Some(SortColumn::Uss),
+            ProcessColumn::Swap => Some(SortColumn::Swap),
+            _ => None,
+        }
+    }
+}
+
+/// State of the column picker dialog: every column in display order, with
+/// the hidden ones after the visible ones.
+pub struct ColumnPicker {
+    pub cursor: usize,
+    pub entries: Vec<(ProcessColumn, bool)>,
+}
+
+/// State of the CPU affinity dialog for one process, or one of its threads.
+pub struct AffinityDialog {
+    pub pid: u32,
+    /// Whether `pid` is a single thread rather than the whole process.
+    pub thread: bool,
+    pub cursor: usize,
+    pub cpus: Vec<bool>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ProcessInfo {
     pub pid: u32,
+    pub ppid: Option<u32>,
     pub name: String,
+    pub cmd: String,
+    pub exe: String,
+    pub user: String,
     pub cpu_usage: f32,
+    /// Resident set size, counting shared pages in full.
     pub memory: u64,
+    pub virtual_memory: u64,
+    /// From `smaps_rollup`, which is only read for the rows on screen
+    /// unless sorting by one of these.
+    pub pss: Option<u64>,
+    pub uss: Option<u64>,
+    pub swap: Option<u64>,
+    pub threads: Option<usize>,
     pub status: String,
+    pub nice: Option<i32>,
+    pub priority: Option<i32>,
+    pub start_time: u64,
+    pub cpu_time: Option<f64>,
+    pub read_rate: u64,
+    pub write_rate: u64,
+    pub gpu_memory: Option<u64>,
+    pub cgroup: String,
+    /// Name of the container it runs in, or the short ID when the container
+    /// engine cannot be asked.
+    pub container: String,
+    /// For a thread, the PID of the process it belongs to.
+    pub thread_of: Option<u32>,
 }
 
+/// Alert events kept for the alerts panel.
+const ALERT_LOG_LEN: usize = 200;
+
 pub struct AppState {
-    pub system: System,
-    pub networks: Networks,
-    pub cpu_history: Vec<CpuHistory>,
-    pub gpus: Option<Vec<GpuInfo>>,
-    pub overall_cpu: f32,
-    pub network_history: Vec<NetworkHistory>,
+    pub history: History,
+    /// Chart series at raw, 10 s and 1 min resolution.
+    pub rollups: Rollups,
+    pub zoom: Zoom,
+    /// Buckets the charts are scrolled back from the snapshot on screen.
+    pub chart_offset: usize,
+    /// Buckets one page of chart scrolling moves, about the width of a
+    /// chart; set as the screen is drawn.
+    pub chart_page: usize,
+    /// Playback state when showing a recording instead of the live system.
+    pub replay: Option<ReplayStatus>,
+    /// Address of the agent when showing another machine.
+    pub remote: Option<String>,
+    /// Snapshot on screen while paused; `None` follows the latest sample.
+    pub view_seq: Option<u64>,
+    /// Filtered and sorted processes of the snapshot on screen.
     pub processes: Vec<ProcessInfo>,
+    /// What the process table shows, built from `processes`.
+    pub process_rows: Vec<ProcessRow>,
+    /// Threads of every process, unless `show_threads` lists them in `processes`.
+    pub threads: Vec<ProcessInfo>,
+    /// List threads as rows of their own, like htop's `H`.
+    pub show_threads: bool,
+    pub expanded_threads: HashSet<u32>,
+    pub group_by: GroupBy,
+    pub expanded_groups: HashSet<String>,
+    pub core_grouping: CoreGrouping,
+    pub cpu_view: CpuView,
+    /// Core whose exact values the heatmap shows; `None` picks the busiest.
+    pub cpu_cursor: Option<usize>,
+    pub bottom_view: BottomView,
+    /// Path of the cgroup selected in the Containers view.
+    pub selected_cgroup: Option<String>,
+    pub cgroup_table_state: TableState,
+    /// Containers view rows visible at once, updated on every render.
+    pub cgroup_page_size: usize,
+    /// Unit selected in the Services view.
+    pub selected_service: Option<String>,
+    pub service_table_state: TableState,
+    /// Services view rows visible at once, updated on every render.
+    pub service_page_size: usize,
+    /// Action on a unit waiting for the user to confirm it.
+    pub service_confirm: Option<(ServiceAction, String)>,
+    pub columns: Vec<ProcessColumn>,
+    pub show_cmdline: bool,
+    pub column_picker: Option<ColumnPicker>,
+    pub affinity_dialog: Option<AffinityDialog>,
     pub sort_column: SortColumn,
     pub sort_ascending: bool,
     pub selected_process: usize,
+    pub selected_key: Option<RowKey>,
+    /// Keep the selected process listed and selected wherever it moves.
+    pub follow: bool,
+    /// Process table rows visible at once, updated on every render.
+    pub process_page_size: usize,
     pub process_table_state: TableState,
     pub tick_rate: Duration,
     pub should_quit: bool,
     pub search_mode: bool,
     pub search_query: String,
-    pub show_loopback: bool,
+    pub filter: Option<Filter>,
+    pub filter_error: Option<String>,
+    /// Name being typed for saving the current filter, if prompting.
+    pub filter_name_input: Option<String>,
     pub max_processes: usize,
+    pub config: Config,
+    pub config_path: Option<PathBuf>,
+    pub status_message: Option<String>,
+    /// Alerts that fired or resolved, newest last.
+    pub alert_log: VecDeque<AlertEvent>,
+    pub show_alerts: bool,
+    pub show_memory_details: bool,
 }
 
 impl AppState {
-    pub fn new(tick_rate: u64, show_loopback: bool, max_processes: usize, sort: String) -> Self {
-        let mut system = System::new_all();
-        system.refresh_all();
-
+    pub fn new(
+        tick_rate: u64,
+        history: History,
+        rollups: Rollups,
+        max_processes: usize,
+        sort: String,
+        config: Config,
+        config_path: Option<PathBuf>,
+    ) -> Self {
         let sort_column = match sort.to_lowercase().as_str() {
             "pid" => SortColumn::Pid,
             "name" => SortColumn::Name,
             "mem" | "memory" => SortColumn::Memory,
+            "virt" => SortColumn::Virtual,
+            "pss" => SortColumn::Pss,
+            "uss" => SortColumn::Uss,
+            "swap" => SortColumn::Swap,
             _ => SortColumn::Cpu,
         };
 
-        Self {
-            system,
-            networks: Networks::new_with_refreshed_list(),
-            cpu_history: Vec::new(),
-            gpus: None,
-            overall_cpu: 0.0,
-            network_history: Vec::new(),
+        let columns = config
+            .columns
+            .clone()
+            .filter(|c| !c.is_empty())
+            .unwrap_or_else(|| ProcessColumn::DEFAULT.to_vec());
+
+        let mut app = Self {
+            history,
+            rollups,
+            zoom: Zoom::Raw,
+            chart_offset: 0,
+            chart_page: 60,
+            replay: None,
+            remote: None,
+            view_seq: None,
             processes: Vec::new(),
+            process_rows: Vec::new(),
+            threads: Vec::new(),
+            show_threads: false,
+            expanded_threads: HashSet::new(),
+            group_by: GroupBy::None,
+            expanded_groups: HashSet::new(),
+            core_grouping: CoreGrouping::Logical,
+            cpu_view: CpuView::Auto,
+            cpu_cursor: None,
+            bottom_view: BottomView::Processes,
+            selected_cgroup: None,
+            cgroup_table_state: TableState::default(),
+            cgroup_page_size: 0,
+            selected_service: None,
+            service_table_state: TableState::default(),
+            service_page_size: 0,
+            service_confirm: None,
+            columns,
+            show_cmdline: config.show_cmdline,
+            column_picker: None,
+            affinity_dialog: None,
             sort_column,
             sort_ascending: false,
             selected_process: 0,
+            selected_key: None,
+            follow: false,
+            process_page_size: 0,
             process_table_state: TableState::default(),
             tick_rate: Duration::from_millis(tick_rate),
             should_quit: false,
             search_mode: false,
             search_query: String::new(),
-            show_loopback,
+            filter: None,
+            filter_error: None,
+            filter_name_input: None,
             max_processes,
+            config,
+            config_path,
+            status_message: None,
+            alert_log: VecDeque::new(),
+            show_alerts: false,
+            show_memory_details: false,
+        };
+        let first = app.history.latest().clone();
+        app.log_alerts(&[], &first);
+        app.refresh_view();
+        app
+    }
+
+    /// The snapshot on screen.
+    pub fn snapshot(&self) -> &Arc<Snapshot> {
+        match self.view_seq {
+            Some(seq) => self.history.get(seq),
+            None => self.history.latest(),
         }
     }
 
-    pub fn next_process(&mut self) {
-        if self.processes.is_empty() {
+    /// One point of series `key` per column of a `width`-wide chart at
+    /// the current zoom, ending at the snapshot on screen less the scroll.
+    pub fn series(&self, key: &str, width: u16) -> Vec<u64> {
+        self.rollups.series(
+            key,
+            self.zoom,
+            self.snapshot().timestamp,
+            width as usize,
+            self.chart_offset,
+        )
+    }
+
+    pub fn cycle_core_grouping(&mut self) {
+        self.core_grouping = self.core_grouping.next();
+        self.cpu_cursor = None;
+        self.status_message = Some(format!("CPU cores by {}", self.core_grouping.label()));
+    }
+
+    pub fn cycle_cpu_view(&mut self) {
+        self.cpu_view = self.cpu_view.next();
+        self.status_message = Some(format!("CPU view: {}", self.cpu_view.label()));
+    }
+
+    /// Moves the heatmap cursor `delta` cores, wrapping around; `count` is
+    /// the number of cells drawn.
+    pub fn move_cpu_cursor(&mut self, delta: isize, count: usize) {
+        if count == 0 {
             return;
         }
-        self.selected_process = (self.selected_process + 1).min(self.processes.len() - 1);
-        self.process_table_state.select(Some(self.selected_process));
+        let current = self.cpu_cursor.unwrap_or(0).min(count - 1) as isize;
+        self.cpu_cursor = Some((current + delta).rem_euclid(count as isize) as usize);
+    }
+
+    pub fn cycle_zoom(&mut self) {
+        self.zoom = self.zoom.next();
+        self.chart_offset = 0;
+        self.status_message = Some(format!(
+            "Chart zoom: {} (retention {})",
+            self.zoom.label(),
+            format_duration(self.rollups.retention())
+        ));
+    }
+
+    /// Scrolls the charts `pages` pages back (or forward, if negative)
+    /// through the history kept at the current zoom.
+    pub fn scroll_charts(&mut self, pages: isize) {
+        if self.zoom == Zoom::All {
+            self.status_message = Some("Charts already show the whole retention".to_string());
+            return;
+        }
+        // Every chart shares the sample clock, so the CPU series tells how
+        // much history there is to page through.
+        let kept = self
+            .rollups
+            .buckets(keys::CPU, self.zoom, self.snapshot().timestamp)
+            .len();
+        let step = pages * self.chart_page as isize;
+        let limit = kept.min(self.rollups.capacity(self.zoom)) as isize;
+        self.chart_offset = (self.chart_offset as isize + step).clamp(0, limit) as usize;
+    }
+
+    /// How far back the charts are scrolled, as a duration.
+    pub fn chart_scroll(&self) -> Duration {
+        self.rollups.bucket_width(self.zoom, 1) * self.chart_offset as u32
+    }
+
+    /// Records a new sample; the screen only moves to it when not paused.
+    pub fn push_snapshot(&mut self, snapshot: Snapshot) {
+        let previous = self.history.latest().clone();
+        self.log_alerts(&previous.alerts, &snapshot);
+        self.rollups.record(&snapshot);
+        self.history.push(snapshot);
+        match self.view_seq {
+            // Keep a paused view on a snapshot that is still retained.
+            Some(seq) => self.view_seq = Some(seq.max(self.history.oldest().seq)),
+            None => self.refresh_view(),
+        }
+    }
+
+    /// Replaces all history with `snapshots` (oldest first) and the chart
+    /// rollups with `rollups`, as after seeking in a replay. Nothing
+    /// changes if there are no snapshots.
+    pub fn reset_history(
+        &mut self,
+        rollups: Rollups,
+        snapshots: impl IntoIterator<Item = Snapshot>,
+    ) {
+        let mut snapshots = snapshots.into_iter();
+        let Some(first) = snapshots.next() else {
+            return;
+        };
+        self.rollups = rollups;
+        self.history = History::new(self.history.capacity(), first);
+        for snapshot in snapshots {
+            self.history.push(snapshot);
+        }
+
+        self.view_seq = None;
+        self.refresh_view();
+    }
+
+    fn log_alerts(&mut self, before: &[alert::Alert], snapshot: &Snapshot) {
+        self.alert_log
+            .extend(alert::diff(before, &snapshot.alerts, snapshot.timestamp));
+        while self.alert_log.len() > ALERT_LOG_LEN {
+            self.alert_log.pop_front();
+        }
+    }
+
+    /// Whether an alert on `panel` is firing in the snapshot on screen.
+    pub fn panel_alerting(&self, panel: alert::Panel) -> bool {
+        self.snapshot().alerts.iter().any(|a| a.panel() == panel)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.view_seq.is_some()
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.view_seq = match self.view_seq {
+            Some(_) => None,
+            None => Some(self.history.latest().seq),
+        };
+        self.refresh_view();
+    }
+
+    /// Moves the timeline cursor by `delta` samples, pausing if needed.
+    pub fn step_timeline(&mut self, delta: i64) {
+        let current = self.snapshot().seq;
+        let target = (current as i64 + delta).clamp(
+            self.history.oldest().seq as i64,
+            self.history.latest().seq as i64,
+        );
+        self.view_seq = Some(target as u64);
+        self.refresh_view();
+    }
+
+    /// Rebuilds the process list from the snapshot on screen: splits off
+    /// threads, applies the filter and sort, then rebuilds the table rows.
+    pub fn refresh_view(&mut self) {
+        let snapshot = Arc::clone(self.snapshot());
+        let mut processes = snapshot.processes.clone();
+
+        // Threads live in their own list unless shown as rows
+        if self.show_threads {
+            self.threads.clear();
+        } else {
+            let (threads, rest) = processes.into_iter().partition(|p| p.thread_of.is_some());
+            self.threads = threads;
+            processes = rest;
+            sort_processes(&mut self.threads, &self.sort_column, self.sort_ascending);
+        }
+
+        // Apply search filter
+        if let Some(filter) = &self.filter {
+            let total_memory = snapshot.memory.total;
+            processes.retain(|p| filter.matches(p, total_memory));
+        }
+
+        // Sort
+        sort_processes(&mut processes, &self.sort_column, self.sort_ascending);
+
+        self.processes = processes;
+        self.rebuild_rows();
+    }
+
+    pub fn set_sort(&mut self, column: SortColumn) {
+        self.sort_column = column;
+        self.refresh_view();
+    }
+
+    /// Sorts by the next shown column that can be sorted on.
+    pub fn cycle_sort(&mut self) {
+        let mut sortable: Vec<SortColumn> = Vec::new();
+        for column in self.columns.iter().filter_map(|c| c.sort_column()) {
+            if !sortable.contains(&column) {
+                sortable.push(column);
+            }
+        }
+        let next = match sortable.iter().position(|&c| c == self.sort_column) {
+            Some(i) => sortable.get(i + 1).or(sortable.first()),
+            None => sortable.first(),
+        };
+        if let Some(&column) = next {
+            self.set_sort(column);
+        }
+    }
+
+    /// Processes to read `smaps_rollup` for on the next sample: `None` for
+    /// all of them, otherwise those on screen if a column needs it.
+    pub fn smaps_pids(&self) -> Option<HashSet<u32>> {
+        if self.sort_column.needs_smaps() {
+            return None;
+        }
+        let mut pids = HashSet::new();
+        let shown = [ProcessColumn::Pss, ProcessColumn::Uss, ProcessColumn::Swap];
+        if !self.columns.iter().any(|c| shown.contains(c)) {
+            return Some(pids);
+        }
+        let offset = self.process_table_state.offset();
+        for row in self.process_rows.iter().skip(offset).take(self.page_size()) {
+            match row {
+                ProcessRow::Process(i) | ProcessRow::Member(i) => {
+                    pids.insert(self.processes[*i].pid);
+                }
+                ProcessRow::Group(g) => {
+                    pids.extend(g.members.iter().map(|&i| self.processes[i].pid));
+                }
+                ProcessRow::Thread(_) => {}
+            }
+        }
+        Some(pids)
+    }
+
+    pub fn reverse_sort(&mut self) {
+        self.sort_ascending = !self.sort_ascending;
+        self.refresh_view();
+    }
+
+    pub fn toggle_show_threads(&mut self) {
+        self.show_threads = !self.show_threads;
+        self.refresh_view();
+    }
+
+    pub fn next_process(&mut self) {
+        self.select_row(self.selected_process.saturating_add(1));
     }
 
     pub fn previous_process(&mut self) {
-        if self.processes.is_empty() {
+        self.select_row(self.selected_process.saturating_sub(1));
+    }
+
+    pub fn page_down(&mut self) {
+        self.select_row(self.selected_process.saturating_add(self.page_size()));
+    }
+
+    pub fn page_up(&mut self) {
+        self.select_row(self.selected_process.saturating_sub(self.page_size()));
+    }
+
+    pub fn first_process(&mut self) {
+        self.select_row(0);
+    }
+
+    pub fn last_process(&mut self) {
+        self.select_row(usize::MAX);
+    }
+
+    fn page_size(&self) -> usize {
+        self.process_page_size.max(1)
+    }
+
+    /// Selects row `index` (clamped) and remembers what is on it, so the
+    /// selection can follow that process across refreshes.
+    fn select_row(&mut self, index: usize) {
+        if self.process_rows.is_empty() {
             return;
         }
-        self.selected_process = self.selected_process.saturating_sub(1);
+        self.selected_process = index.min(self.process_rows.len() - 1);
+        self.selected_key = Some(self.row_key(&self.process_rows[self.selected_process]));
         self.process_table_state.select(Some(self.selected_process));
     }
+
+    fn row_key(&self, row: &ProcessRow) -> RowKey {
+        match row {
+            ProcessRow::Process(i) | ProcessRow::Member(i) => process_key(&self.processes[*i]),
+            ProcessRow::Thread(i) => process_key(&self.threads[*i]),
+            ProcessRow::Group(g) => RowKey::Group(g.key.clone()),
+        }
+    }
+
+    /// Moves the selection to wherever the selected process ended up after
+    /// `process_rows` was rebuilt. If it is gone, the cursor stays at the
+    /// same position but keeps the exited process's key, so the row now
+    /// there is not acted on until the user picks it, and follow mode ends.
+    fn relocate_selection(&mut self) {
+        if self.process_rows.is_empty() {
+            self.process_table_state.select(None);
+            return;
+        }
+
+        let found = self.selected_key.as_ref().and_then(|key| {
+            self.process_rows
+                .iter()
+                .position(|row| self.row_key(row) == *key)
+        });
+        match found {
+            Some(index) => self.select_row(index),
+            None => {
+                if self.follow {
+                    if let Some(RowKey::Pid(pid) | RowKey::Thread(pid)) = &self.selected_key {
+                        self.status_message =
+                            Some(format!("Stopped following {}: no longer listed", pid));
+                    }
+                    self.follow = false;
+                }
+                if self.selected_key.is_none() {
+                    self.select_row(self.selected_process);
+                } else {
+                    self.selected_process = self.selected_process.min(self.process_rows.len() - 1);
+                    self.process_table_state.select(Some(self.selected_process));
+                }
+            }
+        }
+
+        let offset = self.process_table_state.offset();
+        *self.process_table_state.offset_mut() = offset.min(self.process_rows.len() - 1);
+    }
+
+    pub fn toggle_follow(&mut self) {
+        self.follow = !self.follow && matches!(self.selected_key, Some(RowKey::Pid(_)));
+    }
+
+    /// PID being followed, if follow mode is on.
+    pub fn followed_pid(&self) -> Option<u32> {
+        match (&self.selected_key, self.follow) {
+            (Some(RowKey::Pid(pid)), true) => Some(*pid),
+            _ => None,
+        }
+    }
+
+    /// Cgroup selected in the Containers view, of the unit selected in the
+    /// Services view, or of the selected row while the process table is
+    /// grouped by cgroup.
+    pub fn viewed_cgroup(&self) -> Option<String> {
+        match self.bottom_view {
+            BottomView::Containers => return self.selected_cgroup.clone().filter(|c| c != "/"),
+            BottomView::Services => {
+                let unit = self.selected_service.as_ref()?;
+                return self.unit_cgroup(unit).map(|c| c.path.clone());
+            }
+            BottomView::Processes => {}
+        }
+        if self.group_by != GroupBy::Cgroup {
+            return None;
+        }
+        let cgroup = match self.process_rows.get(self.selected_process)? {
+            ProcessRow::Group(g) => &g.key,
+            ProcessRow::Process(i) | ProcessRow::Member(i) => &self.processes[*i].cgroup,
+            ProcessRow::Thread(i) => &self.threads[*i].cgroup,
+        };
+        Some(cgroup.clone()).filter(|c| !c.is_empty())
+    }
+
+    /// Rebuilds `process_rows` from the sorted `processes`, aggregating them
+    /// when a grouping mode is active, then re-locates the selection.
+    pub fn rebuild_rows(&mut self) {
+        let mut rows = self.build_rows();
+
+        // A followed process stays listed even when it falls past the row limit.
+        if let Some(pid) = self.followed_pid() {
+            let listed = rows.iter().any(|row| self.row_key(row) == RowKey::Pid(pid));
+            if !listed {
+                let process = |p: &ProcessInfo| p.pid == pid && p.thread_of.is_none();
+                if let Some(i) = self.processes.iter().position(process) {
+                    rows.push(ProcessRow::Process(i));
+                }
+            }
+        }
+
+        self.process_rows = rows;
+        self.relocate_selection();
+    }
+
+    fn build_rows(&self) -> Vec<ProcessRow> {
+        let mut threads_by_owner: HashMap<u32, Vec<usize>> = HashMap::new();
+        for (i, t) in self.threads.iter().enumerate() {
+            if let Some(owner) = t.thread_of {
+                if self.expanded_threads.contains(&owner) {
+                    threads_by_owner.entry(owner).or_default().push(i);
+                }
+            }
+        }
+        let threads_of = |pid: u32| {
+            threads_by_owner
+                .get(&pid)
+                .into_iter()
+                .flatten()
+                .map(|&t| ProcessRow::Thread(t))
+        };
+
+        if self.group_by == GroupBy::None {
+            let mut rows = Vec::new();
+            for (i, p) in self.processes.iter().enumerate() {
+                rows.push(ProcessRow::Process(i));
+                rows.extend(threads_of(p.pid));
+            }
+            rows.truncate(self.max_processes);
+            return rows;
+        }
+
+        // Threads shown as rows join their process's group, whatever
+        // their own name, even when the filter leaves the process out.
+        let snapshot = self.snapshot();
+        let owners: HashMap<u32, &ProcessInfo> = snapshot
+            .processes
+            .iter()
+            .filter(|p| p.thread_of.is_none())
+            .map(|p| (p.pid, p))
+            .collect();
+        let mut groups: Vec<ProcessGroup> = Vec::new();
+        let mut by_key: HashMap<&str, usize> = HashMap::new();
+        for (i, p) in self.processes.iter().enumerate() {
+            let owner = p.thread_of.and_then(|pid| owners.get(&pid).copied());
+            let key = self.group_by.key(owner.unwrap_or(p));
+            let slot = *by_key.entry(key).or_insert_with(|| {
+                groups.push(ProcessGroup {
+                    key: key.to_string(),
+                    count: 0,
+                    cpu_usage: 0.0,
+                    memory: 0,
+                    virtual_memory: 0,
+                    pss: 0,
+                    uss: 0,
+                    swap: 0,
+                    read_rate: 0,
+                    write_rate: 0,
+                    expanded: self.expanded_groups.contains(key),
+                    members: Vec::new(),
+                });
+                groups.len() - 1
+            });
+            let group = &mut groups[slot];
+            group.members.push(i);
+            // Threads listed as rows are already counted in their process.
+            if p.thread_of.is_some() {
+                continue;
+            }
+            group.count += 1;
+            group.cpu_usage += p.cpu_usage;
+            group.memory += p.memory;
+            group.virtual_memory += p.virtual_memory;
+            group.pss += p.pss.unwrap_or(0);
+            group.uss += p.uss.unwrap_or(0);
+            group.swap += p.swap.unwrap_or(0);
+            group.read_rate += p.read_rate;
+            group.write_rate += p.write_rate;
+        }
+
+        match self.sort_column {
+            SortColumn::Pid => {
+                groups.sort_by_key(|g| g.members.first().map(|&i| self.processes[i].pid))
+            }
+            SortColumn::Name => groups.sort_by(|a, b| a.key.cmp(&b.key)),
+            SortColumn::Cpu => groups.sort_by(|a, b| {
+                a.cpu_usage
+                    .partial_cmp(&b.cpu_usage)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            }),
+            SortColumn::Memory => groups.sort_by_key(|g| g.memory),
+            SortColumn::Virtual => groups.sort_by_key(|g| g.virtual_memory),
+            SortColumn::Pss => groups.sort_by_key(|g| g.pss),
+            SortColumn::Uss => groups.sort_by_key(|g| g.uss),
+            SortColumn::Swap => groups.sort_by_key(|g| g.swap),
+        }
+        if !self.sort_ascending {
+            groups.reverse();
+        }
+
+        let mut rows = Vec::new();
+        for group in groups {
+            let members: Vec<usize> = if group.expanded {
+                group.members.clone()
+            } else {
+                Vec::new()
+            };
+            rows.push(ProcessRow::Group(group));
+            for i in members {
+                rows.push(ProcessRow::Member(i));
+                rows.extend(threads_of(self.processes[i].pid));
+            }
+        }
+        rows.truncate(self.max_processes);
+        rows
+    }
+
+    pub fn cycle_bottom_view(&mut self) {
+        self.bottom_view = self.bottom_view.next();
+    }
+
+    /// Row of the selected cgroup in the snapshot on screen, or the first.
+    pub fn cgroup_index(&self) -> usize {
+        let cgroups = &self.snapshot().cgroups;
+        self.selected_cgroup
+            .as_ref()
+            .and_then(|path| cgroups.iter().position(|c| &c.path == path))
+            .unwrap_or(0)
+    }
+
+    /// Moves the Containers view selection by `delta` rows, clamped.
+    pub fn move_cgroup_cursor(&mut self, delta: isize) {
+        let cgroups = &self.snapshot().cgroups;
+        if cgroups.is_empty() {
+            return;
+        }
+        let index = move_index(self.cgroup_index(), delta, cgroups.len());
+        self.selected_cgroup = Some(cgroups[index].path.clone());
+    }
+
+    pub fn cgroup_page_down(&mut self) {
+        self.move_cgroup_cursor(self.cgroup_page_size.max(1) as isize);
+    }
+
+    pub fn cgroup_page_up(&mut self) {
+        self.move_cgroup_cursor(-(self.cgroup_page_size.max(1) as isize));
+    }
+
+    /// The cgroup of systemd unit `unit` in the snapshot on screen: the
+    /// shallowest one named after it, as a user manager's units may share
+    /// names with system ones.
+    pub fn unit_cgroup(&self, unit: &str) -> Option<&CgroupSample> {
+        self.snapshot()
+            .cgroups
+            .iter()
+            .filter(|c| c.name() == unit)
+            .min_by_key(|c| c.depth())
+    }
+
+    /// Row of the selected unit in the snapshot on screen, or the first.
+    pub fn service_index(&self) -> usize {
+        let units = &self.snapshot().services;
+        self.selected_service
+            .as_ref()
+            .and_then(|unit| units.iter().position(|u| &u.unit == unit))
+            .unwrap_or(0)
+    }
+
+    /// Moves the Services view selection by `delta` rows, clamped.
+    pub fn move_service_cursor(&mut self, delta: isize) {
+        let units = &self.snapshot().services;
+        if units.is_empty() {
+            return;
+        }
+        let index = move_index(self.service_index(), delta, units.len());
+        self.selected_service = Some(units[index].unit.clone());
+    }
+
+    pub fn service_page_down(&mut self) {
+        self.move_service_cursor(self.service_page_size.max(1) as isize);
+    }
+
+    pub fn service_page_up(&mut self) {
+        self.move_service_cursor(-(self.service_page_size.max(1) as isize));
+    }
+
+    /// Asks to confirm `action` on the selected unit.
+    pub fn confirm_service_action(&mut self, action: ServiceAction) {
+        if self.refuse_if_not_local() {
+            return;
+        }
+        let index = self.service_index();
+        if let Some(unit) = self.snapshot().services.get(index) {
+            self.service_confirm = Some((action, unit.unit.clone()));
+        }
+    }
+
+    pub fn apply_service_action(&mut self) {
+        let Some((action, unit)) = self.service_confirm.take() else {
+            return;
+        };
+        self.status_message = Some(match services::control(action, &unit) {
+            Ok(()) => format!("Queued {} of {}", action.verb(), unit),
+            Err(err) => format!("Could not {} {}: {}", action.verb(), unit, err),
+        });
+    }
+
+    /// Switches to the process table, filtered to the processes in the
+    /// selected cgroup and those below it.
+    pub fn show_cgroup_processes(&mut self) {
+        if let Some(path) = self.selected_cgroup.clone() {
+            self.filter_to_cgroup(path);
+        }
+    }
+
+    /// `show_cgroup_processes` for the cgroup of the selected unit.
+    pub fn show_service_processes(&mut self) {
+        let index = self.service_index();
+        let Some(unit) = self.snapshot().services.get(index).map(|u| u.unit.clone()) else {
+            return;
+        };
+        match self.unit_cgroup(&unit).map(|c| c.path.clone()) {
+            Some(path) => self.filter_to_cgroup(path),
+            None => self.status_message = Some(format!("{} has no processes", unit)),
+        }
+    }
+
+    fn filter_to_cgroup(&mut self, path: String) {
+        self.search_query = if path == "/" {
+            String::new()
+        } else {
+            format!(
+                "cgroup ~ /^{}(\\/|$)/",
+                regex::escape(&path).replace('/', "\\/")
+            )
+        };
+        self.update_filter();
+        self.bottom_view = BottomView::Processes;
+        self.status_message = Some(format!("Processes in {} [/] to edit the filter", path));
+    }
+
+    pub fn cycle_grouping(&mut self) {
+        self.group_by = self.group_by.next();
+        self.expanded_groups.clear();
+        self.rebuild_rows();
+    }
+
+    /// PID of the selected process if it has exited since it was selected,
+    /// leaving the cursor on some other row.
+    fn exited_selection(&self) -> Option<u32> {
+        let Some(RowKey::Pid(pid) | RowKey::Thread(pid)) = self.selected_key else {
+            return None;
+        };
+        let under_cursor = self
+            .process_rows
+            .get(self.selected_process)
+            .map(|row| self.row_key(row));
+        (under_cursor != self.selected_key).then_some(pid)
+    }
+
+    /// PID of the process (or TID of the thread) on the selected row.
+    pub fn selected_pid(&self) -> Option<u32> {
+        if self.exited_selection().is_some() {
+            return None;
+        }
+        match self.process_rows.get(self.selected_process)? {
+            ProcessRow::Process(i) | ProcessRow::Member(i) => Some(self.processes[*i].pid),
+            ProcessRow::Thread(i) => Some(self.threads[*i].pid),
+            ProcessRow::Group(_) => None,
+        }
+    }
+
+    pub fn selected_info(&self) -> Option<&ProcessInfo> {
+        if self.exited_selection().is_some() {
+            return None;
+        }
+        match self.process_rows.get(self.selected_process)? {
+            ProcessRow::Process(i) | ProcessRow::Member(i) => self.processes.get(*i),
+            ProcessRow::Thread(i) => self.threads.get(*i),
+            ProcessRow::Group(_) => None,
+        }
+    }
+
+    /// Recorded or remote PIDs may belong to anything on this machine, so
+    /// process actions are off during replay and when connected to an agent.
+    fn refuse_if_not_local(&mut self) -> bool {
+        let reason = if self.replay.is_some() {
+            "during replay"
+        } else if self.remote.is_some() {
+            "on a remote machine"
+        } else {
+            return false;
+        };
+        self.status_message = Some(format!("Process actions are unavailable {}", reason));
+        true
+    }
+
+    /// A paused table shows PIDs that may since have exited or been reused,
+    /// so process actions wait until the display is live again.
+    fn refuse_if_paused(&mut self) -> bool {
+        if !self.is_paused() {
+            return false;
+        }
+        self.status_message = Some("Process actions are unavailable while paused".to_string());
+        true
+    }
+
+    /// The row under the cursor belongs to another process once the
+    /// selected one exits, so actions wait until the user picks a row.
+    fn refuse_if_exited(&mut self) -> bool {
+        let Some(pid) = self.exited_selection() else {
+            return false;
+        };
+        self.status_message = Some(format!("Process {} exited; select a row first", pid));
+        true
+    }
+
+    /// Changes the nice value of the selected process by `delta`.
+    pub fn renice_selected(&mut self, delta: i32) {
+        if self.refuse_if_not_local() || self.refuse_if_paused() || self.refuse_if_exited() {
+            return;
+        }
+        let Some(p) = self.selected_info() else {
+            return;
+        };
+        let pid = p.pid;
+        let nice = (p.nice.unwrap_or(0) + delta).clamp(-20, 19);
+        let result = match p.thread_of {
+            Some(_) => control::renice(pid, nice).map(|()| String::new()),
+            None => control::for_each_thread(pid, |tid| control::renice(tid, nice))
+                .map(|applied| applied.failures()),
+        };
+        self.status_message = Some(match result {
+            Ok(failures) => {
+                self.set_selected_nice(nice);
+                format!("Set nice of {} to {}{}", pid, nice, failures)
+            }
+            Err(err) => control::describe_error("Renice", pid, &err),
+        });
+    }
+
+    fn set_selected_nice(&mut self, nice: i32) {
+        let row = match self.process_rows.get(self.selected_process) {
+            Some(ProcessRow::Process(i) | ProcessRow::Member(i)) => self.processes.get_mut(*i),
+            Some(ProcessRow::Thread(i)) => self.threads.get_mut(*i),
+            _ => None,
+        };
+        if let Some(p) = row {
+            p.nice = Some(nice);
+        }
+    }
+
+    pub fn open_affinity_dialog(&mut self) {
+        if self.refuse_if_not_local() || self.refuse_if_paused() || self.refuse_if_exited() {
+            return;
+        }
+        let Some(p) = self.selected_info() else {
+            return;
+        };
+        let (pid, thread) = (p.pid, p.thread_of.is_some());
+        match control::get_affinity(pid, self.history.latest().cpus.len()) {
+            Ok(cpus) => {
+                self.affinity_dialog = Some(AffinityDialog {
+                    pid,
+                    thread,
+                    cursor: 0,
+                    cpus,
+                })
+            }
+            Err(err) => self.status_message = Some(control::describe_error("Affinity", pid, &err)),
+        }
+    }
+
+    pub fn move_affinity_cursor(&mut self, delta: isize) {
+        if let Some(dialog) = &mut self.affinity_dialog {
+            let last = dialog.cpus.len() as isize - 1;
+            dialog.cursor = (dialog.cursor as isize + delta).clamp(0, last.max(0)) as usize;
+        }
+    }
+
+    pub fn toggle_affinity_cpu(&mut self) {
+        if let Some(dialog) = &mut self.affinity_dialog {
+            if let Some(on) = dialog.cpus.get_mut(dialog.cursor) {
+                *on = !*on;
+            }
+        }
+    }
+
+    /// Selects every CPU, or none if all are already selected.
+    pub fn toggle_all_affinity_cpus(&mut self) {
+        if let Some(dialog) = &mut self.affinity_dialog {
+            let all = dialog.cpus.iter().all(|on| *on);
+            dialog.cpus.iter_mut().for_each(|on| *on = !all);
+        }
+    }
+
+    pub fn apply_affinity(&mut self) {
+        if self.refuse_if_paused() || self.refuse_if_exited() {
+            self.affinity_dialog = None;
+            return;
+        }
+        let Some(dialog) = &self.affinity_dialog else {
+            return;
+        };
+        if !dialog.cpus.iter().any(|on| *on) {
+            self.status_message = Some("Select at least one CPU".to_string());
+            return;
+        }
+        let pid = dialog.pid;
+        let result = match dialog.thread {
+            true => control::set_affinity(pid, &dialog.cpus).map(|()| String::new()),
+            false => control::for_each_thread(pid, |tid| control::set_affinity(tid, &dialog.cpus))
+                .map(|applied| applied.failures()),
+        };
+        self.status_message = Some(match result {
+            Ok(failures) => {
+                let count = dialog.cpus.iter().filter(|on| **on).count();
+                format!("Pinned {} to {} CPU(s){}", pid, count, failures)
+            }
+            Err(err) => control::describe_error("Affinity", pid, &err),
+        });
+        self.affinity_dialog = None;
+    }
+
+    /// Expands or collapses the threads of the selected process.
+    pub fn toggle_threads(&mut self) {
+        let Some(ProcessRow::Process(i) | ProcessRow::Member(i)) =
+            self.process_rows.get(self.selected_process)
+        else {
+            return;
+        };
+        let pid = self.processes[*i].pid;
+        if !self.expanded_threads.remove(&pid) {
+            self.expanded_threads.insert(pid);
+        }
+        self.rebuild_rows();
+    }
+
+    /// Expands or collapses the selected group.
+    pub fn toggle_group(&mut self, expand: Option<bool>) {
+        let Some(ProcessRow::Group(group)) = self.process_rows.get(self.selected_process) else {
+            return;
+        };
+        let expand = expand.unwrap_or(!group.expanded);
+        if expand {
+            self.expanded_groups.insert(group.key.clone());
+        } else {
+            self.expanded_groups.remove(&group.key);
+        }
+        self.rebuild_rows();
+    }
+
+    /// Re-parses `search_query`. On a parse error the previous filter stays
+    /// in effect so the table doesn't flicker while an expression is typed.
+    pub fn update_filter(&mut self) {
+        if self.search_query.trim().is_empty() {
+            self.filter = None;
+            self.filter_error = None;
+        } else {
+            match Filter::parse(&self.search_query, &self.config.filters) {
+                Ok(filter) => {
+                    self.filter = Some(filter);
+                    self.filter_error = None;
+                }
+                Err(err) => {
+                    self.filter_error = Some(err.to_string());
+                    return;
+                }
+            }
+        }
+        self.refresh_view();
+    }
+
+    /// Saves the current query as a named filter, usable as `@name`.
+    pub fn save_filter(&mut self, name: String) {
+        let name = name.trim().trim_start_matches('@').to_string();
+        if name.is_empty() || self.search_query.trim().is_empty() {
+            return;
+        }
+        self.config
+            .filters
+            .insert(name, self.search_query.trim().to_string());
+        self.save_config();
+    }
+
+    /// Text shown in the Name column: the full command line when
+    /// `show_cmdline` is on, falling back to the name for kernel threads.
+    pub fn display_name<'a>(&self, p: &'a ProcessInfo) -> &'a str {
+        if self.show_cmdline && !p.cmd.is_empty() {
+            &p.cmd
+        } else {
+            &p.name
+        }
+    }
+
+    pub fn open_column_picker(&mut self) {
+        let mut entries: Vec<(ProcessColumn, bool)> =
+            self.columns.iter().map(|&c| (c, true)).collect();
+        for column in ProcessColumn::ALL {
+            if !self.columns.contains(&column) {
+                entries.push((column, false));
+            }
+        }
+        self.column_picker = Some(ColumnPicker { cursor: 0, entries });
+    }
+
+    pub fn move_picker_cursor(&mut self, delta: isize) {
+        if let Some(picker) = &mut self.column_picker {
+            let last = picker.entries.len() as isize - 1;
+            picker.cursor = (picker.cursor as isize + delta).clamp(0, last) as usize;
+        }
+    }
+
+    pub fn toggle_picker_column(&mut self) {
+        if let Some(picker) = &mut self.column_picker {
+            let enabled = picker.entries.iter().filter(|(_, on)| *on).count();
+            let entry = &mut picker.entries[picker.cursor];
+            // Always keep at least one column visible.
+            if !(entry.1 && enabled == 1) {
+                entry.1 = !entry.1;
+            }
+        }
+        self.apply_picker();
+    }
+
+    /// Moves the column under the cursor up or down in the display order.
+    pub fn shift_picker_column(&mut self, delta: isize) {
+        if let Some(picker) = &mut self.column_picker {
+            let target = picker.cursor as isize + delta;
+            if target < 0 || target >= picker.entries.len() as isize {
+                return;
+            }
+            picker.entries.swap(picker.cursor, target as usize);
+            picker.cursor = target as usize;
+        }
+        self.apply_picker();
+    }
+
+    fn apply_picker(&mut self) {
+        if let Some(picker) = &self.column_picker {
+            self.columns = picker
+                .entries
+                .iter()
+                .filter(|(_, on)| *on)
+                .map(|(c, _)| *c)
+                .collect();
+        }
+    }
+
+    /// Writes the current column layout to the config file.
+    pub fn save_columns(&mut self) {
+        self.config.columns = Some(self.columns.clone());
+        self.save_config();
+    }
+
+    fn save_config(&mut self) {
+        self.status_message = Some(match &self.config_path {
+            Some(path) => match self.config.save(path) {
+                Ok(()) => format!("Saved {}", path.display()),
+                Err(err) => format!("Save failed: {}", err),
+            },
+            None => "Save failed: no config path".to_string(),
+        });
+    }
+}
+
+fn process_key(p: &ProcessInfo) -> RowKey {
+    match p.thread_of {
+        Some(_) => RowKey::Thread(p.pid),
+        None => RowKey::Pid(p.pid),
+    }
+}
+
+fn sort_processes(processes: &mut [ProcessInfo], column: &SortColumn, ascending: bool) {
+    match column {
+        SortColumn::Pid => processes.sort_by_key(|p| p.pid),
+        SortColumn::Name => processes.sort_by(|a, b| a.name.cmp(&b.name)),
+        SortColumn::Cpu => processes.sort_by(|a, b| {
+            a.cpu_usage
+                .partial_cmp(&b.cpu_usage)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        }),
+        SortColumn::Memory => processes.sort_by_key(|p| p.memory),
+        SortColumn::Virtual => processes.sort_by_key(|p| p.virtual_memory),
+        SortColumn::Pss => processes.sort_by_key(|p| p.pss),
+        SortColumn::Uss => processes.sort_by_key(|p| p.uss),
+        SortColumn::Swap => processes.sort_by_key(|p| p.swap),
+    }
+
+    if !ascending {
+        processes.reverse();
+    }
+}
+
+/// `index` moved by `delta` within a list of `len` rows (not empty).
+fn move_index(index: usize, delta: isize, len: usize) -> usize {
+    index.saturating_add_signed(delta).min(len - 1)
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        s if s % 86_400 == 0 => format!("{}d", s / 86_400),
+        s if s % 3_600 == 0 => format!("{}h", s / 3_600),
+        s if s % 60 == 0 => format!("{}m", s / 60),
+        s => format!("{}s", s),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(pid: u32, cpu_usage: f32) -> ProcessInfo {
+        ProcessInfo {
+            pid,
+            name: format!("proc{}", pid),
+            cpu_usage,
+            ..ProcessInfo::default()
+        }
+    }
+
+    fn snapshot(processes: Vec<ProcessInfo>) -> Snapshot {
+        Snapshot {
+            processes,
+            ..Snapshot::default()
+        }
+    }
+
+    fn app(processes: Vec<ProcessInfo>, max_processes: usize) -> AppState {
+        AppState::new(
+            500,
+            History::new(10, snapshot(processes)),
+            Rollups::new(Duration::from_secs(600), Duration::from_millis(500)),
+            max_processes,
+            "cpu".to_string(),
+            Config::default(),
+            None,
+        )
+    }
+
+    #[test]
+    fn selection_follows_the_process_when_it_moves() {
+        let mut app = app(
+            vec![process(1, 10.0), process(2, 20.0), process(3, 30.0)],
+            100,
+        );
+        assert_eq!(app.selected_pid(), Some(3));
+        app.next_process();
+        assert_eq!(app.selected_pid(), Some(2));
+
+        app.push_snapshot(snapshot(vec![
+            process(1, 10.0),
+            process(2, 50.0),
+            process(3, 30.0),
+        ]));
+        assert_eq!(app.selected_process, 0);
+        assert_eq!(app.selected_pid(), Some(2));
+    }
+
+    #[test]
+    fn exited_selection_is_not_acted_on() {
+        let mut app = app(
+            vec![process(1, 10.0), process(2, 20.0), process(3, 30.0)],
+            100,
+        );
+        app.next_process();
+        app.push_snapshot(snapshot(vec![process(1, 10.0), process(3, 30.0)]));
+
+        // The cursor stays put, on a row the user never chose.
+        assert_eq!(app.selected_process, 1);
+        assert_eq!(app.selected_pid(), None);
+        assert!(app.selected_info().is_none());
+        app.renice_selected(1);
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("Process 2 exited; select a row first")
+        );
+        app.affinity_dialog = Some(AffinityDialog {
+            pid: 1,
+            thread: false,
+            cursor: 0,
+            cpus: vec![true],
+        });
+        app.apply_affinity();
+        assert!(app.affinity_dialog.is_none());
+
+        // Picking a row makes it the selection again.
+        app.previous_process();
+        assert_eq!(app.selected_pid(), Some(3));
+    }
+
+    #[test]
+    fn followed_process_stays_listed_until_it_exits() {
+        let mut app = app(
+            vec![process(1, 10.0), process(2, 20.0), process(3, 30.0)],
+            2,
+        );
+        app.push_snapshot(snapshot(vec![
+            process(1, 40.0),
+            process(2, 20.0),
+            process(3, 30.0),
+        ]));
+        assert_eq!(app.selected_pid(), Some(3));
+        app.last_process();
+        app.previous_process();
+        assert_eq!(app.selected_pid(), Some(1));
+        app.toggle_follow();
+        assert_eq!(app.followed_pid(), Some(1));
+
+        // Past the row limit, but still listed and selected.
+        app.push_snapshot(snapshot(vec![
+            process(1, 1.0),
+            process(2, 20.0),
+            process(3, 30.0),
+        ]));
+        assert_eq!(app.process_rows.len(), 3);
+        assert_eq!(app.selected_pid(), Some(1));
+
+        app.push_snapshot(snapshot(vec![process(2, 20.0), process(3, 30.0)]));
+        assert_eq!(app.followed_pid(), None);
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("Stopped following 1: no longer listed")
+        );
+    }
+
+    #[test]
+    fn pausing_holds_the_view_and_process_actions() {
+        let mut app = app(vec![process(1, 10.0)], 100);
+        app.push_snapshot(snapshot(vec![process(1, 20.0)]));
+        app.toggle_pause();
+        assert!(app.is_paused());
+        app.push_snapshot(snapshot(vec![process(1, 30.0)]));
+        assert_eq!(app.snapshot().seq, 1);
+        assert_eq!(app.processes[0].cpu_usage, 20.0);
+
+        // Process 1 may have exited by now, and its PID been reused.
+        app.renice_selected(1);
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("Process actions are unavailable while paused")
+        );
+
+        app.step_timeline(-5);
+        assert_eq!(app.snapshot().seq, 0);
+        assert_eq!(app.processes[0].cpu_usage, 10.0);
+        app.step_timeline(5);
+        assert_eq!(app.snapshot().seq, 2);
+        assert!(app.is_paused());
+
+        app.toggle_pause();
+        assert!(!app.is_paused());
+        app.push_snapshot(snapshot(vec![process(1, 40.0)]));
+        assert_eq!(app.processes[0].cpu_usage, 40.0);
+    }
+
+    #[test]
+    fn charts_scroll_within_the_zoomed_history() {
+        let mut app = app(vec![process(1, 10.0)], 100);
+        for i in 1..=4u64 {
+            app.push_snapshot(Snapshot {
+                timestamp: i * 500,
+                overall_cpu: i as f32 * 10.0,
+                ..Snapshot::default()
+            });
+        }
+        assert_eq!(app.series(keys::CPU, 3), vec![20, 30, 40]);
+        app.chart_page = 1;
+        app.scroll_charts(1);
+        assert_eq!(app.chart_offset, 1);
+        assert_eq!(app.chart_scroll(), Duration::from_millis(500));
+        assert_eq!(app.series(keys::CPU, 3), vec![10, 20, 30]);
+
+        // Paging stops at the oldest sample kept.
+        app.chart_page = 100;
+        app.scroll_charts(1);
+        assert_eq!(app.chart_offset, 4);
+        assert_eq!(app.series(keys::CPU, 3), vec![10, 20, 30]);
+        app.scroll_charts(-1);
+        assert_eq!(app.chart_offset, 0);
+
+        app.scroll_charts(1);
+        app.cycle_zoom();
+        assert_eq!(app.chart_offset, 0);
+    }
+
+    /// Process 1 with 100 bytes resident, its main thread and a second
+    /// thread, and process 2.
+    fn threaded() -> Vec<ProcessInfo> {
+        let thread = |tid: u32, cpu_usage: f32| ProcessInfo {
+            name: "proc1".to_string(),
+            thread_of: Some(1),
+            ..process(tid, cpu_usage)
+        };
+        vec![
+            ProcessInfo {
+                memory: 100,
+                threads: Some(2),
+                ..process(1, 30.0)
+            },
+            thread(1, 10.0),
+            thread(5, 20.0),
+            process(2, 5.0),
+        ]
+    }
+
+    #[test]
+    fn expanded_threads_include_the_main_thread() {
+        let mut app = app(threaded(), 100);
+        assert_eq!(app.process_rows.len(), 2);
+        app.toggle_threads();
+        let keys: Vec<RowKey> = app.process_rows.iter().map(|r| app.row_key(r)).collect();
+        assert!(
+            keys == [
+                RowKey::Pid(1),
+                RowKey::Thread(5),
+                RowKey::Thread(1),
+                RowKey::Pid(2)
+            ]
+        );
+
+        // The main thread keeps its row, apart from its process.
+        app.last_process();
+        app.previous_process();
+        assert_eq!(app.selected_pid(), Some(1));
+        app.push_snapshot(snapshot(threaded()));
+        assert_eq!(app.selected_process, 2);
+        assert!(app.selected_key == Some(RowKey::Thread(1)));
+    }
+
+    #[test]
+    fn groups_leave_out_threads_shown_as_rows() {
+        let mut processes = threaded();
+        // Threads often name themselves, but stay with their process.
+        processes[2].name = "worker-0".to_string();
+        let mut app = app(processes, 100);
+        app.toggle_show_threads();
+        assert_eq!(app.processes.len(), 4);
+        app.cycle_grouping();
+
+        let ProcessRow::Group(group) = &app.process_rows[0] else {
+            panic!("expected a group row");
+        };
+        assert_eq!(group.key, "proc1");
+        assert_eq!(group.count, 1);
+        assert_eq!(group.members.len(), 3);
+        assert_eq!(group.cpu_usage, 30.0);
+        assert_eq!(group.memory, 100);
+        assert_eq!(app.process_rows.len(), 2);
+    }
 }
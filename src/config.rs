@@ -0,0 +1,175 @@
+use crate::app::ProcessColumn;
+use crate::filter;
+use crate::hooks::HookConfig;
+use color_eyre::{eyre::WrapErr, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// User settings read from `config.toml`. Every field is optional so a
+/// partial file only overrides what it mentions.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct Config {
+    /// Process table columns, in display order.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub columns: Option<Vec<ProcessColumn>>,
+    /// Show full command lines instead of process names.
+    pub show_cmdline: bool,
+    /// Named process filters, referenced as `@name` in a filter expression.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub filters: BTreeMap<String, String>,
+    /// Alert rules by name, e.g. `busy = "cpu.overall > 90 for 30s"`.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub alerts: BTreeMap<String, String>,
+    /// Commands and webhooks run when alerts fire or resolve.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub hooks: Vec<HookConfig>,
+    /// How far back chart history reaches, e.g. `"10m"`, `"1h"` or `"24h"`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retention: Option<String>,
+    /// Where the cgroup v2 hierarchy is mounted, if not `/sys/fs/cgroup`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cgroup_root: Option<PathBuf>,
+    /// Where batteries are listed, if not `/sys/class/power_supply`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub power_supply_root: Option<PathBuf>,
+    /// Socket of the Docker or Podman API to name containers with, or
+    /// `"off"`. Found automatically when not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container_engine: Option<String>,
+}
+
+impl Config {
+    /// `$XDG_CONFIG_HOME/nexmon/config.toml`, falling back to `~/.config`.
+    pub fn default_path() -> Option<PathBuf> {
+        let base = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| {
+                std::env::var_os("HOME")
+                    .or_else(|| std::env::var_os("USERPROFILE"))
+                    .map(|home| PathBuf::from(home).join(".config"))
+            })?;
+        Some(base.join("nexmon").join("config.toml"))
+    }
+
+    /// Loads the config at `path`. A missing file yields the defaults.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => {
+                return Err(err).wrap_err_with(|| format!("reading {}", path.display()));
+            }
+        };
+        toml::from_str(&text).wrap_err_with(|| format!("parsing {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).wrap_err_with(|| format!("creating {}", dir.display()))?;
+        }
+        let text = toml::to_string_pretty(self)?;
+        fs::write(path, text).wrap_err_with(|| format!("writing {}", path.display()))
+    }
+}
+
+/// Parses a duration such as `90s`, `10m`, `1h` or `2d`. A bare number is seconds.
+pub fn parse_duration(input: &str) -> Result<Duration, String> {
+    let input = input.trim();
+    let split = input
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(input.len());
+    let (number, unit) = input.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration '{}'", input))?;
+    let secs = match unit.trim() {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        other => {
+            return Err(format!(
+                "unknown duration unit '{}' (use s, m, h or d)",
+                other
+            ))
+        }
+    };
+    if number == 0 {
+        return Err("duration must be greater than zero".to_string());
+    }
+    number
+        .checked_mul(secs)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("duration '{}' is too long", input))
+}
+
+/// Parses a size such as `512K`, `10M`, `1.5G` or `2GiB` (binary units),
+/// as filters write them. A bare number is bytes.
+pub fn parse_size(input: &str) -> Result<u64, String> {
+    let input = input.trim();
+    let invalid = || format!("invalid size '{}' (use bytes, K, M, G or T)", input);
+    let bytes = filter::parse_magnitude(input).ok_or_else(invalid)?;
+    if bytes.fract() != 0.0 || bytes >= u64::MAX as f64 {
+        return Err(invalid());
+    }
+    Ok(bytes as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations() {
+        for (input, secs) in [
+            ("90", 90),
+            ("90s", 90),
+            (" 10m ", 600),
+            ("1h", 3600),
+            ("2d", 172_800),
+        ] {
+            assert_eq!(
+                parse_duration(input),
+                Ok(Duration::from_secs(secs)),
+                "{}",
+                input
+            );
+        }
+        for (input, error) in [
+            ("", "invalid duration"),
+            ("m", "invalid duration"),
+            ("-5s", "invalid duration"),
+            ("1.5h", "unknown duration unit"),
+            ("5y", "unknown duration unit"),
+            ("0s", "greater than zero"),
+            ("99999999999999999d", "too long"),
+        ] {
+            let err = parse_duration(input).unwrap_err();
+            assert!(err.contains(error), "{}: {}", input, err);
+        }
+    }
+
+    #[test]
+    fn sizes() {
+        for (input, bytes) in [
+            ("4096", 4096),
+            ("512K", 512 << 10),
+            ("10M", 10 << 20),
+            ("10mb", 10 << 20),
+            ("1.5G", 3 << 29),
+            ("2GiB", 2 << 30),
+            ("1T", 1 << 40),
+        ] {
+            assert_eq!(parse_size(input), Ok(bytes), "{}", input);
+        }
+        for input in ["", "K", "-5M", "10%", "0.5", "3x", "99999999999T"] {
+            assert!(parse_size(input).is_err(), "{}", input);
+        }
+    }
+}
@@ -0,0 +1,886 @@
+//! Process filter expressions, e.g. `cpu > 20 && user == "postgres"`.
+//!
+//! A filter is a boolean expression over process fields. Comparisons take
+//! the form `field op value`; a bare word, quoted string or `/regex/` on its
+//! own matches against the name, command line, user and executable, so a
+//! plain search term keeps working as before. Adjacent terms are ANDed.
+//! `@name` expands to a named filter from the config.
+
+use crate::app::ProcessInfo;
+use regex::{Regex, RegexBuilder};
+use std::{collections::BTreeMap, fmt};
+
+/// A parse failure, with the character offset it was detected at.
+#[derive(Debug, PartialEq)]
+pub struct ParseError {
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "col {}: {}", self.position + 1, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Field {
+    Pid,
+    Ppid,
+    Name,
+    Cmd,
+    Exe,
+    User,
+    Cpu,
+    Mem,
+    MemPercent,
+    Virt,
+    Threads,
+    State,
+    Nice,
+    Priority,
+    Start,
+    CpuTime,
+    Read,
+    Write,
+    GpuMem,
+    Cgroup,
+    Container,
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Field> {
+        Some(match name.to_lowercase().as_str() {
+            "pid" => Field::Pid,
+            "ppid" => Field::Ppid,
+            "name" => Field::Name,
+            "cmd" | "command" => Field::Cmd,
+            "exe" => Field::Exe,
+            "user" => Field::User,
+            "cpu" => Field::Cpu,
+            "mem" | "rss" => Field::Mem,
+            "mem%" => Field::MemPercent,
+            "virt" => Field::Virt,
+            "threads" => Field::Threads,
+            "state" | "status" => Field::State,
+            "nice" | "ni" => Field::Nice,
+            "priority" | "pri" => Field::Priority,
+            "start" => Field::Start,
+            "time" | "cpu_time" => Field::CpuTime,
+            "read" => Field::Read,
+            "write" => Field::Write,
+            "gpu_mem" | "gpumem" => Field::GpuMem,
+            "cgroup" => Field::Cgroup,
+            "container" => Field::Container,
+            _ => return None,
+        })
+    }
+
+    fn is_text(self) -> bool {
+        matches!(
+            self,
+            Field::Name
+                | Field::Cmd
+                | Field::Exe
+                | Field::User
+                | Field::State
+                | Field::Cgroup
+                | Field::Container
+        )
+    }
+
+    fn text(self, p: &ProcessInfo) -> &str {
+        match self {
+            Field::Name => &p.name,
+            Field::Cmd => &p.cmd,
+            Field::Exe => &p.exe,
+            Field::User => &p.user,
+            Field::State => &p.status,
+            Field::Cgroup => &p.cgroup,
+            Field::Container => &p.container,
+            _ => "",
+        }
+    }
+
+    fn number(self, p: &ProcessInfo, total_memory: u64) -> Option<f64> {
+        Some(match self {
+            Field::Pid => p.pid as f64,
+            Field::Ppid => p.ppid? as f64,
+            Field::Cpu => p.cpu_usage as f64,
+            Field::Mem => p.memory as f64,
+            Field::MemPercent if total_memory > 0 => p.memory as f64 / total_memory as f64 * 100.0,
+            Field::Virt => p.virtual_memory as f64,
+            Field::Threads => p.threads? as f64,
+            Field::Nice => p.nice? as f64,
+            Field::Priority => p.priority? as f64,
+            Field::Start => p.start_time as f64,
+            Field::CpuTime => p.cpu_time?,
+            Field::Read => p.read_rate as f64,
+            Field::Write => p.write_rate as f64,
+            Field::GpuMem => p.gpu_memory? as f64,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    Match,
+    NotMatch,
+}
+
+impl CmpOp {
+    /// Numeric comparison; the regex operators never match numbers.
+    pub fn compare(self, left: f64, right: f64) -> bool {
+        match self {
+            CmpOp::Eq => left == right,
+            CmpOp::Ne => left != right,
+            CmpOp::Gt => left > right,
+            CmpOp::Ge => left >= right,
+            CmpOp::Lt => left < right,
+            CmpOp::Le => left <= right,
+            CmpOp::Match | CmpOp::NotMatch => false,
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            CmpOp::Eq => "==",
+            CmpOp::Ne => "!=",
+            CmpOp::Gt => ">",
+            CmpOp::Ge => ">=",
+            CmpOp::Lt => "<",
+            CmpOp::Le => "<=",
+            CmpOp::Match => "~",
+            CmpOp::NotMatch => "!~",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Number {
+        field: Field,
+        op: CmpOp,
+        value: f64,
+    },
+    /// Case-insensitive equality on a text field.
+    TextEq {
+        field: Field,
+        negate: bool,
+        value: String,
+    },
+    TextMatch {
+        field: Field,
+        negate: bool,
+        regex: Regex,
+    },
+    /// A bare search term: lowercase substring of any text field.
+    Search(String),
+    /// A bare `/regex/`: matches any text field.
+    SearchRegex(Regex),
+}
+
+impl Expr {
+    pub fn matches(&self, p: &ProcessInfo, total_memory: u64) -> bool {
+        match self {
+            Expr::And(a, b) => a.matches(p, total_memory) && b.matches(p, total_memory),
+            Expr::Or(a, b) => a.matches(p, total_memory) || b.matches(p, total_memory),
+            Expr::Not(e) => !e.matches(p, total_memory),
+            Expr::Number { field, op, value } => field
+                .number(p, total_memory)
+                .is_some_and(|v| op.compare(v, *value)),
+            Expr::TextEq {
+                field,
+                negate,
+                value,
+            } => field.text(p).eq_ignore_ascii_case(value) != *negate,
+            Expr::TextMatch {
+                field,
+                negate,
+                regex,
+            } => regex.is_match(field.text(p)) != *negate,
+            Expr::Search(term) => text_fields(p).any(|t| t.to_lowercase().contains(term)),
+            Expr::SearchRegex(regex) => text_fields(p).any(|t| regex.is_match(t)),
+        }
+    }
+}
+
+fn text_fields(p: &ProcessInfo) -> impl Iterator<Item = &str> {
+    [&p.name, &p.cmd, &p.user, &p.exe]
+        .into_iter()
+        .map(|s| s.as_str())
+}
+
+/// A parsed filter, ready to evaluate against processes.
+#[derive(Debug)]
+pub struct Filter {
+    expr: Expr,
+}
+
+impl Filter {
+    /// Parses `input`, resolving `@name` references against `named`.
+    pub fn parse(input: &str, named: &BTreeMap<String, String>) -> Result<Filter, ParseError> {
+        let expr = parse_expr(input, named, 0)?;
+        Ok(Filter { expr })
+    }
+
+    pub fn matches(&self, p: &ProcessInfo, total_memory: u64) -> bool {
+        self.expr.matches(p, total_memory)
+    }
+}
+
+/// Named filters may reference each other; this bounds the nesting.
+const MAX_DEPTH: usize = 8;
+
+fn parse_expr(
+    input: &str,
+    named: &BTreeMap<String, String>,
+    depth: usize,
+) -> Result<Expr, ParseError> {
+    let tokens = tokenize(input)?;
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        end: input.chars().count(),
+        named,
+        depth,
+    };
+    if parser.tokens.is_empty() {
+        return Err(parser.error_at(0, "empty filter"));
+    }
+    let expr = parser.parse_or()?;
+    if let Some(tok) = parser.peek() {
+        return Err(parser.error_at(tok.pos, format!("unexpected {}", tok.kind)));
+    }
+    Ok(expr)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum TokenKind {
+    Word(String),
+    Str(String),
+    Regex { pattern: String, ignore_case: bool },
+    Op(CmpOp),
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TokenKind::Word(w) => write!(f, "`{}`", w),
+            TokenKind::Str(s) => write!(f, "\"{}\"", s),
+            TokenKind::Regex { pattern, .. } => write!(f, "/{}/", pattern),
+            TokenKind::Op(op) => write!(f, "`{}`", op.symbol()),
+            TokenKind::And => write!(f, "`&&`"),
+            TokenKind::Or => write!(f, "`||`"),
+            TokenKind::Not => write!(f, "`!`"),
+            TokenKind::LParen => write!(f, "`(`"),
+            TokenKind::RParen => write!(f, "`)`"),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Token {
+    kind: TokenKind,
+    pos: usize,
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '.' | '%' | '-' | ':' | '@' | '+')
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, ParseError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    let error = |pos: usize, message: &str| ParseError {
+        position: pos,
+        message: message.to_string(),
+    };
+
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        let next = chars.get(i + 1).copied();
+
+        let kind = match c {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' => {
+                i += 1;
+                TokenKind::LParen
+            }
+            ')' => {
+                i += 1;
+                TokenKind::RParen
+            }
+            '&' if next == Some('&') => {
+                i += 2;
+                TokenKind::And
+            }
+            '|' if next == Some('|') => {
+                i += 2;
+                TokenKind::Or
+            }
+            '=' if next == Some('=') => {
+                i += 2;
+                TokenKind::Op(CmpOp::Eq)
+            }
+            '=' => {
+                i += 1;
+                TokenKind::Op(CmpOp::Eq)
+            }
+            '!' if next == Some('=') => {
+                i += 2;
+                TokenKind::Op(CmpOp::Ne)
+            }
+            '!' if next == Some('~') => {
+                i += 2;
+                TokenKind::Op(CmpOp::NotMatch)
+            }
+            '!' => {
+                i += 1;
+                TokenKind::Not
+            }
+            '>' if next == Some('=') => {
+                i += 2;
+                TokenKind::Op(CmpOp::Ge)
+            }
+            '>' => {
+                i += 1;
+                TokenKind::Op(CmpOp::Gt)
+            }
+            '<' if next == Some('=') => {
+                i += 2;
+                TokenKind::Op(CmpOp::Le)
+            }
+            '<' => {
+                i += 1;
+                TokenKind::Op(CmpOp::Lt)
+            }
+            '~' => {
+                i += 1;
+                TokenKind::Op(CmpOp::Match)
+            }
+            '"' | '\'' => {
+                let quote = c;
+                let mut value = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => return Err(error(start, "unterminated string")),
+                        // Only `\\` and the quote are escapes; other backslashes
+                        // are kept for regexes such as `"\d+"`.
+                        Some('\\') if matches!(chars.get(i + 1), Some(&ch) if ch == quote || ch == '\\') =>
+                        {
+                            value.push(chars[i + 1]);
+                            i += 2;
+                        }
+                        Some(&ch) if ch == quote => {
+                            i += 1;
+                            break;
+                        }
+                        Some(&ch) => {
+                            value.push(ch);
+                            i += 1;
+                        }
+                    }
+                }
+                TokenKind::Str(value)
+            }
+            '/' => {
+                let mut pattern = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => return Err(error(start, "unterminated regex")),
+                        Some('\\') if chars.get(i + 1) == Some(&'/') => {
+                            pattern.push('/');
+                            i += 2;
+                        }
+                        Some('/') => {
+                            i += 1;
+                            break;
+                        }
+                        Some(&ch) => {
+                            pattern.push(ch);
+                            i += 1;
+                        }
+                    }
+                }
+                let ignore_case = chars.get(i) == Some(&'i');
+                if ignore_case {
+                    i += 1;
+                }
+                // A path such as `/usr/bin/java` is a word: only a closing
+                // slash that ends the token makes a regex.
+                if chars.get(i).is_some_and(|&c| is_word_char(c) || c == '/') {
+                    i = start;
+                    while i < chars.len() && (is_word_char(chars[i]) || chars[i] == '/') {
+                        i += 1;
+                    }
+                    TokenKind::Word(chars[start..i].iter().collect())
+                } else {
+                    TokenKind::Regex {
+                        pattern,
+                        ignore_case,
+                    }
+                }
+            }
+            c if is_word_char(c) => {
+                while i < chars.len() && is_word_char(chars[i]) {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                match word.to_lowercase().as_str() {
+                    "and" => TokenKind::And,
+                    "or" => TokenKind::Or,
+                    "not" => TokenKind::Not,
+                    _ => TokenKind::Word(word),
+                }
+            }
+            _ => return Err(error(start, &format!("unexpected character `{}`", c))),
+        };
+        tokens.push(Token { kind, pos: start });
+    }
+
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    end: usize,
+    named: &'a BTreeMap<String, String>,
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_kind(&self) -> Option<&TokenKind> {
+        self.peek().map(|t| &t.kind)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let tok = self.tokens.get(self.pos).map(|t| Token {
+            kind: t.kind.clone(),
+            pos: t.pos,
+        });
+        self.pos += 1;
+        tok
+    }
+
+    fn error_at(&self, position: usize, message: impl Into<String>) -> ParseError {
+        ParseError {
+            position,
+            message: message.into(),
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, ParseError> {
+        let mut lhs = self.parse_and()?;
+        while self.peek_kind() == Some(&TokenKind::Or) {
+            self.pos += 1;
+            let rhs = self.parse_and()?;
+            lhs = Expr::Or(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, ParseError> {
+        let mut lhs = self.parse_unary()?;
+        loop {
+            match self.peek_kind() {
+                Some(TokenKind::And) => self.pos += 1,
+                // Juxtaposed terms are an implicit AND.
+                Some(TokenKind::Or) | Some(TokenKind::RParen) | None => break,
+                Some(_) => {}
+            }
+            let rhs = self.parse_unary()?;
+            lhs = Expr::And(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, ParseError> {
+        if self.peek_kind() == Some(&TokenKind::Not) {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.parse_unary()?)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, ParseError> {
+        let Some(tok) = self.next() else {
+            return Err(self.error_at(self.end, "expected a condition"));
+        };
+
+        match tok.kind {
+            TokenKind::LParen => {
+                let expr = self.parse_or()?;
+                match self.next() {
+                    Some(Token {
+                        kind: TokenKind::RParen,
+                        ..
+                    }) => Ok(expr),
+                    _ => Err(self.error_at(tok.pos, "unclosed `(`")),
+                }
+            }
+            TokenKind::Word(word) => {
+                if let Some(TokenKind::Op(_)) = self.peek_kind() {
+                    let field = Field::from_name(&word).ok_or_else(|| {
+                        self.error_at(tok.pos, format!("unknown field `{}`", word))
+                    })?;
+                    return self.parse_comparison(field);
+                }
+                if let Some(name) = word.strip_prefix('@') {
+                    return self.expand_named(name, tok.pos);
+                }
+                Ok(Expr::Search(word.to_lowercase()))
+            }
+            TokenKind::Str(s) => Ok(Expr::Search(s.to_lowercase())),
+            TokenKind::Regex {
+                pattern,
+                ignore_case,
+            } => Ok(Expr::SearchRegex(self.build_regex(
+                &pattern,
+                ignore_case,
+                tok.pos,
+            )?)),
+            other => Err(self.error_at(tok.pos, format!("unexpected {}", other))),
+        }
+    }
+
+    fn parse_comparison(&mut self, field: Field) -> Result<Expr, ParseError> {
+        let op_tok = self.next().expect("caller checked for an operator");
+        let TokenKind::Op(op) = op_tok.kind else {
+            unreachable!()
+        };
+
+        let Some(value) = self.next() else {
+            return Err(self.error_at(
+                self.end,
+                format!("expected a value after `{}`", op.symbol()),
+            ));
+        };
+
+        if field.is_text() {
+            let negate = matches!(op, CmpOp::Ne | CmpOp::NotMatch);
+            return match (op, value.kind) {
+                (CmpOp::Eq | CmpOp::Ne, TokenKind::Word(v) | TokenKind::Str(v)) => {
+                    Ok(Expr::TextEq {
+                        field,
+                        negate,
+                        value: v,
+                    })
+                }
+                (
+                    CmpOp::Match | CmpOp::NotMatch,
+                    TokenKind::Regex {
+                        pattern,
+                        ignore_case,
+                    },
+                ) => Ok(Expr::TextMatch {
+                    field,
+                    negate,
+                    regex: self.build_regex(&pattern, ignore_case, value.pos)?,
+                }),
+                (CmpOp::Match | CmpOp::NotMatch, TokenKind::Word(v) | TokenKind::Str(v)) => {
+                    Ok(Expr::TextMatch {
+                        field,
+                        negate,
+                        regex: self.build_regex(&v, false, value.pos)?,
+                    })
+                }
+                (CmpOp::Eq | CmpOp::Ne | CmpOp::Match | CmpOp::NotMatch, other) => Err(self
+                    .error_at(
+                        value.pos,
+                        format!("expected a string or /regex/, found {}", other),
+                    )),
+                _ => Err(self.error_at(
+                    op_tok.pos,
+                    format!("`{}` does not apply to text field", op.symbol()),
+                )),
+            };
+        }
+
+        if matches!(op, CmpOp::Match | CmpOp::NotMatch) {
+            return Err(self.error_at(
+                op_tok.pos,
+                format!("`{}` only applies to text fields", op.symbol()),
+            ));
+        }
+        match value.kind {
+            TokenKind::Word(w) => match parse_number(&w) {
+                Some(n) => Ok(Expr::Number {
+                    field,
+                    op,
+                    value: n,
+                }),
+                None => Err(self.error_at(value.pos, format!("invalid number `{}`", w))),
+            },
+            other => Err(self.error_at(value.pos, format!("expected a number, found {}", other))),
+        }
+    }
+
+    fn build_regex(
+        &self,
+        pattern: &str,
+        ignore_case: bool,
+        pos: usize,
+    ) -> Result<Regex, ParseError> {
+        RegexBuilder::new(pattern)
+            .case_insensitive(ignore_case)
+            .build()
+            .map_err(|e| {
+                // The regex error is multi-line; its last line is the reason.
+                let reason = e.to_string();
+                let reason = reason.lines().last().unwrap_or("").trim().to_string();
+                self.error_at(pos, format!("invalid regex: {}", reason))
+            })
+    }
+
+    fn expand_named(&self, name: &str, pos: usize) -> Result<Expr, ParseError> {
+        let Some(text) = self.named.get(name) else {
+            return Err(self.error_at(pos, format!("unknown filter `@{}`", name)));
+        };
+        if self.depth >= MAX_DEPTH {
+            return Err(self.error_at(pos, format!("filter `@{}` nests too deeply", name)));
+        }
+        parse_expr(text, self.named, self.depth + 1)
+            .map_err(|e| self.error_at(pos, format!("in `@{}`: {}", name, e)))
+    }
+}
+
+/// Parses `20`, `-5`, `1.5`, `20%`, `512K`, `1G`, `2GiB` (binary multiples).
+pub(crate) fn parse_number(word: &str) -> Option<f64> {
+    let word = word.strip_suffix('%').unwrap_or(word);
+    match word.strip_prefix('-') {
+        Some(magnitude) => parse_magnitude(magnitude).map(|value| -value),
+        None => parse_magnitude(word),
+    }
+}
+
+/// `parse_number` without a sign or percent, as sizes are written.
+pub(crate) fn parse_magnitude(word: &str) -> Option<f64> {
+    let split = word
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(word.len());
+    let (digits, unit) = word.split_at(split);
+    let value: f64 = digits.parse().ok()?;
+    let multiplier = match unit.to_lowercase().as_str() {
+        "" | "b" => 1.0,
+        "k" | "kb" | "kib" => 1024.0,
+        "m" | "mb" | "mib" => 1024.0 * 1024.0,
+        "g" | "gb" | "gib" => 1024.0 * 1024.0 * 1024.0,
+        "t" | "tb" | "tib" => 1024.0 * 1024.0 * 1024.0 * 1024.0,
+        _ => return None,
+    };
+    Some(value * multiplier)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process() -> ProcessInfo {
+        ProcessInfo {
+            pid: 4242,
+            ppid: Some(1),
+            name: "java".to_string(),
+            cmd: "/usr/bin/java -jar server.jar".to_string(),
+            exe: "/usr/bin/java".to_string(),
+            user: "postgres".to_string(),
+            cpu_usage: 35.0,
+            memory: 2 * 1024 * 1024 * 1024,
+            virtual_memory: 8 * 1024 * 1024 * 1024,
+            pss: None,
+            uss: None,
+            swap: None,
+            threads: Some(40),
+            status: "Sleep".to_string(),
+            nice: Some(5),
+            priority: Some(25),
+            start_time: 1_700_000_000,
+            cpu_time: Some(120.5),
+            read_rate: 0,
+            write_rate: 4096,
+            gpu_memory: None,
+            cgroup: "/system.slice/postgresql.service".to_string(),
+            container: "db".to_string(),
+            thread_of: None,
+        }
+    }
+
+    fn eval(input: &str) -> bool {
+        let filter = Filter::parse(input, &BTreeMap::new()).unwrap();
+        filter.matches(&process(), 8 * 1024 * 1024 * 1024)
+    }
+
+    fn error(input: &str) -> ParseError {
+        Filter::parse(input, &BTreeMap::new()).unwrap_err()
+    }
+
+    #[test]
+    fn bare_words_search_text_fields() {
+        assert!(eval("java"));
+        assert!(eval("JAVA"));
+        assert!(eval("server.jar"));
+        assert!(eval("postgres"));
+        assert!(!eval("python"));
+        assert!(eval("\"-jar server\""));
+        assert!(eval("java server"));
+        assert!(!eval("java python"));
+    }
+
+    #[test]
+    fn numeric_comparisons() {
+        assert!(eval("cpu > 20"));
+        assert!(eval("cpu >= 35"));
+        assert!(!eval("cpu < 35"));
+        assert!(eval("cpu <= 35%"));
+        assert!(eval("pid == 4242"));
+        assert!(eval("pid != 1"));
+        assert!(eval("threads > 10"));
+        assert!(eval("nice == 5"));
+        assert!(eval("time > 120"));
+        assert!(eval("mem% == 25"));
+        assert!(eval("nice > -5"));
+        assert!(!eval("nice < -5"));
+        assert_eq!(parse_number("-5"), Some(-5.0));
+        assert_eq!(parse_number("-1k"), Some(-1024.0));
+        assert_eq!(parse_number("-"), None);
+    }
+
+    #[test]
+    fn size_units() {
+        assert!(eval("mem > 1G"));
+        assert!(eval("mem >= 2GiB"));
+        assert!(!eval("mem > 2gb"));
+        assert!(eval("virt > 512M"));
+        assert!(eval("write == 4K"));
+        assert_eq!(parse_number("1.5k"), Some(1536.0));
+        assert_eq!(parse_number("3x"), None);
+    }
+
+    #[test]
+    fn missing_values_never_match() {
+        assert!(!eval("gpu_mem > 0"));
+        assert!(!eval("gpu_mem == 0"));
+        assert!(eval("!(gpu_mem > 0)"));
+    }
+
+    #[test]
+    fn text_comparisons() {
+        assert!(eval("user == \"postgres\""));
+        assert!(eval("user == postgres"));
+        assert!(eval("user != root"));
+        assert!(eval("state == sleep"));
+        assert!(!eval("state == zombie"));
+        assert!(eval("name ~ /^ja/"));
+        assert!(!eval("name ~ /^JA/"));
+        assert!(eval("name ~ /^JA/i"));
+        assert!(eval("cmd ~ \"server\\.jar$\""));
+        assert!(eval("cmd ~ \"\\w+\\.jar\""));
+        assert!(!eval("cmd ~ \"\\d+\""));
+        assert!(eval("cmd ~ 'java \\'?-jar'"));
+        assert!(eval("name !~ /python/"));
+        assert!(eval("/bin\\/java/"));
+        // Paths are plain searches, not regexes.
+        assert!(eval("/usr/bin/java"));
+        assert!(!eval("/usr/bin/python"));
+        assert!(eval("cmd ~ /usr/bin/java"));
+        assert!(!eval("/usr/lib/x/i"));
+        assert!(eval("cgroup ~ /postgresql/"));
+        assert!(eval("container == db"));
+    }
+
+    #[test]
+    fn boolean_operators_and_precedence() {
+        assert!(eval("cpu > 20 && user == \"postgres\""));
+        assert!(eval("cpu > 90 || user == postgres"));
+        assert!(!eval("cpu > 90 || user == root"));
+        // && binds tighter than ||.
+        assert!(eval("user == root && cpu > 90 || name == java"));
+        assert!(!eval("user == root && (cpu > 90 || name == java)"));
+        assert!(eval("not user == root"));
+        assert!(eval("!!java"));
+        assert!(eval("cpu > 20 and not (state == zombie or nice < 0)"));
+    }
+
+    #[test]
+    fn named_filters() {
+        let mut named = BTreeMap::new();
+        named.insert("hogs".to_string(), "cpu > 20".to_string());
+        named.insert("pg".to_string(), "user == postgres".to_string());
+        named.insert("both".to_string(), "@hogs && @pg".to_string());
+        named.insert("loop".to_string(), "@loop".to_string());
+
+        let p = process();
+        let total = 8 * 1024 * 1024 * 1024;
+        assert!(Filter::parse("@both", &named).unwrap().matches(&p, total));
+        assert!(!Filter::parse("@hogs && !@pg", &named)
+            .unwrap()
+            .matches(&p, total));
+
+        let err = Filter::parse("@missing", &named).unwrap_err();
+        assert_eq!(err.message, "unknown filter `@missing`");
+        let err = Filter::parse("@loop", &named).unwrap_err();
+        assert!(err.message.contains("nests too deeply"), "{}", err.message);
+    }
+
+    #[test]
+    fn error_messages_point_at_the_problem() {
+        assert_eq!(
+            error("cpuu > 3"),
+            ParseError {
+                position: 0,
+                message: "unknown field `cpuu`".to_string()
+            }
+        );
+        assert_eq!(error("cpu >").message, "expected a value after `>`");
+        assert_eq!(error("cpu >").position, 5);
+        assert_eq!(error("cpu > lots").message, "invalid number `lots`");
+        assert_eq!(error("cpu > lots").position, 6);
+        assert_eq!(
+            error("cpu ~ /x/").message,
+            "`~` only applies to text fields"
+        );
+        assert_eq!(
+            error("name > 3").message,
+            "`>` does not apply to text field"
+        );
+        assert_eq!(error("user == \"pg").message, "unterminated string");
+        assert_eq!(error("name ~ /ja").message, "unterminated regex");
+        assert!(error("name ~ /(/").message.starts_with("invalid regex"));
+        assert_eq!(error("(cpu > 1").message, "unclosed `(`");
+        assert_eq!(error("cpu > 1)").message, "unexpected `)`");
+        assert_eq!(error("cpu > 1 &&").message, "expected a condition");
+        assert_eq!(error("   ").message, "empty filter");
+        assert_eq!(error("cpu > 1 # x").message, "unexpected character `#`");
+        assert_eq!(error("cpuu > 3").to_string(), "col 1: unknown field `cpuu`");
+    }
+}
@@ -0,0 +1,474 @@
+//! Shell commands and webhooks run when alerts fire or resolve.
+//!
+//! Every hook runs on its own thread with a timeout, so a slow or hung hook
+//! never holds up sampling. Commands get the alert as `NEXMON_*` environment
+//! variables and as JSON on stdin; webhooks get the same JSON as a POST body.
+//! A flapping alert runs each hook at most once per `rate_limit`; when a
+//! fire is skipped, so is the matching resolve.
+
+use crate::alert::{Alert, AlertEvent, EventKind};
+use crate::config::parse_duration;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    io::Write,
+    process::{Command, Stdio},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Hooks running at once; further events are dropped with a warning.
+const MAX_RUNNING: usize = 8;
+
+/// One `[[hooks]]` entry of the config.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HookConfig {
+    /// Shell command, run with `sh -c`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    /// URL the event JSON is POSTed to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Alert rules this hook is for; empty means all of them.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<String>,
+    /// `fired` and/or `resolved`; empty means both.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub on: Vec<EventKind>,
+    /// How long the hook may run before it is killed (default 10s).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<String>,
+    /// Minimum time between runs for the same alert (default 1m).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<String>,
+}
+
+#[derive(Clone)]
+enum Target {
+    Command(String),
+    Webhook(String),
+}
+
+impl Target {
+    fn describe(&self) -> String {
+        match self {
+            Target::Command(command) => format!("hook `{}`", command),
+            Target::Webhook(url) => format!("webhook {}", url),
+        }
+    }
+}
+
+struct Hook {
+    target: Target,
+    rules: Vec<String>,
+    on: Vec<EventKind>,
+    timeout: Duration,
+    rate_limit: Duration,
+}
+
+/// What hooks receive, as JSON.
+#[derive(Serialize)]
+struct Payload<'a> {
+    event: EventKind,
+    /// Unix time in milliseconds.
+    at: u64,
+    host: &'a str,
+    #[serde(flatten)]
+    alert: &'a Alert,
+}
+
+pub struct Hooks {
+    hooks: Vec<Hook>,
+    host: String,
+    /// Last run per (hook, rule, metric), for rate limiting.
+    last_run: HashMap<(usize, String, String), Instant>,
+    /// Alerts whose fire was rate limited, so their resolve is skipped too.
+    suppressed: HashSet<(usize, String, String)>,
+    running: Arc<AtomicUsize>,
+    failures_tx: mpsc::Sender<String>,
+    failures_rx: mpsc::Receiver<String>,
+}
+
+impl Hooks {
+    pub fn new(configs: &[HookConfig]) -> Result<Self, String> {
+        let hooks = configs
+            .iter()
+            .enumerate()
+            .map(|(i, config)| {
+                let target = match (&config.command, &config.url) {
+                    (Some(command), None) => Target::Command(command.clone()),
+                    (None, Some(url)) => Target::Webhook(url.clone()),
+                    _ => return Err(format!("hook {}: set exactly one of command or url", i + 1)),
+                };
+                let duration = |text: &Option<String>, default: Duration| match text {
+                    Some(text) => {
+                        parse_duration(text).map_err(|e| format!("hook {}: {}", i + 1, e))
+                    }
+                    None => Ok(default),
+                };
+                Ok(Hook {
+                    target,
+                    rules: config.rules.clone(),
+                    on: config.on.clone(),
+                    timeout: duration(&config.timeout, Duration::from_secs(10))?,
+                    rate_limit: duration(&config.rate_limit, Duration::from_secs(60))?,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        let (failures_tx, failures_rx) = mpsc::channel();
+        Ok(Self {
+            hooks,
+            host: sysinfo::System::host_name().unwrap_or_default(),
+            last_run: HashMap::new(),
+            suppressed: HashSet::new(),
+            running: Arc::new(AtomicUsize::new(0)),
+            failures_tx,
+            failures_rx,
+        })
+    }
+
+    /// Starts every hook that applies to `events`, without waiting for them.
+    pub fn dispatch(&mut self, events: &[AlertEvent]) {
+        for event in events {
+            for i in 0..self.hooks.len() {
+                let hook = &self.hooks[i];
+                let wanted = (hook.rules.is_empty() || hook.rules.contains(&event.alert.rule))
+                    && (hook.on.is_empty() || hook.on.contains(&event.kind));
+                if !wanted || !self.allow(i, event) {
+                    continue;
+                }
+                self.spawn(i, event);
+            }
+        }
+    }
+
+    /// Applies the rate limit of hook `i` to `event`.
+    fn allow(&mut self, i: usize, event: &AlertEvent) -> bool {
+        let key = (i, event.alert.rule.clone(), event.alert.metric.clone());
+        match event.kind {
+            EventKind::Fired => {
+                let now = Instant::now();
+                let limited = self
+                    .last_run
+                    .get(&key)
+                    .is_some_and(|last| now.duration_since(*last) < self.hooks[i].rate_limit);
+                if limited {
+                    self.suppressed.insert(key);
+                } else {
+                    self.last_run.insert(key, now);
+                }
+                !limited
+            }
+            EventKind::Resolved => !self.suppressed.remove(&key),
+        }
+    }
+
+    fn spawn(&self, i: usize, event: &AlertEvent) {
+        let hook = &self.hooks[i];
+        if self.running.load(Ordering::SeqCst) >= MAX_RUNNING {
+            let _ = self.failures_tx.send(format!(
+                "{} skipped: {} hooks already running",
+                hook.target.describe(),
+                MAX_RUNNING
+            ));
+            return;
+        }
+
+        let payload = Payload {
+            event: event.kind,
+            at: event.at,
+            host: &self.host,
+            alert: &event.alert,
+        };
+        let json = serde_json::to_string(&payload).unwrap_or_default();
+        let env = environment(&payload);
+        let target = hook.target.clone();
+        let timeout = hook.timeout;
+        let running = Arc::clone(&self.running);
+        let failures = self.failures_tx.clone();
+
+        running.fetch_add(1, Ordering::SeqCst);
+        thread::spawn(move || {
+            let result = match &target {
+                Target::Command(command) => run_command(command, &env, &json, timeout),
+                Target::Webhook(url) => post(url, &json, timeout),
+            };
+            if let Err(err) = result {
+                let _ = failures.send(format!("{} failed: {}", target.describe(), err));
+            }
+            running.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+
+    /// Failures reported by hooks since the last call.
+    pub fn failures(&self) -> Vec<String> {
+        self.failures_rx.try_iter().collect()
+    }
+
+    /// Failures reported since the last call, as one footer message.
+    pub fn failure_message(&self) -> Option<String> {
+        join_failures(self.failures())
+    }
+}
+
+fn join_failures(failures: Vec<String>) -> Option<String> {
+    match failures.len() {
+        0 => None,
+        1 => failures.into_iter().next(),
+        n => Some(format!("{} hooks failed: {}", n, failures.join("; "))),
+    }
+}
+
+fn environment(payload: &Payload) -> Vec<(&'static str, String)> {
+    let alert = payload.alert;
+    let event = match payload.event {
+        EventKind::Fired => "fired",
+        EventKind::Resolved => "resolved",
+    };
+    vec![
+        ("NEXMON_EVENT", event.to_string()),
+        ("NEXMON_AT", payload.at.to_string()),
+        ("NEXMON_HOST", payload.host.to_string()),
+        ("NEXMON_RULE", alert.rule.clone()),
+        ("NEXMON_METRIC", alert.metric.clone()),
+        ("NEXMON_VALUE", alert.value.to_string()),
+        ("NEXMON_CONDITION", alert.condition.clone()),
+        ("NEXMON_SINCE", alert.since.to_string()),
+    ]
+}
+
+fn run_command(
+    command: &str,
+    env: &[(&str, String)],
+    json: &str,
+    timeout: Duration,
+) -> Result<(), String> {
+    let (shell, flag) = if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+    let mut child = Command::new(shell)
+        .arg(flag)
+        .arg(command)
+        .envs(env.iter().map(|(k, v)| (k, v)))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| e.to_string())?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // A hook that ignores stdin may exit before reading it.
+        let _ = stdin.write_all(json.as_bytes());
+    }
+
+    let deadline = Instant::now() + timeout;
+    loop {
+        match child.try_wait().map_err(|e| e.to_string())? {
+            Some(status) if status.success() => return Ok(()),
+            Some(status) => return Err(format!("exited with {}", status)),
+            None if Instant::now() >= deadline => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("timed out after {:?}, killed", timeout));
+            }
+            None => thread::sleep(Duration::from_millis(20)),
+        }
+    }
+}
+
+fn post(url: &str, json: &str, timeout: Duration) -> Result<(), String> {
+    ureq::AgentBuilder::new()
+        .timeout(timeout)
+        .build()
+        .post(url)
+        .set("Content-Type", "application/json")
+        .send_string(json)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read};
+    use std::net::TcpListener;
+
+    fn event(kind: EventKind, rule: &str) -> AlertEvent {
+        AlertEvent {
+            kind,
+            at: 5000,
+            alert: Alert {
+                rule: rule.to_string(),
+                metric: "cpu.overall".to_string(),
+                value: 97.5,
+                condition: "cpu.overall > 90".to_string(),
+                since: 5000,
+            },
+        }
+    }
+
+    fn hooks(config: HookConfig) -> Hooks {
+        Hooks::new(&[config]).unwrap()
+    }
+
+    fn wait_for(mut done: impl FnMut() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !done() {
+            assert!(Instant::now() < deadline, "timed out waiting for hook");
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("nexmon-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn command_gets_env_and_json() {
+        let dir = temp_dir("hook-env");
+        let out = dir.join("out");
+        let mut hooks = hooks(HookConfig {
+            command: Some(format!(
+                "{{ echo \"$NEXMON_EVENT $NEXMON_RULE $NEXMON_VALUE\"; cat; }} > {}.tmp && mv {0}.tmp {0}",
+                out.display()
+            )),
+            ..HookConfig::default()
+        });
+        hooks.dispatch(&[event(EventKind::Fired, "busy")]);
+        wait_for(|| out.exists());
+
+        let text = std::fs::read_to_string(&out).unwrap();
+        let (env, json) = text.split_once('\n').unwrap();
+        assert_eq!(env, "fired busy 97.5");
+        let json: serde_json::Value = serde_json::from_str(json).unwrap();
+        assert_eq!(json["event"], "fired");
+        assert_eq!(json["metric"], "cpu.overall");
+        assert_eq!(json["since"], 5000);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hung_command_times_out_without_blocking() {
+        let mut hooks = hooks(HookConfig {
+            command: Some("sleep 10".to_string()),
+            timeout: Some("1s".to_string()),
+            ..HookConfig::default()
+        });
+        let start = Instant::now();
+        hooks.dispatch(&[event(EventKind::Fired, "busy")]);
+        assert!(start.elapsed() < Duration::from_millis(500));
+
+        let mut failures = Vec::new();
+        wait_for(|| {
+            failures.extend(hooks.failures());
+            !failures.is_empty()
+        });
+        assert!(failures[0].contains("timed out"), "{:?}", failures);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rate_limit_skips_flapping_fire_and_its_resolve() {
+        let dir = temp_dir("hook-rate");
+        let log = dir.join("log");
+        let mut hooks = hooks(HookConfig {
+            command: Some(format!("echo $NEXMON_EVENT >> {}", log.display())),
+            rules: vec!["busy".to_string()],
+            ..HookConfig::default()
+        });
+        for _ in 0..2 {
+            hooks.dispatch(&[event(EventKind::Fired, "busy")]);
+            wait_for(|| hooks.running.load(Ordering::SeqCst) == 0);
+            hooks.dispatch(&[event(EventKind::Resolved, "busy")]);
+            wait_for(|| hooks.running.load(Ordering::SeqCst) == 0);
+        }
+        // Other rules are not this hook's business.
+        hooks.dispatch(&[event(EventKind::Fired, "other")]);
+        wait_for(|| hooks.running.load(Ordering::SeqCst) == 0);
+
+        assert_eq!(std::fs::read_to_string(&log).unwrap(), "fired\nresolved\n");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn webhook_posts_json() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/incidents", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            let mut stream = stream;
+            stream
+                .write_all(b"HTTP/1.1 204 No Content\r\nContent-Length: 0\r\n\r\n")
+                .unwrap();
+            (request_line, String::from_utf8(body).unwrap())
+        });
+
+        let mut hooks = hooks(HookConfig {
+            url: Some(url),
+            on: vec![EventKind::Resolved],
+            ..HookConfig::default()
+        });
+        // Only resolves were asked for.
+        hooks.dispatch(&[event(EventKind::Fired, "busy")]);
+        hooks.dispatch(&[event(EventKind::Resolved, "busy")]);
+
+        let (request_line, body) = server.join().unwrap();
+        assert!(request_line.starts_with("POST /incidents "));
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["event"], "resolved");
+        assert_eq!(json["rule"], "busy");
+        wait_for(|| hooks.running.load(Ordering::SeqCst) == 0);
+        assert!(hooks.failures().is_empty());
+    }
+
+    #[test]
+    fn failures_share_one_message() {
+        assert_eq!(join_failures(Vec::new()), None);
+        let failed = "hook `false` failed: exit status: 1".to_string();
+        let skipped = "webhook http://localhost/ skipped: 8 hooks already running".to_string();
+        assert_eq!(join_failures(vec![failed.clone()]), Some(failed.clone()));
+        assert_eq!(
+            join_failures(vec![failed.clone(), skipped.clone()]).unwrap(),
+            format!("2 hooks failed: {}; {}", failed, skipped)
+        );
+    }
+
+    #[test]
+    fn config_needs_one_target() {
+        assert!(Hooks::new(&[HookConfig::default()]).is_err());
+        assert!(Hooks::new(&[HookConfig {
+            url: Some("http://localhost/".to_string()),
+            timeout: Some("soon".to_string()),
+            ..HookConfig::default()
+        }])
+        .is_err());
+    }
+}
@@ -1,20 +1,36 @@
+pub mod alert;
 pub mod app;
+pub mod config;
+pub mod filter;
+pub mod hooks;
 pub mod system;
 pub mod ui;
 
+use alert::AlertEngine;
 use app::AppState;
-use clap::Parser;
-use color_eyre::Result;
+use clap::{Parser, Subcommand};
+use color_eyre::{eyre::eyre, Result};
+use config::Config;
 use crossterm::{
     event::{self, Event, KeyCode, KeyModifiers},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
+use hooks::Hooks;
 use ratatui::{backend::CrosstermBackend, Terminal};
 use std::{
     io,
+    path::PathBuf,
     time::{Duration, Instant},
 };
+use system::collector::Collector;
+use system::csvlog::CsvLog;
+use system::recording::{Player, Recorder};
+use system::remote::{Agent, Remote};
+use system::rollup::Rollups;
+use system::services::ServiceAction;
+use system::snapshot::History;
+use system::source::{Live, Source};
 
 #[derive(Parser)]
 #[command(name = "nexmon", about = "Futuristic system monitor", version)]
@@ -27,6 +43,45 @@ struct Args {
     processes: usize,
     #[arg(short, long, default_value = "cpu")]
     sort: String,
+    /// Number of past samples kept for pausing and stepping back
+    #[arg(long, default_value_t = 120)]
+    history: usize,
+    /// How far back charts reach, e.g. 10m, 1h, 24h (default: 10m)
+    #[arg(long, value_parser = config::parse_duration)]
+    retention: Option<Duration>,
+    /// Config file (default: ~/.config/nexmon/config.toml)
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// Append one row of metrics per tick to this CSV file
+    #[arg(long, value_name = "PATH")]
+    log_csv: Option<PathBuf>,
+    /// Rotate the CSV log when it reaches this size, e.g. 512K, 10M
+    #[arg(long, value_parser = config::parse_size, default_value = "10M")]
+    log_csv_max_size: u64,
+    /// Rotated CSV logs to keep (log.csv.1, log.csv.2, ...)
+    #[arg(long, default_value_t = 5)]
+    log_csv_keep: usize,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Monitor as usual while writing every sample to a compressed file
+    Record { file: PathBuf },
+    /// Play back a file made with `record`
+    Replay { file: PathBuf },
+    /// Collect without a UI and stream samples to `connect` clients over TCP
+    Agent {
+        /// Address to listen on, e.g. 127.0.0.1:7070
+        #[arg(long, value_name = "ADDR")]
+        listen: String,
+    },
+    /// Monitor another machine running `nexmon agent`
+    Connect {
+        #[arg(value_name = "HOST:PORT")]
+        addr: String,
+    },
 }
 
 #[tokio::main]
@@ -34,18 +89,88 @@ async fn main() -> Result<()> {
     color_eyre::install()?;
     let args = Args::parse();
 
+    let config_path = args.config.clone().or_else(Config::default_path);
+    let config = match &config_path {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
+    let retention = match (args.retention, &config.retention) {
+        (Some(retention), _) => retention,
+        (None, Some(text)) => {
+            config::parse_duration(text).map_err(|err| eyre!("retention in config: {}", err))?
+        }
+        (None, None) => Duration::from_secs(10 * 60),
+    };
+
+    let interval = Duration::from_millis(args.interval);
+    let mut source = match &args.command {
+        Some(Command::Replay { file }) => Source::Replay(Box::new(Player::open(file)?)),
+        Some(Command::Connect { addr }) => Source::Remote(Remote::connect(addr)?),
+        Some(Command::Agent { listen }) => {
+            let alerts = AlertEngine::new(&config.alerts, &config.filters, interval)
+                .map_err(|err| eyre!("{}", err))?;
+            let hooks = Hooks::new(&config.hooks).map_err(|err| eyre!("{}", err))?;
+            let agent = Agent::bind(listen, interval)?;
+            eprintln!("nexmon agent listening on {}", agent.local_addr());
+            return run_agent(
+                &agent,
+                collector(&args, &config, interval),
+                alerts,
+                hooks,
+                interval,
+            );
+        }
+        command => {
+            let recorder = match command {
+                Some(Command::Record { file }) => Some(Recorder::create(file, interval)?),
+                _ => None,
+            };
+            let csv = args
+                .log_csv
+                .as_deref()
+                .map(|path| CsvLog::open(path, interval, args.log_csv_max_size, args.log_csv_keep))
+                .transpose()?;
+            let alerts = AlertEngine::new(&config.alerts, &config.filters, interval)
+                .map_err(|err| eyre!("{}", err))?;
+            let hooks = Hooks::new(&config.hooks).map_err(|err| eyre!("{}", err))?;
+            Source::Live(Box::new(Live {
+                collector: collector(&args, &config, interval),
+                interval,
+                recorder,
+                csv,
+                alerts,
+                hooks,
+            }))
+        }
+    };
+    let first = source.first()?;
+
     enable_raw_mode()?;
     let mut stdout = io::stdout();
     execute!(stdout, EnterAlternateScreen)?;
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    let mut app = AppState::new(args.interval, args.show_loopback, args.processes, args.sort);
+    let mut rollups = Rollups::new(retention, source.interval());
+    rollups.record(&first);
+    let mut app = AppState::new(
+        source.interval().as_millis() as u64,
+        History::new(args.history, first),
+        rollups,
+        args.processes,
+        args.sort,
+        config,
+        config_path,
+    );
+    match &source {
+        Source::Replay(player) => app.replay = Some(player.status()),
+        Source::Remote(remote) => app.remote = Some(remote.addr().to_string()),
+        Source::Live(_) => {}
+    }
 
-    let tick_rate = Duration::from_millis(args.interval);
     let mut last_tick = Instant::now();
 
-    let res = run_app(&mut terminal, &mut app, tick_rate, &mut last_tick);
+    let res = run_app(&mut terminal, &mut app, &mut source, &mut last_tick);
 
     disable_raw_mode()?;
     execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
@@ -55,60 +180,225 @@ async fn main() -> Result<()> {
         println!("{:?}", err);
     }
 
-    Ok(())
+    source.finish()
+}
+
+/// A collector with the paths and engine socket the config overrides.
+fn collector(args: &Args, config: &Config, interval: Duration) -> Collector {
+    let mut collector = Collector::new(interval, args.show_loopback);
+    if let Some(root) = &config.cgroup_root {
+        collector.set_cgroup_root(root.clone());
+    }
+    if let Some(root) = &config.power_supply_root {
+        collector.set_power_supply_root(root.clone());
+    }
+    if let Some(setting) = &config.container_engine {
+        collector.set_engine_socket(system::engine::find_socket(Some(setting)));
+    }
+    collector
+}
+
+/// Collects every `interval` what the connected clients need, evaluates
+/// alerts and runs hooks, and publishes the result until killed.
+fn run_agent(
+    agent: &Agent,
+    mut collector: Collector,
+    mut alerts: AlertEngine,
+    mut hooks: Hooks,
+    interval: Duration,
+) -> Result<()> {
+    loop {
+        let started = Instant::now();
+        let settings = agent.settings();
+        collector.set_pressure_cgroup(settings.pressure_cgroup);
+        collector.set_walk_cgroups(settings.walk_cgroups);
+        collector.set_list_services(settings.list_services);
+        collector.set_smaps_pids(settings.smaps_pids);
+        let mut snapshot = collector.collect();
+        if let Some(err) = collector.take_engine_error() {
+            eprintln!("Container names unavailable: {}", err);
+        }
+        if let Some(err) = collector.take_services_error() {
+            eprintln!("Services unavailable: {}", err);
+        }
+        hooks.dispatch(&alerts.evaluate(&mut snapshot));
+        for failure in hooks.failures() {
+            eprintln!("{}", failure);
+        }
+        agent.publish(&snapshot)?;
+        std::thread::sleep(interval.saturating_sub(started.elapsed()));
+    }
 }
 
 fn run_app<B: ratatui::backend::Backend>(
     terminal: &mut Terminal<B>,
     app: &mut AppState,
-    tick_rate: Duration,
+    source: &mut Source,
     last_tick: &mut Instant,
 ) -> io::Result<()> {
     loop {
         terminal.draw(|f| ui::render(f, app))?;
 
+        let tick_rate = source.tick_rate();
         let timeout = tick_rate.saturating_sub(last_tick.elapsed());
         if crossterm::event::poll(timeout)? {
             if let Event::Key(key) = event::read()? {
-                if app.search_mode {
+                app.status_message = None;
+                if app.column_picker.is_some() {
+                    match key.code {
+                        KeyCode::Esc | KeyCode::Enter | KeyCode::Char('f') => {
+                            app.column_picker = None
+                        }
+                        KeyCode::Char('j') | KeyCode::Down => app.move_picker_cursor(1),
+                        KeyCode::Char('k') | KeyCode::Up => app.move_picker_cursor(-1),
+                        KeyCode::Char('J') => app.shift_picker_column(1),
+                        KeyCode::Char('K') => app.shift_picker_column(-1),
+                        KeyCode::Char(' ') => app.toggle_picker_column(),
+                        KeyCode::Char('s') | KeyCode::Char('S') => app.save_columns(),
+                        _ => {}
+                    }
+                } else if app.affinity_dialog.is_some() {
+                    match key.code {
+                        KeyCode::Esc => app.affinity_dialog = None,
+                        KeyCode::Enter => app.apply_affinity(),
+                        KeyCode::Char('j') | KeyCode::Down => app.move_affinity_cursor(1),
+                        KeyCode::Char('k') | KeyCode::Up => app.move_affinity_cursor(-1),
+                        KeyCode::Char(' ') => app.toggle_affinity_cpu(),
+                        KeyCode::Char('a') => app.toggle_all_affinity_cpus(),
+                        _ => {}
+                    }
+                } else if app.show_alerts {
+                    if matches!(key.code, KeyCode::Esc | KeyCode::Char('!')) {
+                        app.show_alerts = false;
+                    }
+                } else if app.service_confirm.is_some() {
+                    match key.code {
+                        KeyCode::Char('y') | KeyCode::Char('Y') => app.apply_service_action(),
+                        _ => app.service_confirm = None,
+                    }
+                } else if app.show_memory_details {
+                    if matches!(key.code, KeyCode::Esc | KeyCode::Char('d')) {
+                        app.show_memory_details = false;
+                    }
+                } else if let Some(name) = &mut app.filter_name_input {
+                    match key.code {
+                        KeyCode::Enter => {
+                            let name = std::mem::take(name);
+                            app.filter_name_input = None;
+                            app.save_filter(name);
+                        }
+                        KeyCode::Esc => app.filter_name_input = None,
+                        KeyCode::Backspace => {
+                            name.pop();
+                        }
+                        KeyCode::Char(c) => name.push(c),
+                        _ => {}
+                    }
+                } else if app.search_mode {
                     match key.code {
                         KeyCode::Enter => app.search_mode = false,
                         KeyCode::Esc => {
                             app.search_mode = false;
                             app.search_query.clear();
+                            app.update_filter();
                         }
                         KeyCode::Backspace => {
                             app.search_query.pop();
+                            app.update_filter();
+                        }
+                        KeyCode::Char('s') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                            app.filter_name_input = Some(String::new())
                         }
                         KeyCode::Char(c) => {
                             app.search_query.push(c);
+                            app.update_filter();
                         }
                         _ => {}
                     }
-                } else {
+                } else if !source.handle_key(app, key.code) {
+                    let containers = app.bottom_view == app::BottomView::Containers;
+                    let services = app.bottom_view == app::BottomView::Services;
                     match key.code {
                         KeyCode::Char('q') | KeyCode::Char('Q') => return Ok(()),
                         KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
                             return Ok(())
                         }
+                        KeyCode::Char('j') | KeyCode::Down if containers => {
+                            app.move_cgroup_cursor(1)
+                        }
+                        KeyCode::Char('k') | KeyCode::Up if containers => {
+                            app.move_cgroup_cursor(-1)
+                        }
+                        KeyCode::PageDown if containers => app.cgroup_page_down(),
+                        KeyCode::PageUp if containers => app.cgroup_page_up(),
+                        KeyCode::Home if containers => app.move_cgroup_cursor(isize::MIN),
+                        KeyCode::End if containers => app.move_cgroup_cursor(isize::MAX),
+                        KeyCode::Enter if containers => app.show_cgroup_processes(),
+                        KeyCode::Char('j') | KeyCode::Down if services => {
+                            app.move_service_cursor(1)
+                        }
+                        KeyCode::Char('k') | KeyCode::Up if services => app.move_service_cursor(-1),
+                        KeyCode::PageDown if services => app.service_page_down(),
+                        KeyCode::PageUp if services => app.service_page_up(),
+                        KeyCode::Home if services => app.move_service_cursor(isize::MIN),
+                        KeyCode::End if services => app.move_service_cursor(isize::MAX),
+                        KeyCode::Enter if services => app.show_service_processes(),
+                        KeyCode::Char('r') if services => {
+                            app.confirm_service_action(ServiceAction::Restart)
+                        }
+                        KeyCode::Char('s') if services => {
+                            app.confirm_service_action(ServiceAction::Stop)
+                        }
+                        KeyCode::Tab => app.cycle_bottom_view(),
                         KeyCode::Char('j') | KeyCode::Down => app.next_process(),
                         KeyCode::Char('k') | KeyCode::Up => app.previous_process(),
+                        KeyCode::PageDown => app.page_down(),
+                        KeyCode::PageUp => app.page_up(),
+                        KeyCode::Home => app.first_process(),
+                        KeyCode::End => app.last_process(),
                         KeyCode::Char('C') | KeyCode::Char('c') => {
-                            app.sort_column = app::SortColumn::Cpu
+                            app.set_sort(app::SortColumn::Cpu)
                         }
                         KeyCode::Char('M') | KeyCode::Char('m') => {
-                            app.sort_column = app::SortColumn::Memory
+                            app.set_sort(app::SortColumn::Memory)
                         }
                         KeyCode::Char('P') | KeyCode::Char('p') => {
-                            app.sort_column = app::SortColumn::Pid
+                            app.set_sort(app::SortColumn::Pid)
                         }
                         KeyCode::Char('N') | KeyCode::Char('n') => {
-                            app.sort_column = app::SortColumn::Name
-                        }
-                        KeyCode::Char('R') | KeyCode::Char('r') => {
-                            app.sort_ascending = !app.sort_ascending
+                            app.set_sort(app::SortColumn::Name)
                         }
+                        KeyCode::Char('R') | KeyCode::Char('r') => app.reverse_sort(),
+                        KeyCode::Char('s') => app.cycle_sort(),
+                        KeyCode::Char(' ') => app.toggle_pause(),
+                        KeyCode::Char('[') => app.step_timeline(-1),
+                        KeyCode::Char(']') => app.step_timeline(1),
+                        KeyCode::Char('{') => app.step_timeline(-10),
+                        KeyCode::Char('}') => app.step_timeline(10),
                         KeyCode::Char('/') => app.search_mode = true,
+                        KeyCode::Char('G') | KeyCode::Char('g') => app.cycle_grouping(),
+                        KeyCode::Char('t') => app.toggle_threads(),
+                        KeyCode::Char('+') => app.renice_selected(1),
+                        KeyCode::Char('-') => app.renice_selected(-1),
+                        KeyCode::Char('x') => app.open_affinity_dialog(),
+                        KeyCode::Char('H') => app.toggle_show_threads(),
+                        KeyCode::Enter => app.toggle_group(None),
+                        KeyCode::Right | KeyCode::Char('l') => app.toggle_group(Some(true)),
+                        KeyCode::Left | KeyCode::Char('h') => app.toggle_group(Some(false)),
+                        KeyCode::Char('f') => app.open_column_picker(),
+                        KeyCode::Char('F') => app.toggle_follow(),
+                        KeyCode::Char('z') => app.cycle_zoom(),
+                        KeyCode::Char('b') => app.scroll_charts(1),
+                        KeyCode::Char('B') => app.scroll_charts(-1),
+                        KeyCode::Char('o') => app.cycle_core_grouping(),
+                        KeyCode::Char('v') => app.cycle_cpu_view(),
+                        KeyCode::Char('(') => app.move_cpu_cursor(-1, ui::cpu::core_count(app)),
+                        KeyCode::Char(')') => app.move_cpu_cursor(1, ui::cpu::core_count(app)),
+                        KeyCode::Char('!') => app.show_alerts = true,
+                        KeyCode::Char('d') => app.show_memory_details = true,
+                        KeyCode::Char('A') | KeyCode::Char('a') => {
+                            app.show_cmdline = !app.show_cmdline
+                        }
                         _ => {}
                     }
                 }
@@ -116,7 +406,7 @@ fn run_app<B: ratatui::backend::Backend>(
         }
 
         if last_tick.elapsed() >= tick_rate {
-            system::collector::refresh(app);
+            source.tick(app);
             *last_tick = Instant::now();
         }
     }
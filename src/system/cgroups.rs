@@ -0,0 +1,391 @@
+//! Every cgroup on the unified (v2) hierarchy with its CPU, memory, IO and
+//! PID usage, for the Containers view.
+
+use crate::system::engine::ContainerInfo;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// What a cgroup holds, going by systemd and container runtime naming.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CgroupKind {
+    #[default]
+    Other,
+    Slice,
+    Service,
+    Scope,
+    Docker,
+    Podman,
+    Containerd,
+    Crio,
+    /// A Kubernetes pod, holding one cgroup per container.
+    Pod,
+}
+
+impl CgroupKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            CgroupKind::Other => "",
+            CgroupKind::Slice => "slice",
+            CgroupKind::Service => "service",
+            CgroupKind::Scope => "scope",
+            CgroupKind::Docker => "docker",
+            CgroupKind::Podman => "podman",
+            CgroupKind::Containerd => "containerd",
+            CgroupKind::Crio => "cri-o",
+            CgroupKind::Pod => "pod",
+        }
+    }
+}
+
+/// Counters and current usage as read from one cgroup directory.
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct CgroupStats {
+    /// Relative to the root and starting with `/`, as in `/proc/<pid>/cgroup`.
+    pub path: String,
+    /// CPU time used since the cgroup was created.
+    pub usage_usec: u64,
+    pub nr_throttled: u64,
+    pub throttled_usec: u64,
+    /// Missing on the root cgroup.
+    pub memory: Option<u64>,
+    /// `None` when unlimited.
+    pub memory_max: Option<u64>,
+    /// Summed over devices.
+    pub read_bytes: u64,
+    pub write_bytes: u64,
+    /// Tasks, threads included; `None` without the pids controller.
+    pub pids: Option<u64>,
+}
+
+/// One cgroup in a snapshot, with rates over the time since the previous one.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CgroupSample {
+    pub path: String,
+    pub kind: CgroupKind,
+    /// Full ID of the container the cgroup runs, if any.
+    pub container_id: Option<String>,
+    /// Name, image and project, when the container engine could be asked.
+    pub container: Option<ContainerInfo>,
+    /// Percent of one CPU.
+    pub cpu_usage: f32,
+    pub memory: Option<u64>,
+    pub memory_max: Option<u64>,
+    pub read_rate: u64,
+    pub write_rate: u64,
+    pub pids: Option<u64>,
+    /// Percent of the time held back by its `cpu.max` quota.
+    pub throttled: f32,
+    /// Quota periods in which it was throttled.
+    pub throttled_periods: u64,
+}
+
+impl CgroupSample {
+    /// 0 for the root cgroup, 1 for its children, and so on.
+    pub fn depth(&self) -> usize {
+        if self.path == "/" {
+            0
+        } else {
+            self.path.matches('/').count()
+        }
+    }
+
+    /// The last component of the path.
+    pub fn name(&self) -> &str {
+        match self.path.rsplit_once('/') {
+            Some((_, name)) if !name.is_empty() => name,
+            _ => &self.path,
+        }
+    }
+}
+
+/// Every cgroup under `root`, parents before children and siblings by
+/// name. Empty when `root` is not a cgroup v2 hierarchy.
+pub fn walk(root: &Path) -> Vec<CgroupStats> {
+    let mut cgroups = Vec::new();
+    if root.join("cgroup.controllers").exists() {
+        visit(root, "/".to_string(), &mut cgroups);
+    }
+    cgroups
+}
+
+fn visit(dir: &Path, path: String, cgroups: &mut Vec<CgroupStats>) {
+    let mut children: Vec<String> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()))
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect();
+    children.sort();
+    let prefix = if path == "/" { "" } else { path.as_str() };
+    let child_paths: Vec<String> = children
+        .iter()
+        .map(|name| format!("{}/{}", prefix, name))
+        .collect();
+
+    cgroups.push(read_stats(dir, path));
+    for (name, child) in children.iter().zip(child_paths) {
+        visit(&dir.join(name), child, cgroups);
+    }
+}
+
+fn read_stats(dir: &Path, path: String) -> CgroupStats {
+    let read = |file: &str| std::fs::read_to_string(dir.join(file)).ok();
+    let number = |file: &str| read(file)?.trim().parse().ok();
+    let cpu = read("cpu.stat").unwrap_or_default();
+    let (read_bytes, write_bytes) = read("io.stat").map_or((0, 0), |text| parse_io_stat(&text));
+    CgroupStats {
+        path,
+        usage_usec: flat_keyed(&cpu, "usage_usec").unwrap_or(0),
+        nr_throttled: flat_keyed(&cpu, "nr_throttled").unwrap_or(0),
+        throttled_usec: flat_keyed(&cpu, "throttled_usec").unwrap_or(0),
+        memory: number("memory.current"),
+        // "max" when unlimited, which does not parse.
+        memory_max: number("memory.max"),
+        read_bytes,
+        write_bytes,
+        pids: number("pids.current"),
+    }
+}
+
+/// The value of `key` in a file of `key value` lines, such as `cpu.stat`.
+fn flat_keyed(text: &str, key: &str) -> Option<u64> {
+    text.lines().find_map(|line| {
+        let (k, value) = line.split_once(' ')?;
+        (k == key).then(|| value.trim().parse().ok())?
+    })
+}
+
+/// Bytes read and written, summed over the `MAJ:MIN rbytes=.. wbytes=..`
+/// line of each device.
+pub fn parse_io_stat(text: &str) -> (u64, u64) {
+    let mut read = 0;
+    let mut written = 0;
+    for field in text.split_whitespace() {
+        match field.split_once('=') {
+            Some(("rbytes", value)) => read += value.parse().unwrap_or(0),
+            Some(("wbytes", value)) => written += value.parse().unwrap_or(0),
+            _ => {}
+        }
+    }
+    (read, written)
+}
+
+/// What the cgroup at `path` holds, and the container ID if it is one.
+pub fn classify(path: &str) -> (CgroupKind, Option<String>) {
+    let name = path.rsplit('/').next().unwrap_or_default();
+    let unit = name
+        .strip_suffix(".scope")
+        .or_else(|| name.strip_suffix(".slice"))
+        .or_else(|| name.strip_suffix(".service"))
+        .unwrap_or(name);
+
+    // The conmon process podman watches each container with.
+    if !unit.starts_with("libpod-conmon-") {
+        for (prefix, kind) in [
+            ("docker-", CgroupKind::Docker),
+            ("libpod-", CgroupKind::Podman),
+            ("cri-containerd-", CgroupKind::Containerd),
+            ("crio-", CgroupKind::Crio),
+        ] {
+            if let Some(id) = unit.strip_prefix(prefix).filter(|id| is_container_id(id)) {
+                return (kind, Some(id.to_string()));
+            }
+        }
+    }
+
+    // Without systemd managing cgroups, containers are bare IDs under a
+    // directory named after the runtime.
+    if is_container_id(name) {
+        let kind = if path.contains("/docker/") {
+            CgroupKind::Docker
+        } else if path.contains("libpod") {
+            CgroupKind::Podman
+        } else if path.contains("kubepods") {
+            CgroupKind::Containerd
+        } else {
+            CgroupKind::Other
+        };
+        if kind != CgroupKind::Other {
+            return (kind, Some(name.to_string()));
+        }
+    }
+
+    let pod = name.starts_with("pod") || (name.contains("-pod") && name.ends_with(".slice"));
+    let kind = if pod && path.contains("kubepods") {
+        CgroupKind::Pod
+    } else if name.ends_with(".slice") {
+        CgroupKind::Slice
+    } else if name.ends_with(".service") {
+        CgroupKind::Service
+    } else if name.ends_with(".scope") {
+        CgroupKind::Scope
+    } else {
+        CgroupKind::Other
+    };
+    (kind, None)
+}
+
+/// ID of the container the cgroup at `path` belongs to, looking up
+/// through its parents.
+pub fn container_id(mut path: &str) -> Option<String> {
+    while path.len() > 1 {
+        if let (_, Some(id)) = classify(path) {
+            return Some(id);
+        }
+        path = &path[..path.rfind('/')?];
+    }
+    None
+}
+
+fn is_container_id(text: &str) -> bool {
+    text.len() == 64 && text.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// `stats` with its counters turned into rates over the `secs` since
+/// `previous` was read from the same cgroup.
+pub fn sample(stats: &CgroupStats, previous: Option<&CgroupStats>, secs: f64) -> CgroupSample {
+    let (kind, container_id) = classify(&stats.path);
+    let mut sample = CgroupSample {
+        path: stats.path.clone(),
+        kind,
+        container_id,
+        memory: stats.memory,
+        memory_max: stats.memory_max,
+        pids: stats.pids,
+        ..CgroupSample::default()
+    };
+    if let Some(previous) = previous {
+        let secs = secs.max(0.001);
+        let usec = secs * 1_000_000.0;
+        let delta = |now: u64, before: u64| now.saturating_sub(before);
+        sample.cpu_usage =
+            (delta(stats.usage_usec, previous.usage_usec) as f64 / usec * 100.0) as f32;
+        sample.throttled =
+            (delta(stats.throttled_usec, previous.throttled_usec) as f64 / usec * 100.0) as f32;
+        sample.throttled_periods = delta(stats.nr_throttled, previous.nr_throttled);
+        sample.read_rate = (delta(stats.read_bytes, previous.read_bytes) as f64 / secs) as u64;
+        sample.write_rate = (delta(stats.write_bytes, previous.write_bytes) as f64 / secs) as u64;
+    }
+    sample
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    const DOCKER_ID: &str = "3f2a1b4c5d6e7f8091a2b3c4d5e6f708192a3b4c5d6e7f8091a2b3c4d5e6f701";
+
+    fn fixtures() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/cgroup")
+    }
+
+    #[test]
+    fn walks_tree_depth_first() {
+        let cgroups = walk(&fixtures());
+        let paths: Vec<&str> = cgroups.iter().map(|c| c.path.as_str()).collect();
+        assert_eq!(paths.len(), 9);
+        assert_eq!(paths[0], "/");
+        assert_eq!(paths[1], "/kubepods.slice");
+        assert_eq!(paths[5], "/system.slice");
+        assert_eq!(
+            paths[6],
+            format!("/system.slice/docker-{}.scope", DOCKER_ID)
+        );
+        assert_eq!(paths[7], "/system.slice/sshd.service");
+        assert_eq!(paths[8], "/user.slice");
+        assert!(walk(&fixtures().join("missing")).is_empty());
+    }
+
+    #[test]
+    fn reads_cgroup_files() {
+        let cgroups = walk(&fixtures());
+        let root = &cgroups[0];
+        assert_eq!(root.usage_usec, 9_000_000_000);
+        assert_eq!((root.memory, root.pids), (None, None));
+
+        let system = &cgroups[5];
+        assert_eq!(system.memory, Some(2_147_483_648));
+        assert_eq!(system.memory_max, None);
+        assert_eq!(
+            (system.read_bytes, system.write_bytes),
+            (1_052_672, 2_097_152)
+        );
+        assert_eq!(system.pids, Some(143));
+
+        let docker = &cgroups[6];
+        assert_eq!(docker.memory_max, Some(1_073_741_824));
+        assert_eq!(
+            (docker.nr_throttled, docker.throttled_usec),
+            (120, 3_400_000)
+        );
+    }
+
+    #[test]
+    fn classifies_by_name() {
+        let docker = format!("/system.slice/docker-{}.scope", DOCKER_ID);
+        assert_eq!(
+            classify(&docker),
+            (CgroupKind::Docker, Some(DOCKER_ID.to_string()))
+        );
+        assert_eq!(
+            classify(&format!("/machine.slice/libpod-{}.scope", DOCKER_ID)).0,
+            CgroupKind::Podman
+        );
+        assert_eq!(
+            classify(&format!("/machine.slice/libpod-conmon-{}.scope", DOCKER_ID)).0,
+            CgroupKind::Scope
+        );
+        assert_eq!(
+            classify(&format!("/docker/{}", DOCKER_ID)).0,
+            CgroupKind::Docker
+        );
+        let cgroups = walk(&fixtures());
+        assert_eq!(classify(&cgroups[3].path).0, CgroupKind::Pod);
+        assert_eq!(classify(&cgroups[4].path).0, CgroupKind::Containerd);
+        assert_eq!(classify("/kubepods/burstable/pod1b2c").0, CgroupKind::Pod);
+        assert_eq!(
+            classify("/system.slice/sshd.service").0,
+            CgroupKind::Service
+        );
+        assert_eq!(classify("/user.slice").0, CgroupKind::Slice);
+        assert_eq!(classify("/").0, CgroupKind::Other);
+        assert_eq!(
+            container_id(&format!("{}/init", docker)).as_deref(),
+            Some(DOCKER_ID)
+        );
+        assert_eq!(container_id("/user.slice/user-1000.slice"), None);
+    }
+
+    #[test]
+    fn rates_since_previous_sample() {
+        let before = CgroupStats {
+            path: "/a/b.scope".to_string(),
+            usage_usec: 1_000_000,
+            nr_throttled: 10,
+            throttled_usec: 100_000,
+            read_bytes: 4096,
+            ..CgroupStats::default()
+        };
+        let after = CgroupStats {
+            usage_usec: 1_500_000,
+            nr_throttled: 14,
+            throttled_usec: 350_000,
+            read_bytes: 4096 + 2048,
+            ..before.clone()
+        };
+        let first = sample(&before, None, 0.5);
+        assert_eq!(first.cpu_usage, 0.0);
+        assert_eq!((first.depth(), first.name()), (2, "b.scope"));
+
+        let s = sample(&after, Some(&before), 0.5);
+        assert_eq!(s.cpu_usage, 100.0);
+        assert_eq!(s.throttled, 50.0);
+        assert_eq!(s.throttled_periods, 4);
+        assert_eq!(s.read_rate, 4096);
+        assert_eq!(s.kind, CgroupKind::Scope);
+    }
+}
@@ -1,145 +1,550 @@
-use crate::app::{AppState, CpuHistory, NetworkHistory, ProcessInfo, SortColumn};
-use std::collections::VecDeque;
-
-pub fn refresh(app: &mut AppState) {
-    app.system.refresh_all();
-    app.networks.refresh_list();
-    app.networks.refresh();
-
-    // CPU
-    let cpus = app.system.cpus();
-    if app.cpu_history.is_empty() {
-        for cpu in cpus {
-            let mut history = VecDeque::with_capacity(60);
-            for _ in 0..60 {
-                history.push_back(0);
-            }
-            app.cpu_history.push(CpuHistory {
-                core_name: cpu.name().to_string(),
-                usage: 0.0,
-                history,
-            });
+use crate::app::ProcessInfo;
+use crate::system::cgroups::{self, CgroupSample, CgroupStats};
+use crate::system::cpuinfo::{self, Topology};
+use crate::system::engine::{self, Engine};
+use crate::system::gpu;
+use crate::system::meminfo;
+use crate::system::power;
+use crate::system::procfs;
+use crate::system::psi::{self, CgroupPressure};
+use crate::system::services::{self, ServiceUnit};
+use crate::system::snapshot::{
+    CpuSample, DiskSample, MemorySample, NetworkSample, Snapshot, SystemSample, TaskCounts,
+};
+use crate::system::swap::{self, SwapSample, Zswap};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use sysinfo::{Disks, Networks, System, Users};
+
+/// How often systemd is asked for its services while they are shown.
+const SERVICES_REFRESH: Duration = Duration::from_secs(5);
+
+/// Owns the sysinfo handles and turns each refresh into a `Snapshot`.
+pub struct Collector {
+    system: System,
+    networks: Networks,
+    disks: Disks,
+    users: Users,
+    interval: Duration,
+    show_loopback: bool,
+    /// Host, OS, kernel, CPU model and boot time, which do not change
+    /// while running.
+    identity: SystemSample,
+    /// Of each logical CPU, read once since it does not change either.
+    topology: Vec<Topology>,
+    /// Thermal throttle count of each CPU as of the previous sample.
+    throttle_counts: Vec<Option<u64>>,
+    /// Where the cgroup v2 hierarchy is mounted.
+    cgroup_root: PathBuf,
+    /// Where batteries and AC adapters are listed.
+    power_supply_root: PathBuf,
+    /// Cgroup whose pressure to read along with the system's.
+    pressure_cgroup: Option<String>,
+    /// Whether to walk every cgroup, which only the Containers view needs.
+    walk_cgroups: bool,
+    /// Docker or Podman, for naming containers.
+    engine: Option<Engine>,
+    /// Whether to list systemd services, which only the Services view needs.
+    list_services: bool,
+    /// Services as last listed, which is only done every few seconds.
+    services: Option<(Vec<ServiceUnit>, Instant)>,
+    /// The listing in flight, if any. `systemctl` can be slow, so it runs
+    /// on a background thread and samples show the last list meanwhile.
+    services_pending: Option<Receiver<Result<Vec<ServiceUnit>, String>>>,
+    /// Why listing services last failed, and whether that was passed on.
+    services_error: Option<(String, bool)>,
+    /// Counters of each cgroup by path, as of the previous walk.
+    cgroup_stats: Option<(HashMap<String, CgroupStats>, Instant)>,
+    /// Processes to read `smaps_rollup` for; `None` means all of them.
+    smaps_pids: Option<HashSet<u32>>,
+    /// Pages swapped in and out since boot, as of the previous sample.
+    swap_counters: Option<((u64, u64), Instant)>,
+    /// CPU time of each process's main thread as of the previous sample,
+    /// which sysinfo does not list as a thread.
+    main_thread_times: HashMap<u32, f64>,
+}
+
+impl Collector {
+    pub fn new(interval: Duration, show_loopback: bool) -> Self {
+        let mut system = System::new_all();
+        system.refresh_all();
+        let sys_cpu = Path::new(cpuinfo::SYS_CPU);
+        let topology = (0..system.cpus().len())
+            .map(|i| cpuinfo::read_topology(&cpuinfo::cpu_dir(sys_cpu, i)))
+            .collect();
+        let first_cpu = system.cpus().first();
+        let identity = SystemSample {
+            host: System::host_name().unwrap_or_else(|| "unknown".to_string()),
+            os: System::long_os_version()
+                .or_else(System::name)
+                .unwrap_or_default(),
+            kernel: System::kernel_version().unwrap_or_default(),
+            cpu_brand: first_cpu
+                .map(|c| c.brand().trim().to_string())
+                .unwrap_or_default(),
+            cpu_vendor: first_cpu
+                .map(|c| c.vendor_id().to_string())
+                .unwrap_or_default(),
+            boot_time: System::boot_time(),
+            ..SystemSample::default()
+        };
+
+        Self {
+            system,
+            networks: Networks::new_with_refreshed_list(),
+            disks: Disks::new_with_refreshed_list(),
+            users: Users::new_with_refreshed_list(),
+            interval,
+            show_loopback,
+            identity,
+            topology,
+            throttle_counts: Vec::new(),
+            cgroup_root: psi::cgroup_root(),
+            power_supply_root: PathBuf::from(power::SYS_POWER_SUPPLY),
+            pressure_cgroup: None,
+            walk_cgroups: false,
+            engine: engine::find_socket(None).map(Engine::new),
+            list_services: false,
+            services: None,
+            services_pending: None,
+            services_error: None,
+            cgroup_stats: None,
+            smaps_pids: Some(HashSet::new()),
+            swap_counters: None,
+            main_thread_times: HashMap::new(),
         }
     }
 
-    let mut total_cpu = 0.0;
-    for (i, cpu) in app.system.cpus().iter().enumerate() {
-        if let Some(history) = app.cpu_history.get_mut(i) {
-            history.usage = cpu.cpu_usage();
-            total_cpu += history.usage;
-            if history.history.len() >= 60 {
-                history.history.pop_front();
-            }
-            history.history.push_back(history.usage as u64);
+    pub fn set_cgroup_root(&mut self, root: PathBuf) {
+        self.cgroup_root = root;
+    }
+
+    pub fn set_power_supply_root(&mut self, root: PathBuf) {
+        self.power_supply_root = root;
+    }
+
+    /// Replaces the detected container engine socket; `None` turns the
+    /// engine off.
+    pub fn set_engine_socket(&mut self, socket: Option<PathBuf>) {
+        self.engine = socket.map(Engine::new);
+    }
+
+    /// Why container names could not be fetched, once per distinct error.
+    pub fn take_engine_error(&mut self) -> Option<String> {
+        self.engine.as_mut()?.take_error()
+    }
+
+    pub fn set_list_services(&mut self, list: bool) {
+        self.list_services = list;
+        if !list {
+            self.services = None;
+            self.services_pending = None;
         }
     }
-    app.overall_cpu = if app.system.cpus().is_empty() {
-        0.0
-    } else {
-        total_cpu / app.system.cpus().len() as f32
-    };
-
-    // GPU
-    if let Some(new_gpus) = crate::system::gpu::get_gpu_info() {
-        if let Some(ref mut existing_gpus) = app.gpus {
-            for (i, new_gpu) in new_gpus.into_iter().enumerate() {
-                if let Some(existing) = existing_gpus.get_mut(i) {
-                    existing.usage = new_gpu.usage;
-                    existing.mem_used_mb = new_gpu.mem_used_mb;
-                    existing.mem_total_mb = new_gpu.mem_total_mb;
-                    existing.temp_c = new_gpu.temp_c;
-
-                    if existing.history.len() >= 60 {
-                        existing.history.pop_front();
-                    }
-                    existing.history.push_back(existing.usage as u64);
-                } else {
-                    existing_gpus.push(new_gpu);
-                }
+
+    /// Why services could not be listed, once per distinct error.
+    pub fn take_services_error(&mut self) -> Option<String> {
+        match &mut self.services_error {
+            Some((err, reported)) if !*reported => {
+                *reported = true;
+                Some(err.clone())
             }
-        } else {
-            app.gpus = Some(new_gpus);
+            _ => None,
         }
     }
 
-    // Network
-    for (name, network) in &app.networks {
-        if !app.show_loopback && name.starts_with("lo") {
-            continue;
+    pub fn set_walk_cgroups(&mut self, walk: bool) {
+        self.walk_cgroups = walk;
+        if !walk {
+            self.cgroup_stats = None;
         }
+    }
 
-        let rx_delta = network.received();
-        let tx_delta = network.transmitted();
+    pub fn set_pressure_cgroup(&mut self, cgroup: Option<String>) {
+        self.pressure_cgroup = cgroup;
+    }
 
-        if let Some(net_hist) = app
-            .network_history
-            .iter_mut()
-            .find(|n| n.interface_name == *name)
-        {
-            net_hist.rx_bytes = rx_delta;
-            net_hist.tx_bytes = tx_delta;
-            if net_hist.rx_history.len() >= 60 {
-                net_hist.rx_history.pop_front();
-            }
-            if net_hist.tx_history.len() >= 60 {
-                net_hist.tx_history.pop_front();
-            }
-            net_hist.rx_history.push_back(rx_delta);
-            net_hist.tx_history.push_back(tx_delta);
+    pub fn set_smaps_pids(&mut self, pids: Option<HashSet<u32>>) {
+        self.smaps_pids = pids;
+    }
+
+    pub fn collect(&mut self) -> Snapshot {
+        self.system.refresh_all();
+        self.networks.refresh_list();
+        self.networks.refresh();
+        self.disks.refresh_list();
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+
+        let cpus = self.collect_cpus();
+        let overall_cpu = if cpus.is_empty() {
+            0.0
         } else {
-            let mut rx_history = VecDeque::with_capacity(60);
-            let mut tx_history = VecDeque::with_capacity(60);
-            for _ in 0..60 {
-                rx_history.push_back(0);
-                tx_history.push_back(0);
-            }
-            app.network_history.push(NetworkHistory {
-                interface_name: name.to_string(),
-                rx_bytes: rx_delta,
-                tx_bytes: tx_delta,
-                rx_history,
-                tx_history,
-            });
+            cpus.iter().map(|c| c.usage).sum::<f32>() / cpus.len() as f32
+        };
+
+        let memory = MemorySample {
+            total: self.system.total_memory(),
+            used: self.system.used_memory(),
+            available: self.system.available_memory(),
+            total_swap: self.system.total_swap(),
+            used_swap: self.system.used_swap(),
+            free_swap: self.system.free_swap(),
+            details: meminfo::read(),
+        };
+
+        let swap = self.collect_swap(&memory);
+
+        // GPU
+        let gpus = gpu::get_gpu_info().unwrap_or_default();
+
+        // Network
+        let mut networks: Vec<NetworkSample> = self
+            .networks
+            .iter()
+            .filter(|(name, _)| self.show_loopback || !name.starts_with("lo"))
+            .map(|(name, network)| NetworkSample {
+                name: name.to_string(),
+                rx_bytes: network.received(),
+                tx_bytes: network.transmitted(),
+            })
+            .collect();
+        networks.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let disks = self
+            .disks
+            .iter()
+            .map(|disk| DiskSample {
+                mount: disk.mount_point().display().to_string(),
+                total: disk.total_space(),
+                available: disk.available_space(),
+            })
+            .collect();
+
+        let power = power::read(&self.power_supply_root);
+        let pressure = psi::read_system();
+        let cgroup_pressure = self.pressure_cgroup.as_ref().and_then(|path| {
+            Some(CgroupPressure {
+                pressure: psi::read_cgroup(&self.cgroup_root, path)?,
+                path: path.clone(),
+            })
+        });
+
+        let mut cgroups = self.collect_cgroups();
+        let mut processes = self.collect_processes();
+        self.label_containers(&mut processes, &mut cgroups);
+        let services = self.collect_services();
+        let load = System::load_average();
+        let system = SystemSample {
+            uptime: System::uptime(),
+            load: [load.one, load.five, load.fifteen],
+            tasks: TaskCounts::count(&processes),
+            ..self.identity.clone()
+        };
+
+        Snapshot {
+            seq: 0,
+            timestamp,
+            system,
+            overall_cpu,
+            cpus,
+            memory,
+            swap,
+            networks,
+            gpus,
+            disks,
+            power,
+            pressure,
+            cgroup_pressure,
+            cgroups,
+            services,
+            alerts: Vec::new(),
+            processes,
         }
     }
 
-    // Processes
-    app.processes.clear();
-    for (pid, process) in app.system.processes() {
-        app.processes.push(ProcessInfo {
-            pid: pid.as_u32(),
-            name: process.name().to_string(), // use name() as fallback if available, it's string slice
-            cpu_usage: process.cpu_usage(),
-            memory: process.memory(),
-            status: format!("{:?}", process.status()),
+    fn collect_cpus(&mut self) -> Vec<CpuSample> {
+        let sys_cpu = Path::new(cpuinfo::SYS_CPU);
+        let mut throttle_counts = Vec::new();
+        let cpus = self
+            .system
+            .cpus()
+            .iter()
+            .enumerate()
+            .map(|(i, cpu)| {
+                let dir = cpuinfo::cpu_dir(sys_cpu, i);
+                let policy = cpuinfo::read_policy(&dir);
+                let throttle_count = cpuinfo::read_throttle_count(&dir);
+                let previous = self.throttle_counts.get(i).copied().flatten();
+                throttle_counts.push(throttle_count);
+                let topology = self.topology.get(i).copied().unwrap_or_default();
+                CpuSample {
+                    name: cpu.name().to_string(),
+                    usage: cpu.cpu_usage(),
+                    frequency: cpu.frequency(),
+                    governor: policy.as_ref().map(|p| p.governor.clone()),
+                    min_frequency: policy.as_ref().map(|p| p.min_mhz),
+                    max_frequency: policy.as_ref().map(|p| p.max_mhz),
+                    throttled: matches!((previous, throttle_count), (Some(a), Some(b)) if b > a),
+                    socket: topology.socket,
+                    core: topology.core,
+                    node: topology.node,
+                }
+            })
+            .collect();
+        self.throttle_counts = throttle_counts;
+        cpus
+    }
+
+    fn collect_swap(&mut self, memory: &MemorySample) -> SwapSample {
+        let zswap = swap::read_zswap_enabled(Path::new(swap::ZSWAP_ENABLED)).map(|enabled| {
+            let details = memory.details.as_ref();
+            Zswap {
+                enabled,
+                pool: details.map_or(0, |d| d.zswap),
+                stored: details.map_or(0, |d| d.zswapped),
+            }
         });
+
+        let mut sample = SwapSample {
+            devices: swap::read_swaps(Path::new(swap::PROC_SWAPS)),
+            zram: swap::read_zram(Path::new(swap::SYS_BLOCK)),
+            zswap,
+            ..SwapSample::default()
+        };
+        let now = Instant::now();
+        let counters = swap::read_vmstat(Path::new(swap::PROC_VMSTAT));
+        if let (Some((pages_in, pages_out)), Some(((last_in, last_out), at))) =
+            (counters, self.swap_counters)
+        {
+            let secs = now.duration_since(at).as_secs_f64().max(0.001);
+            sample.pages_in = pages_in.saturating_sub(last_in) as f64 / secs;
+            sample.pages_out = pages_out.saturating_sub(last_out) as f64 / secs;
+        }
+        self.swap_counters = counters.map(|c| (c, now));
+        sample
     }
 
-    // Apply search filter
-    if !app.search_query.is_empty() {
-        let query = app.search_query.to_lowercase();
-        app.processes
-            .retain(|p| p.name.to_lowercase().contains(&query));
+    fn collect_cgroups(&mut self) -> Vec<CgroupSample> {
+        if !self.walk_cgroups {
+            return Vec::new();
+        }
+        let now = Instant::now();
+        let stats = cgroups::walk(&self.cgroup_root);
+        let samples = match &self.cgroup_stats {
+            Some((previous, at)) => {
+                let secs = now.duration_since(*at).as_secs_f64();
+                stats
+                    .iter()
+                    .map(|s| cgroups::sample(s, previous.get(&s.path), secs))
+                    .collect()
+            }
+            None => stats
+                .iter()
+                .map(|s| cgroups::sample(s, None, 0.0))
+                .collect(),
+        };
+        let by_path = stats.into_iter().map(|s| (s.path.clone(), s)).collect();
+        self.cgroup_stats = Some((by_path, now));
+        samples
     }
 
-    // Sort
-    match app.sort_column {
-        SortColumn::Pid => app.processes.sort_by(|a, b| a.pid.cmp(&b.pid)),
-        SortColumn::Name => app.processes.sort_by(|a, b| a.name.cmp(&b.name)),
-        SortColumn::Cpu => app.processes.sort_by(|a, b| {
-            a.cpu_usage
-                .partial_cmp(&b.cpu_usage)
-                .unwrap_or(std::cmp::Ordering::Equal)
-        }),
-        SortColumn::Memory => app.processes.sort_by(|a, b| a.memory.cmp(&b.memory)),
+    /// Services as last listed, starting a new listing when that is stale.
+    fn collect_services(&mut self) -> Vec<ServiceUnit> {
+        if !self.list_services {
+            return Vec::new();
+        }
+        self.receive_services();
+        let stale = self
+            .services
+            .as_ref()
+            .is_none_or(|(_, at)| at.elapsed() >= SERVICES_REFRESH);
+        if stale && self.services_pending.is_none() {
+            let (sender, receiver) = mpsc::channel();
+            thread::spawn(move || sender.send(services::list_units()));
+            self.services_pending = Some(receiver);
+        }
+        self.services
+            .as_ref()
+            .map(|(units, _)| units.clone())
+            .unwrap_or_default()
     }
 
-    if !app.sort_ascending {
-        app.processes.reverse();
+    fn receive_services(&mut self) {
+        let Some(pending) = &self.services_pending else {
+            return;
+        };
+        let result = match pending.try_recv() {
+            Ok(result) => result,
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => Err("service listing thread died".to_string()),
+        };
+        self.services_pending = None;
+        let units = match result {
+            Ok(units) => {
+                self.services_error = None;
+                units
+            }
+            Err(err) => {
+                if self
+                    .services_error
+                    .as_ref()
+                    .is_none_or(|(last, _)| *last != err)
+                {
+                    self.services_error = Some((err, false));
+                }
+                Vec::new()
+            }
+        };
+        self.services = Some((units, Instant::now()));
     }
 
-    // Truncate
-    if app.processes.len() > app.max_processes {
-        app.processes.truncate(app.max_processes);
+    /// Names the container each process and cgroup belongs to, asking the
+    /// engine for names when there are containers at all.
+    fn label_containers(&mut self, processes: &mut [ProcessInfo], cgroups: &mut [CgroupSample]) {
+        let ids: Vec<Option<String>> = processes
+            .iter()
+            .map(|p| cgroups::container_id(&p.cgroup))
+            .collect();
+        let mut all_ids = ids
+            .iter()
+            .flatten()
+            .chain(cgroups.iter().filter_map(|c| c.container_id.as_ref()))
+            .map(String::as_str)
+            .peekable();
+        let engine = match &mut self.engine {
+            Some(engine) if all_ids.peek().is_some() => {
+                engine.update(all_ids);
+                Some(&*engine)
+            }
+            _ => None,
+        };
+        let lookup = |id: &str| engine.and_then(|e| e.get(id));
+
+        for (p, id) in processes.iter_mut().zip(ids) {
+            if let Some(id) = id {
+                p.container = match lookup(&id) {
+                    Some(info) => info.name.clone(),
+                    None => id[..12].to_string(),
+                };
+            }
+        }
+        for c in cgroups {
+            c.container = c.container_id.as_deref().and_then(lookup).cloned();
+        }
+    }
+
+    fn collect_processes(&mut self) -> Vec<ProcessInfo> {
+        let gpu_memory = gpu::get_process_gpu_memory();
+        let secs = self.interval.as_secs_f64().max(0.001);
+
+        // sysinfo lists threads alongside processes; map each back to its owner.
+        let mut thread_owner = HashMap::new();
+        for (pid, process) in self.system.processes() {
+            for task in process.tasks().into_iter().flatten() {
+                thread_owner.insert(*task, pid.as_u32());
+            }
+        }
+
+        let mut processes = Vec::new();
+        let mut main_thread_times = HashMap::new();
+        for (pid, process) in self.system.processes() {
+            let user = process
+                .user_id()
+                .and_then(|uid| self.users.get_user_by_id(uid))
+                .map(|u| u.name().to_string())
+                .unwrap_or_default();
+            let stat = procfs::read_stat(pid.as_u32());
+            let cgroup = procfs::read_cgroup(pid.as_u32()).unwrap_or_default();
+            let disk = process.disk_usage();
+            let thread_of = thread_owner.get(pid).copied();
+            let wants_smaps = self
+                .smaps_pids
+                .as_ref()
+                .is_none_or(|pids| pids.contains(&pid.as_u32()));
+            let smaps = if wants_smaps && thread_of.is_none() {
+                procfs::read_smaps(pid.as_u32())
+            } else {
+                None
+            };
+
+            // Threads share their process's memory, which is not repeated on them.
+            let (memory, virtual_memory) = match thread_of {
+                Some(_) => (0, 0),
+                None => (process.memory(), process.virtual_memory()),
+            };
+            let info = ProcessInfo {
+                pid: pid.as_u32(),
+                ppid: process.parent().map(|p| p.as_u32()),
+                name: process.name().to_string(), // use name() as fallback if available, it's string slice
+                cmd: process.cmd().join(" "),
+                exe: process
+                    .exe()
+                    .map(|e| e.display().to_string())
+                    .unwrap_or_default(),
+                user,
+                cpu_usage: process.cpu_usage(),
+                memory,
+                virtual_memory,
+                pss: smaps.map(|s| s.pss),
+                uss: smaps.map(|s| s.uss),
+                swap: smaps.map(|s| s.swap),
+                // `tasks` leaves out the main thread.
+                threads: process.tasks().map(|t| t.len() + 1),
+                status: format!("{:?}", process.status()),
+                nice: stat.as_ref().map(|s| s.nice),
+                priority: stat.as_ref().map(|s| s.priority),
+                start_time: process.start_time(),
+                cpu_time: stat.as_ref().map(|s| s.cpu_time),
+                read_rate: (disk.read_bytes as f64 / secs) as u64,
+                write_rate: (disk.written_bytes as f64 / secs) as u64,
+                gpu_memory: gpu_memory.get(&pid.as_u32()).copied(),
+                cgroup,
+                container: String::new(),
+                thread_of,
+            };
+
+            // `tasks` leaves out the main thread, so it is read separately.
+            let threaded = process.tasks().is_some_and(|t| !t.is_empty());
+            let main_thread = match threaded && thread_of.is_none() {
+                true => procfs::read_thread_stat(pid.as_u32(), pid.as_u32()),
+                false => None,
+            };
+            if let Some(main) = main_thread {
+                let previous = self.main_thread_times.get(&pid.as_u32());
+                let cpu_usage = previous.map_or(0.0, |prev| {
+                    ((main.cpu_time - prev).max(0.0) / secs * 100.0) as f32
+                });
+                main_thread_times.insert(pid.as_u32(), main.cpu_time);
+                processes.push(ProcessInfo {
+                    cpu_usage,
+                    memory: 0,
+                    virtual_memory: 0,
+                    pss: None,
+                    uss: None,
+                    swap: None,
+                    threads: None,
+                    status: main.status().to_string(),
+                    nice: Some(main.nice),
+                    priority: Some(main.priority),
+                    cpu_time: Some(main.cpu_time),
+                    read_rate: 0,
+                    write_rate: 0,
+                    gpu_memory: None,
+                    thread_of: Some(pid.as_u32()),
+                    ..info.clone()
+                });
+            }
+            processes.push(info);
+        }
+        self.main_thread_times = main_thread_times;
+
+        processes
     }
 }
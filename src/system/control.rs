@@ -0,0 +1,213 @@
+//! Actions that change a running process: nice value and CPU affinity.
+
+use std::io;
+
+/// Sets the nice value of `pid` (a thread ID also works on Linux).
+#[cfg(unix)]
+pub fn renice(pid: u32, nice: i32) -> io::Result<()> {
+    // SAFETY: setpriority only reads its integer arguments.
+    let ret = unsafe { libc::setpriority(libc::PRIO_PROCESS, pid as libc::id_t, nice) };
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(unix))]
+pub fn renice(_pid: u32, _nice: i32) -> io::Result<()> {
+    Err(unsupported())
+}
+
+/// Which of the first `cpu_count` CPUs `pid` may run on.
+#[cfg(target_os = "linux")]
+pub fn get_affinity(pid: u32, cpu_count: usize) -> io::Result<Vec<bool>> {
+    // SAFETY: cpu_set_t is plain data and zeroed is a valid empty set.
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    // SAFETY: `set` is a valid cpu_set_t of the size passed.
+    let ret = unsafe {
+        libc::sched_getaffinity(
+            pid as libc::pid_t,
+            std::mem::size_of::<libc::cpu_set_t>(),
+            &mut set,
+        )
+    };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: CPU_ISSET reads within the set for any index below CPU_SETSIZE.
+    Ok((0..cpu_count)
+        .map(|cpu| cpu < libc::CPU_SETSIZE as usize && unsafe { libc::CPU_ISSET(cpu, &set) })
+        .collect())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn get_affinity(_pid: u32, _cpu_count: usize) -> io::Result<Vec<bool>> {
+    Err(unsupported())
+}
+
+/// Restricts `pid` to the CPUs whose flag is set.
+#[cfg(target_os = "linux")]
+pub fn set_affinity(pid: u32, cpus: &[bool]) -> io::Result<()> {
+    // SAFETY: cpu_set_t is plain data and zeroed is a valid empty set.
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    for (cpu, _) in cpus.iter().enumerate().filter(|(_, on)| **on) {
+        if cpu < libc::CPU_SETSIZE as usize {
+            // SAFETY: `cpu` is below CPU_SETSIZE.
+            unsafe { libc::CPU_SET(cpu, &mut set) };
+        }
+    }
+    // SAFETY: `set` is a valid cpu_set_t of the size passed.
+    let ret = unsafe {
+        libc::sched_setaffinity(
+            pid as libc::pid_t,
+            std::mem::size_of::<libc::cpu_set_t>(),
+            &set,
+        )
+    };
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn set_affinity(_pid: u32, _cpus: &[bool]) -> io::Result<()> {
+    Err(unsupported())
+}
+
+/// How many threads of a process an action reached.
+#[derive(Debug, PartialEq)]
+pub struct Applied {
+    pub changed: usize,
+    pub failed: usize,
+}
+
+impl Applied {
+    /// `" (2 of 8 threads failed)"`, or nothing when every thread changed.
+    pub fn failures(&self) -> String {
+        match self.failed {
+            0 => String::new(),
+            failed => format!(" ({} of {} threads failed)", failed, failed + self.changed),
+        }
+    }
+}
+
+/// Applies `action` to every thread of `pid`, as `taskset -a` does, since
+/// nice values and affinity are per thread on Linux. Threads that exit
+/// meanwhile are skipped. Fails only when no thread was changed.
+#[cfg(target_os = "linux")]
+pub fn for_each_thread(
+    pid: u32,
+    mut action: impl FnMut(u32) -> io::Result<()>,
+) -> io::Result<Applied> {
+    let mut tids: Vec<u32> = match std::fs::read_dir(format!("/proc/{}/task", pid)) {
+        Ok(entries) => entries
+            .flatten()
+            .filter_map(|entry| entry.file_name().to_str()?.parse().ok())
+            .collect(),
+        // Let the action report a vanished process.
+        Err(_) => vec![pid],
+    };
+    tids.sort_unstable();
+
+    let mut applied = Applied {
+        changed: 0,
+        failed: 0,
+    };
+    let mut first_error = None;
+    for tid in tids {
+        match action(tid) {
+            Ok(()) => applied.changed += 1,
+            Err(err) if tid != pid && err.raw_os_error() == Some(libc::ESRCH) => {}
+            Err(err) => {
+                applied.failed += 1;
+                first_error.get_or_insert(err);
+            }
+        }
+    }
+    match first_error {
+        Some(err) if applied.changed == 0 => Err(err),
+        _ => Ok(applied),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn for_each_thread(
+    pid: u32,
+    mut action: impl FnMut(u32) -> io::Result<()>,
+) -> io::Result<Applied> {
+    action(pid).map(|()| Applied {
+        changed: 1,
+        failed: 0,
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn unsupported() -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, "not supported on this platform")
+}
+
+/// Turns an OS error into a footer message, explaining permission failures.
+pub fn describe_error(action: &str, pid: u32, err: &io::Error) -> String {
+    #[cfg(unix)]
+    if err.raw_os_error() == Some(libc::ESRCH) {
+        return format!("{} {}: process no longer exists", action, pid);
+    }
+    if err.kind() == io::ErrorKind::PermissionDenied {
+        return format!(
+            "{} {}: permission denied (other users' processes and lowering nice need root or CAP_SYS_NICE)",
+            action, pid
+        );
+    }
+    format!("{} {}: {}", action, pid, err)
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn reaches_every_thread_and_counts_failures() {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let worker = std::thread::spawn(move || stopped.recv());
+        let pid = std::process::id();
+
+        let mut seen = Vec::new();
+        let applied = for_each_thread(pid, |tid| {
+            seen.push(tid);
+            Ok(())
+        })
+        .unwrap();
+        assert!(seen.contains(&pid));
+        assert!(applied.changed >= 2);
+        assert_eq!(applied.failures(), "");
+
+        // Only the main thread changes: the rest are reported. Other tests
+        // start and stop threads, so count this pass's own calls.
+        let denied = || io::Error::from(io::ErrorKind::PermissionDenied);
+        let mut calls = 0;
+        let applied = for_each_thread(pid, |tid| {
+            calls += 1;
+            match tid == pid {
+                true => Ok(()),
+                false => Err(denied()),
+            }
+        })
+        .unwrap();
+        assert!(calls >= 2);
+        assert_eq!(applied.changed, 1);
+        assert_eq!(
+            applied.failures(),
+            format!(" ({} of {} threads failed)", calls - 1, calls)
+        );
+
+        let err = for_each_thread(pid, |_| Err(denied())).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+
+        stop.send(()).unwrap();
+        worker.join().unwrap().unwrap();
+    }
+}
@@ -0,0 +1,97 @@
+//! Per-CPU topology, frequency scaling policy and throttle counts from
+//! `/sys/devices/system/cpu/cpu<N>`.
+
+use std::path::{Path, PathBuf};
+
+pub const SYS_CPU: &str = "/sys/devices/system/cpu";
+
+/// Where a logical CPU sits. Fields are `None` where sysfs does not say.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct Topology {
+    /// Physical package (socket).
+    pub socket: Option<u32>,
+    /// Physical core within the socket; SMT siblings share it.
+    pub core: Option<u32>,
+    pub node: Option<u32>,
+}
+
+/// The cpufreq scaling policy, frequencies in MHz.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct FreqPolicy {
+    pub governor: String,
+    pub min_mhz: u64,
+    pub max_mhz: u64,
+}
+
+pub fn cpu_dir(root: &Path, index: usize) -> PathBuf {
+    root.join(format!("cpu{}", index))
+}
+
+fn read_number(path: &Path) -> Option<u64> {
+    std::fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+pub fn read_topology(cpu_dir: &Path) -> Topology {
+    let topology = cpu_dir.join("topology");
+    // The CPU's NUMA node shows up as a `node<N>` entry in its directory.
+    let node = std::fs::read_dir(cpu_dir).ok().and_then(|entries| {
+        entries.flatten().find_map(|entry| {
+            let name = entry.file_name();
+            name.to_str()?.strip_prefix("node")?.parse().ok()
+        })
+    });
+    Topology {
+        socket: read_number(&topology.join("physical_package_id")).map(|v| v as u32),
+        core: read_number(&topology.join("core_id")).map(|v| v as u32),
+        node,
+    }
+}
+
+/// `None` without cpufreq, as in most virtual machines.
+pub fn read_policy(cpu_dir: &Path) -> Option<FreqPolicy> {
+    let cpufreq = cpu_dir.join("cpufreq");
+    let governor = std::fs::read_to_string(cpufreq.join("scaling_governor")).ok()?;
+    Some(FreqPolicy {
+        governor: governor.trim().to_string(),
+        // Exposed in kHz.
+        min_mhz: read_number(&cpufreq.join("scaling_min_freq"))? / 1000,
+        max_mhz: read_number(&cpufreq.join("scaling_max_freq"))? / 1000,
+    })
+}
+
+/// How often the core has been thermally throttled since boot (Intel only).
+pub fn read_throttle_count(cpu_dir: &Path) -> Option<u64> {
+    read_number(&cpu_dir.join("thermal_throttle/core_throttle_count"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixtures() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/cpu")
+    }
+
+    #[test]
+    fn reads_topology() {
+        let root = fixtures();
+        let topology: Vec<Topology> = (0..4).map(|i| read_topology(&cpu_dir(&root, i))).collect();
+        // cpu0 and cpu1 are SMT siblings.
+        assert_eq!(topology[0], topology[1]);
+        assert_eq!(topology[2].core, Some(1));
+        assert_eq!(topology[3].socket, Some(1));
+        assert_eq!(topology[3].node, Some(1));
+        assert_eq!(read_topology(&cpu_dir(&root, 9)), Topology::default());
+    }
+
+    #[test]
+    fn reads_policy_and_throttling() {
+        let root = fixtures();
+        let policy = read_policy(&cpu_dir(&root, 2)).unwrap();
+        assert_eq!(policy.governor, "performance");
+        assert_eq!((policy.min_mhz, policy.max_mhz), (400, 4900));
+        assert_eq!(read_policy(&cpu_dir(&root, 3)), None);
+        assert_eq!(read_throttle_count(&cpu_dir(&root, 0)), Some(12));
+        assert_eq!(read_throttle_count(&cpu_dir(&root, 1)), None);
+    }
+}
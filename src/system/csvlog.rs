@@ -0,0 +1,377 @@
+//! `--log-csv`: one row of system metrics per tick.
+//!
+//! A file's columns never change once its header is written: they are the
+//! cores, interfaces and GPUs seen since it was started. Metrics that
+//! disappear leave empty cells. One that appears rotates the file early, so
+//! the next file's header has a column for it as well as the old ones.
+//! Files are also rotated when they reach a size limit, as `log.csv.1`,
+//! `log.csv.2`, ... with the oldest deleted past `keep`.
+
+use crate::system::snapshot::Snapshot;
+use chrono::TimeZone;
+use color_eyre::{eyre::WrapErr, Result};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// The metrics a file has columns for.
+#[derive(Clone, Default, PartialEq, Debug)]
+struct Columns {
+    cores: usize,
+    /// In the snapshot's order.
+    interfaces: Vec<String>,
+    gpus: usize,
+}
+
+impl Columns {
+    /// Columns for everything in `snapshot`.
+    fn of(snapshot: &Snapshot) -> Columns {
+        Columns {
+            cores: snapshot.cpus.len(),
+            interfaces: snapshot.networks.iter().map(|n| n.name.clone()).collect(),
+            gpus: snapshot.gpus.len(),
+        }
+    }
+
+    /// Adds columns for whatever in `snapshot` has none yet, keeping the
+    /// existing ones. Returns whether any were added.
+    fn widen(&mut self, snapshot: &Snapshot) -> bool {
+        let mut wider = false;
+        if snapshot.cpus.len() > self.cores {
+            self.cores = snapshot.cpus.len();
+            wider = true;
+        }
+        for net in &snapshot.networks {
+            if !self.interfaces.contains(&net.name) {
+                self.interfaces.push(net.name.clone());
+                wider = true;
+            }
+        }
+        if snapshot.gpus.len() > self.gpus {
+            self.gpus = snapshot.gpus.len();
+            wider = true;
+        }
+        wider
+    }
+
+    fn header(&self) -> String {
+        let mut cols = vec![
+            "timestamp".to_string(),
+            "unix_ms".to_string(),
+            "cpu_pct".to_string(),
+        ];
+        cols.extend((0..self.cores).map(|i| format!("cpu{}_pct", i)));
+        cols.extend(
+            [
+                "mem_used_bytes",
+                "mem_total_bytes",
+                "swap_used_bytes",
+                "alerts_firing",
+            ]
+            .map(String::from),
+        );
+        for name in &self.interfaces {
+            cols.push(format!("net_{}_rx_bps", name));
+            cols.push(format!("net_{}_tx_bps", name));
+        }
+        for i in 0..self.gpus {
+            cols.push(format!("gpu{}_util_pct", i));
+            cols.push(format!("gpu{}_vram_mb", i));
+            cols.push(format!("gpu{}_temp_c", i));
+        }
+        cols.iter().map(|c| quote(c)).collect::<Vec<_>>().join(",")
+    }
+
+    /// Recovers the columns of a header this module wrote.
+    fn parse(header: &str) -> Option<Columns> {
+        let mut columns = Columns::default();
+        for col in split_fields(header) {
+            let col = col.as_str();
+            if let Some(name) = col
+                .strip_prefix("net_")
+                .and_then(|c| c.strip_suffix("_rx_bps"))
+            {
+                columns.interfaces.push(name.to_string());
+            } else if col.starts_with("cpu") && col.ends_with("_pct") && col != "cpu_pct" {
+                columns.cores += 1;
+            } else if col.starts_with("gpu") && col.ends_with("_util_pct") {
+                columns.gpus += 1;
+            }
+        }
+        (columns.header() == header).then_some(columns)
+    }
+
+    fn row(&self, snapshot: &Snapshot, interval: Duration) -> String {
+        let secs = interval.as_secs_f64().max(0.001);
+        let time = chrono::Local
+            .timestamp_millis_opt(snapshot.timestamp as i64)
+            .single()
+            .map(|t| t.format("%Y-%m-%dT%H:%M:%S%.3f%:z").to_string())
+            .unwrap_or_default();
+
+        let mut cells = vec![
+            time,
+            snapshot.timestamp.to_string(),
+            format!("{:.1}", snapshot.overall_cpu),
+        ];
+        cells.extend((0..self.cores).map(|i| {
+            snapshot
+                .cpus
+                .get(i)
+                .map(|c| format!("{:.1}", c.usage))
+                .unwrap_or_default()
+        }));
+        cells.push(snapshot.memory.used.to_string());
+        cells.push(snapshot.memory.total.to_string());
+        cells.push(snapshot.memory.used_swap.to_string());
+        cells.push(snapshot.alerts.len().to_string());
+        for name in &self.interfaces {
+            match snapshot.networks.iter().find(|n| &n.name == name) {
+                Some(net) => {
+                    cells.push(format!("{:.0}", net.rx_bytes as f64 / secs));
+                    cells.push(format!("{:.0}", net.tx_bytes as f64 / secs));
+                }
+                None => cells.extend([String::new(), String::new()]),
+            }
+        }
+        for i in 0..self.gpus {
+            match snapshot.gpus.get(i) {
+                Some(gpu) => {
+                    cells.push(format!("{:.1}", gpu.usage));
+                    cells.push(format!("{:.0}", gpu.mem_used_mb));
+                    cells.push(gpu.temp_c.to_string());
+                }
+                None => cells.extend([String::new(), String::new(), String::new()]),
+            }
+        }
+        cells.join(",")
+    }
+}
+
+pub struct CsvLog {
+    path: PathBuf,
+    interval: Duration,
+    max_size: u64,
+    keep: usize,
+    columns: Columns,
+    /// Open file and its size, once a header has been written or adopted.
+    file: Option<(File, u64)>,
+}
+
+impl CsvLog {
+    /// Prepares to log to `path`. An existing file written by nexmon is
+    /// appended to; anything else there is rotated away on the first row.
+    pub fn open(path: &Path, interval: Duration, max_size: u64, keep: usize) -> Result<Self> {
+        let mut log = Self {
+            path: path.to_path_buf(),
+            interval,
+            max_size: max_size.max(1),
+            keep,
+            columns: Columns::default(),
+            file: None,
+        };
+
+        let header = File::open(path)
+            .ok()
+            .and_then(|f| BufReader::new(f).lines().next())
+            .and_then(|line| line.ok());
+        if let Some(columns) = header.as_deref().and_then(Columns::parse) {
+            let file = OpenOptions::new()
+                .append(true)
+                .open(path)
+                .wrap_err_with(|| format!("opening {}", path.display()))?;
+            let size = file.metadata()?.len();
+            log.columns = columns;
+            log.file = Some((file, size));
+        }
+        Ok(log)
+    }
+
+    pub fn write(&mut self, snapshot: &Snapshot) -> Result<()> {
+        let full = matches!(&self.file, Some((_, size)) if *size >= self.max_size);
+        if self.file.is_none() || full {
+            self.columns = Columns::of(snapshot);
+            self.start_file()?;
+        } else if self.columns.widen(snapshot) {
+            self.start_file()?;
+        }
+
+        let (file, size) = self.file.as_mut().expect("file was just opened");
+        let line = format!("{}\n", self.columns.row(snapshot, self.interval));
+        file.write_all(line.as_bytes())
+            .wrap_err_with(|| format!("writing {}", self.path.display()))?;
+        *size += line.len() as u64;
+        Ok(())
+    }
+
+    /// Rotates any current file away and starts a new one with a header.
+    fn start_file(&mut self) -> Result<()> {
+        self.file = None;
+        if self.path.exists() {
+            self.rotate()?;
+        }
+        let mut file = File::create(&self.path)
+            .wrap_err_with(|| format!("creating {}", self.path.display()))?;
+        let header = format!("{}\n", self.columns.header());
+        file.write_all(header.as_bytes())?;
+        self.file = Some((file, header.len() as u64));
+        Ok(())
+    }
+
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&self) -> Result<()> {
+        if self.keep == 0 {
+            return fs::remove_file(&self.path)
+                .wrap_err_with(|| format!("removing {}", self.path.display()));
+        }
+        let _ = fs::remove_file(self.rotated(self.keep));
+        for n in (1..self.keep).rev() {
+            let from = self.rotated(n);
+            if from.exists() {
+                fs::rename(&from, self.rotated(n + 1))
+                    .wrap_err_with(|| format!("rotating {}", from.display()))?;
+            }
+        }
+        fs::rename(&self.path, self.rotated(1))
+            .wrap_err_with(|| format!("rotating {}", self.path.display()))
+    }
+}
+
+/// `field` as a CSV cell, quoted if it holds a comma, quote or line break.
+fn quote(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// The cells of a CSV line, unquoted.
+fn split_fields(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                fields.last_mut().unwrap().push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            c => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::snapshot::{CpuSample, NetworkSample};
+
+    fn snapshot(interfaces: &[&str]) -> Snapshot {
+        Snapshot {
+            timestamp: 1_700_000_000_000,
+            overall_cpu: 12.5,
+            cpus: vec![CpuSample::default(); 2],
+            networks: interfaces
+                .iter()
+                .map(|name| NetworkSample {
+                    name: name.to_string(),
+                    rx_bytes: 2048,
+                    tx_bytes: 1024,
+                })
+                .collect(),
+            ..Snapshot::default()
+        }
+    }
+
+    fn lines(path: &Path) -> Vec<String> {
+        fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn new_interface_starts_a_wider_file() {
+        let dir = std::env::temp_dir().join(format!("nexmon-csv-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("log.csv");
+        let interval = Duration::from_secs(2);
+
+        let mut log = CsvLog::open(&path, interval, 1 << 20, 2).unwrap();
+        log.write(&snapshot(&["eth0"])).unwrap();
+        log.write(&snapshot(&["eth0", "veth1a2b"])).unwrap();
+        log.write(&snapshot(&["veth1a2b"])).unwrap();
+
+        let first = lines(&log.rotated(1));
+        assert_eq!(first.len(), 2);
+        assert!(first[0].ends_with("alerts_firing,net_eth0_rx_bps,net_eth0_tx_bps"));
+        assert!(first[1].ends_with(",0,1024,512"));
+
+        let current = lines(&path);
+        assert_eq!(current.len(), 3);
+        assert!(current[0].ends_with("net_eth0_tx_bps,net_veth1a2b_rx_bps,net_veth1a2b_tx_bps"));
+        assert!(current[1].ends_with(",0,1024,512,1024,512"));
+        // Gone interfaces leave empty cells and keep their columns.
+        assert!(current[2].ends_with(",0,,,1024,512"));
+
+        // A restart appends to the file it left behind.
+        drop(log);
+        let mut log = CsvLog::open(&path, interval, 1 << 20, 2).unwrap();
+        log.write(&snapshot(&["eth0"])).unwrap();
+        assert_eq!(lines(&path).len(), 4);
+        assert!(!log.rotated(2).exists());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn columns_follow_the_snapshot_when_rotating_by_size() {
+        let dir = std::env::temp_dir().join(format!("nexmon-csv-cols-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("log.csv");
+
+        let mut log = CsvLog::open(&path, Duration::from_secs(2), 1, 2).unwrap();
+        log.write(&snapshot(&["eth0", "veth1a2b"])).unwrap();
+        log.write(&snapshot(&["eth0", "odd,\"name\""])).unwrap();
+
+        let current = lines(&path);
+        assert!(current[0].ends_with(
+            "net_eth0_tx_bps,\"net_odd,\"\"name\"\"_rx_bps\",\"net_odd,\"\"name\"\"_tx_bps\""
+        ));
+        let columns = Columns::parse(&current[0]).unwrap();
+        assert_eq!(columns.interfaces, ["eth0", "odd,\"name\""]);
+        assert!(lines(&log.rotated(1))[0].contains("net_veth1a2b_rx_bps"));
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn rotates_by_size_and_drops_oldest() {
+        let dir = std::env::temp_dir().join(format!("nexmon-csv-size-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("log.csv");
+
+        let mut log = CsvLog::open(&path, Duration::from_secs(1), 1, 2).unwrap();
+        for _ in 0..4 {
+            log.write(&snapshot(&[])).unwrap();
+        }
+        assert_eq!(lines(&path).len(), 2);
+        assert!(log.rotated(2).exists());
+        assert!(!log.rotated(3).exists());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,409 @@
+//! Container names, images and compose projects from the Docker-compatible
+//! API that Docker and Podman serve on a local Unix socket.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long to wait on the engine before giving up on a query.
+const TIMEOUT: Duration = Duration::from_millis(500);
+/// How often the container list is fetched again regardless.
+const REFRESH: Duration = Duration::from_secs(10);
+/// Minimum gap between fetches when an unknown container shows up.
+const RETRY: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Default, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ContainerInfo {
+    /// Full 64-character ID.
+    pub id: String,
+    pub name: String,
+    pub image: String,
+    /// Compose project, from the `com.docker.compose.project` label.
+    pub project: Option<String>,
+    /// `running`, `paused`, `exited`, ...
+    pub state: String,
+}
+
+/// A container as listed by `GET /containers/json`.
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ApiContainer {
+    id: String,
+    #[serde(default)]
+    names: Vec<String>,
+    #[serde(default)]
+    image: String,
+    labels: Option<HashMap<String, String>>,
+    #[serde(default)]
+    state: String,
+}
+
+/// The socket to query: `setting` from the config if given (`off`
+/// disables the integration), else `DOCKER_HOST` if it is a `unix://`
+/// address, else the first of the usual Docker and Podman sockets to exist.
+pub fn find_socket(setting: Option<&str>) -> Option<PathBuf> {
+    match setting {
+        Some("off") => return None,
+        Some(path) => return Some(PathBuf::from(path.trim_start_matches("unix://"))),
+        None => {}
+    }
+    if let Ok(host) = std::env::var("DOCKER_HOST") {
+        return host.strip_prefix("unix://").map(PathBuf::from);
+    }
+    let runtime = std::env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from);
+    let mut candidates = vec![
+        PathBuf::from("/var/run/docker.sock"),
+        PathBuf::from("/run/podman/podman.sock"),
+    ];
+    if let Some(dir) = runtime {
+        candidates.push(dir.join("docker.sock"));
+        candidates.push(dir.join("podman/podman.sock"));
+    }
+    candidates.into_iter().find(|path| path.exists())
+}
+
+/// Every container the engine at `socket` knows about, running or not.
+#[cfg(unix)]
+pub fn list_containers(socket: &Path) -> Result<Vec<ContainerInfo>, String> {
+    let body = get(socket, "/containers/json?all=true")?;
+    let containers: Vec<ApiContainer> =
+        serde_json::from_slice(&body).map_err(|err| format!("bad container list: {}", err))?;
+    Ok(containers
+        .into_iter()
+        .map(|c| {
+            let labels = c.labels.unwrap_or_default();
+            let project = ["com.docker.compose.project", "io.podman.compose.project"]
+                .iter()
+                .find_map(|key| labels.get(*key).cloned());
+            ContainerInfo {
+                // Names come with a leading slash.
+                name: c
+                    .names
+                    .first()
+                    .map(|n| n.trim_start_matches('/').to_string())
+                    .unwrap_or_default(),
+                id: c.id,
+                image: c.image,
+                project,
+                state: c.state,
+            }
+        })
+        .collect())
+}
+
+#[cfg(not(unix))]
+pub fn list_containers(_socket: &Path) -> Result<Vec<ContainerInfo>, String> {
+    Err("container engines are only supported over Unix sockets".to_string())
+}
+
+/// The body of a GET request to `path` on the HTTP server at `socket`.
+#[cfg(unix)]
+fn get(socket: &Path, path: &str) -> Result<Vec<u8>, String> {
+    use std::os::unix::net::UnixStream;
+
+    let error = |err: std::io::Error| format!("{}: {}", socket.display(), err);
+    let mut stream = UnixStream::connect(socket).map_err(error)?;
+    stream.set_read_timeout(Some(TIMEOUT)).map_err(error)?;
+    stream.set_write_timeout(Some(TIMEOUT)).map_err(error)?;
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        path
+    );
+    stream.write_all(request.as_bytes()).map_err(error)?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).map_err(error)?;
+    parse_response(&response)
+}
+
+/// The body of a complete HTTP/1.1 response, which must be `200 OK`.
+fn parse_response(response: &[u8]) -> Result<Vec<u8>, String> {
+    let split = response
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or("truncated response")?;
+    let head = String::from_utf8_lossy(&response[..split]);
+    let body = &response[split + 4..];
+    let mut lines = head.lines();
+    let status = lines.next().unwrap_or_default();
+    if status.split_whitespace().nth(1) != Some("200") {
+        return Err(format!("engine answered {}", status));
+    }
+    let chunked = lines.any(|line| {
+        let line = line.to_ascii_lowercase();
+        line.starts_with("transfer-encoding:") && line.contains("chunked")
+    });
+    if chunked {
+        dechunk(body)
+    } else {
+        Ok(body.to_vec())
+    }
+}
+
+/// Joins the chunks of a `Transfer-Encoding: chunked` body.
+fn dechunk(mut body: &[u8]) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    loop {
+        let end = body
+            .windows(2)
+            .position(|w| w == b"\r\n")
+            .ok_or("truncated chunk")?;
+        let size = std::str::from_utf8(&body[..end])
+            .ok()
+            .and_then(|line| usize::from_str_radix(line.split(';').next()?.trim(), 16).ok())
+            .ok_or("bad chunk size")?;
+        body = &body[end + 2..];
+        if size == 0 {
+            return Ok(out);
+        }
+        if body.len() < size {
+            return Err("truncated chunk".to_string());
+        }
+        out.extend_from_slice(&body[..size]);
+        body = body.get(size + 2..).unwrap_or_default();
+    }
+}
+
+/// Containers by ID, fetched again every so often and whenever an ID
+/// turns up that the last fetch did not know. Fetches run on a background
+/// thread so a slow engine does not hold up sampling.
+pub struct Engine {
+    socket: PathBuf,
+    containers: HashMap<String, ContainerInfo>,
+    fetched: Option<Instant>,
+    /// The fetch in flight, if any.
+    pending: Option<Receiver<Result<Vec<ContainerInfo>, String>>>,
+    /// IDs seen when the fetch in flight started.
+    asked: HashSet<String>,
+    /// IDs the last fetch did not name, such as containerd or Kubernetes
+    /// containers the engine does not manage. They wait for the regular
+    /// refresh instead of triggering a fetch of their own.
+    unnamed: HashSet<String>,
+    /// Why the last fetch failed, if it did.
+    error: Option<String>,
+    /// A new error not yet passed on by `take_error`.
+    unreported: Option<String>,
+}
+
+impl Engine {
+    pub fn new(socket: PathBuf) -> Self {
+        Self {
+            socket,
+            containers: HashMap::new(),
+            fetched: None,
+            pending: None,
+            asked: HashSet::new(),
+            unnamed: HashSet::new(),
+            error: None,
+            unreported: None,
+        }
+    }
+
+    /// Takes in a finished fetch, then starts another if the list is stale
+    /// or misses one of `ids`. Names show up once that fetch finishes.
+    pub fn update<'a>(&mut self, ids: impl Iterator<Item = &'a str>) {
+        self.receive();
+        if self.pending.is_some() {
+            return;
+        }
+        let ids: Vec<&str> = ids.collect();
+        let age = self.fetched.map(|at| at.elapsed());
+        let stale = age.is_none_or(|age| age >= REFRESH);
+        let unknown = age.is_none_or(|age| age >= RETRY)
+            && ids
+                .iter()
+                .any(|id| !self.containers.contains_key(*id) && !self.unnamed.contains(*id));
+        if !stale && !unknown {
+            return;
+        }
+        self.fetched = Some(Instant::now());
+        self.asked = ids.into_iter().map(str::to_string).collect();
+        let (sender, receiver) = mpsc::channel();
+        let socket = self.socket.clone();
+        thread::spawn(move || sender.send(list_containers(&socket)));
+        self.pending = Some(receiver);
+    }
+
+    fn receive(&mut self) {
+        let Some(pending) = &self.pending else {
+            return;
+        };
+        let result = match pending.try_recv() {
+            Ok(result) => result,
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => Err("engine query thread died".to_string()),
+        };
+        self.pending = None;
+        match result {
+            Ok(list) => {
+                self.containers = list.into_iter().map(|c| (c.id.clone(), c)).collect();
+                let containers = &self.containers;
+                self.unnamed = self
+                    .asked
+                    .drain()
+                    .filter(|id| !containers.contains_key(id))
+                    .collect();
+                self.error = None;
+            }
+            Err(err) => {
+                if self.error.as_ref() != Some(&err) {
+                    self.unreported = Some(err.clone());
+                }
+                self.error = Some(err);
+            }
+        }
+    }
+
+    /// The error of the last fetch, once per distinct error.
+    pub fn take_error(&mut self) -> Option<String> {
+        self.unreported.take()
+    }
+
+    pub fn get(&self, id: &str) -> Option<&ContainerInfo> {
+        self.containers.get(id)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::os::unix::net::UnixListener;
+
+    /// Serves `response` to one request on a fresh socket and returns the
+    /// socket path along with the request line it got.
+    fn stub(name: &str, response: Vec<u8>) -> (PathBuf, thread::JoinHandle<String>) {
+        let path =
+            std::env::temp_dir().join(format!("nexmon-{}-{}.sock", name, std::process::id()));
+        std::fs::remove_file(&path).ok();
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut stream = stream;
+            stream.write_all(&response).unwrap();
+            request_line
+        });
+        (path, server)
+    }
+
+    /// Waits for the fetch in flight to finish.
+    fn settle(engine: &mut Engine) {
+        while engine.pending.is_some() {
+            thread::sleep(Duration::from_millis(5));
+            engine.receive();
+        }
+    }
+
+    fn fixture() -> Vec<u8> {
+        std::fs::read(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/engine/containers.json"
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn lists_containers_over_socket() {
+        // Docker streams the list in chunks.
+        let body = fixture();
+        let (first, rest) = body.split_at(100);
+        let mut response =
+            b"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nTransfer-Encoding: chunked\r\n\r\n"
+                .to_vec();
+        for chunk in [first, rest] {
+            response.extend(format!("{:x}\r\n", chunk.len()).bytes());
+            response.extend(chunk);
+            response.extend(b"\r\n");
+        }
+        response.extend(b"0\r\n\r\n");
+        let (socket, server) = stub("engine", response);
+
+        let containers = list_containers(&socket).unwrap();
+        assert!(server
+            .join()
+            .unwrap()
+            .starts_with("GET /containers/json?all=true "));
+        assert_eq!(containers.len(), 3);
+        assert_eq!(containers[0].name, "shop-web-1");
+        assert_eq!(containers[0].image, "nginx:1.25");
+        assert_eq!(containers[0].project.as_deref(), Some("shop"));
+        assert_eq!(containers[1].project, None);
+        // Podman's compose labels its containers differently.
+        assert_eq!(containers[2].project.as_deref(), Some("builds"));
+        assert_eq!(containers[2].state, "exited");
+        std::fs::remove_file(&socket).ok();
+    }
+
+    #[test]
+    fn engine_errors_are_reported() {
+        let (socket, server) = stub(
+            "engine-error",
+            b"HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\n\r\n".to_vec(),
+        );
+        let mut engine = Engine::new(socket.clone());
+        engine.update(std::iter::empty());
+        server.join().unwrap();
+        settle(&mut engine);
+        assert!(engine.take_error().unwrap().contains("500"));
+        assert!(engine.take_error().is_none());
+        std::fs::remove_file(&socket).ok();
+
+        let mut engine = Engine::new(socket);
+        engine.update(std::iter::empty());
+        settle(&mut engine);
+        assert!(engine.error.is_some());
+        assert!(find_socket(Some("off")).is_none());
+        assert_eq!(
+            find_socket(Some("unix:///run/user/1000/podman/podman.sock")),
+            Some(PathBuf::from("/run/user/1000/podman/podman.sock"))
+        );
+    }
+
+    #[test]
+    fn ids_the_engine_cannot_name_do_not_refetch() {
+        let body = fixture();
+        let mut response =
+            format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", body.len()).into_bytes();
+        response.extend(body);
+        let (socket, server) = stub("engine-unnamed", response);
+
+        let web = "3f2a1b4c5d6e7f8091a2b3c4d5e6f708192a3b4c5d6e7f8091a2b3c4d5e6f701";
+        let pod = "9e8d7c6b5a4f3e2d1c0b9a8f7e6d5c4b3a2f1e0d9c8b7a6f5e4d3c2b1a0f9e8d";
+        let mut engine = Engine::new(socket.clone());
+        engine.update([web, pod].into_iter());
+        // The fetch runs in the background.
+        assert!(engine.get(web).is_none());
+        server.join().unwrap();
+        settle(&mut engine);
+        assert_eq!(engine.get(web).unwrap().name, "shop-web-1");
+        assert!(engine.unnamed.contains(pod));
+
+        // Past the retry gap, but the pod's ID was already asked about.
+        engine.fetched = Some(Instant::now() - RETRY);
+        engine.update([web, pod].into_iter());
+        assert!(engine.pending.is_none());
+        std::fs::remove_file(&socket).ok();
+    }
+
+    #[test]
+    fn parses_plain_responses() {
+        let body = parse_response(b"HTTP/1.0 200 OK\r\nContent-Length: 2\r\n\r\n[]").unwrap();
+        assert_eq!(body, b"[]");
+        assert!(parse_response(b"HTTP/1.1 200 OK\r\n").is_err());
+        assert!(dechunk(b"zz\r\n").is_err());
+    }
+}
@@ -1,17 +1,8 @@
-use std::collections::VecDeque;
+use crate::system::snapshot::GpuSample;
+use std::collections::HashMap;
 use std::process::Command;
 
-#[derive(Clone)]
-pub struct GpuInfo {
-    pub name: String,
-    pub usage: f32,
-    pub mem_used_mb: f64,
-    pub mem_total_mb: f64,
-    pub temp_c: u32,
-    pub history: VecDeque<u64>,
-}
-
-pub fn get_gpu_info() -> Option<Vec<GpuInfo>> {
+pub fn get_gpu_info() -> Option<Vec<GpuSample>> {
     // Run nvidia-smi with CSV query format:
     // index, name, utilization.gpu, memory.used, memory.total, temperature.gpu
     let output = Command::new("nvidia-smi")
@@ -41,18 +32,12 @@ pub fn get_gpu_info() -> Option<Vec<GpuInfo>> {
         let mem_total_mb: f64 = parts[4].parse().unwrap_or(0.0);
         let temp_c: u32 = parts[5].parse().unwrap_or(0);
 
-        let mut history = VecDeque::with_capacity(60);
-        for _ in 0..60 {
-            history.push_back(0);
-        }
-
-        gpus.push(GpuInfo {
+        gpus.push(GpuSample {
             name,
             usage,
             mem_used_mb,
             mem_total_mb,
             temp_c,
-            history,
         });
     }
 
@@ -62,3 +47,33 @@ pub fn get_gpu_info() -> Option<Vec<GpuInfo>> {
         Some(gpus)
     }
 }
+
+/// GPU memory used by each process, in bytes, keyed by PID.
+pub fn get_process_gpu_memory() -> HashMap<u32, u64> {
+    let mut usage = HashMap::new();
+
+    let output = match Command::new("nvidia-smi")
+        .args([
+            "--query-compute-apps=pid,used_memory",
+            "--format=csv,noheader,nounits",
+        ])
+        .output()
+    {
+        Ok(output) if output.status.success() => output,
+        _ => return usage,
+    };
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    for line in stdout.lines() {
+        let parts: Vec<&str> = line.split(',').map(|s| s.trim()).collect();
+        if parts.len() != 2 {
+            continue;
+        }
+        if let (Ok(pid), Ok(mem_mb)) = (parts[0].parse::<u32>(), parts[1].parse::<u64>()) {
+            // A process can hold memory on several GPUs.
+            *usage.entry(pid).or_insert(0) += mem_mb * 1_048_576;
+        }
+    }
+
+    usage
+}
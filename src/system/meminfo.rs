@@ -0,0 +1,137 @@
+//! Where memory goes, from `/proc/meminfo`.
+
+use serde::{Deserialize, Serialize};
+
+/// Sizes in bytes.
+#[derive(Clone, Default, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MemInfo {
+    pub total: u64,
+    pub free: u64,
+    pub available: u64,
+    pub buffers: u64,
+    /// Page cache, including `shmem`.
+    pub cached: u64,
+    /// tmpfs and shared memory segments.
+    pub shmem: u64,
+    pub slab_reclaimable: u64,
+    pub slab_unreclaimable: u64,
+    /// Part of the page cache waiting to be written back.
+    pub dirty: u64,
+    /// Part of the page cache being written back.
+    pub writeback: u64,
+    pub kernel_stack: u64,
+    pub page_tables: u64,
+    /// Reserved for the hugepage pool, whether in use or not.
+    pub hugepages: u64,
+    /// RAM taken by the zswap pool.
+    pub zswap: u64,
+    /// Data in the zswap pool, before compression.
+    pub zswapped: u64,
+}
+
+impl MemInfo {
+    /// Page cache other than shared memory.
+    pub fn page_cache(&self) -> u64 {
+        self.cached.saturating_sub(self.shmem)
+    }
+
+    /// What is left once the free memory, caches and kernel allocations
+    /// above are taken out: memory used by applications.
+    pub fn apps(&self) -> u64 {
+        self.total.saturating_sub(
+            self.free
+                + self.buffers
+                + self.cached
+                + self.slab_reclaimable
+                + self.slab_unreclaimable
+                + self.kernel_stack
+                + self.page_tables
+                + self.hugepages,
+        )
+    }
+}
+
+pub fn parse(text: &str) -> Option<MemInfo> {
+    let mut info = MemInfo::default();
+    let mut hugepage_count = 0;
+    let mut hugepage_size = 0;
+    let mut hugetlb = None;
+    for line in text.lines() {
+        let Some((key, rest)) = line.split_once(':') else {
+            continue;
+        };
+        let mut words = rest.split_whitespace();
+        let Some(Ok(value)) = words.next().map(str::parse::<u64>) else {
+            continue;
+        };
+        let bytes = match words.next() {
+            Some("kB") => value * 1024,
+            _ => value,
+        };
+        match key {
+            "MemTotal" => info.total = bytes,
+            "MemFree" => info.free = bytes,
+            "MemAvailable" => info.available = bytes,
+            "Buffers" => info.buffers = bytes,
+            "Cached" => info.cached = bytes,
+            "Shmem" => info.shmem = bytes,
+            "SReclaimable" => info.slab_reclaimable = bytes,
+            "SUnreclaim" => info.slab_unreclaimable = bytes,
+            "Dirty" => info.dirty = bytes,
+            "Writeback" => info.writeback = bytes,
+            "KernelStack" => info.kernel_stack = bytes,
+            "PageTables" => info.page_tables = bytes,
+            // Only on Linux 5.19 and later.
+            "Zswap" => info.zswap = bytes,
+            "Zswapped" => info.zswapped = bytes,
+            "HugePages_Total" => hugepage_count = value,
+            "Hugepagesize" => hugepage_size = bytes,
+            // Covers every hugepage size; only on Linux 5.16 and later.
+            "Hugetlb" => hugetlb = Some(bytes),
+            _ => {}
+        }
+    }
+    info.hugepages = hugetlb.unwrap_or(hugepage_count * hugepage_size);
+    (info.total > 0).then_some(info)
+}
+
+#[cfg(target_os = "linux")]
+pub fn read() -> Option<MemInfo> {
+    parse(&std::fs::read_to_string("/proc/meminfo").ok()?)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn read() -> Option<MemInfo> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_fixture() {
+        let text = std::fs::read_to_string(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/meminfo/meminfo"
+        ))
+        .unwrap();
+        let info = parse(&text).unwrap();
+        assert_eq!(info.total, 6_158_152 * 1024);
+        assert_eq!(info.shmem, 9_484 * 1024);
+        assert_eq!(info.page_cache(), (3_673_392 - 9_484) * 1024);
+        assert_eq!(info.dirty, 220 * 1024);
+        assert_eq!(info.hugepages, 131_072 * 1024);
+        assert_eq!(info.apps(), 153_584 * 1024);
+    }
+
+    #[test]
+    fn hugepages_without_hugetlb_line() {
+        let info =
+            parse("MemTotal: 1000 kB\nHugePages_Total:       4\nHugepagesize:       2048 kB\n")
+                .unwrap();
+        assert_eq!(info.hugepages, 4 * 2048 * 1024);
+        assert!(parse("").is_none());
+    }
+}
@@ -1,2 +1,18 @@
+pub mod cgroups;
 pub mod collector;
+pub mod control;
+pub mod cpuinfo;
+pub mod csvlog;
+pub mod engine;
 pub mod gpu;
+pub mod meminfo;
+pub mod power;
+pub mod procfs;
+pub mod psi;
+pub mod recording;
+pub mod remote;
+pub mod rollup;
+pub mod services;
+pub mod snapshot;
+pub mod source;
+pub mod swap;
@@ -0,0 +1,181 @@
+//! Batteries and AC adapters from `/sys/class/power_supply`.
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+pub const SYS_POWER_SUPPLY: &str = "/sys/class/power_supply";
+
+/// A system battery. Energies in watt-hours, rates in watts.
+#[derive(Clone, Default, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Battery {
+    /// Such as `BAT0`.
+    pub name: String,
+    /// `Charging`, `Discharging`, `Full`, `Not charging` or `Unknown`.
+    pub status: String,
+    /// Charge in percent of the current full capacity.
+    pub percent: f64,
+    pub energy_now: Option<f64>,
+    pub energy_full: Option<f64>,
+    /// What the battery held when new.
+    pub energy_full_design: Option<f64>,
+    /// Rate of charge or discharge.
+    pub power: Option<f64>,
+    /// Seconds until empty while discharging, or until full while charging.
+    pub time_remaining: Option<u64>,
+}
+
+impl Battery {
+    pub fn charging(&self) -> bool {
+        self.status == "Charging"
+    }
+
+    pub fn discharging(&self) -> bool {
+        self.status == "Discharging"
+    }
+
+    /// Full capacity in percent of the design capacity.
+    pub fn health(&self) -> Option<f64> {
+        let (full, design) = (self.energy_full?, self.energy_full_design?);
+        (design > 0.0).then(|| full / design * 100.0)
+    }
+}
+
+#[derive(Clone, Default, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PowerSample {
+    /// Sorted by name; empty on machines without a battery.
+    pub batteries: Vec<Battery>,
+    /// Whether an AC adapter is plugged in; `None` when there is none to ask.
+    pub ac_online: Option<bool>,
+}
+
+/// Reads every supply under `root`. Peripheral batteries, such as those of
+/// wireless mice, are left out.
+pub fn read(root: &Path) -> PowerSample {
+    let mut sample = PowerSample::default();
+    let Ok(entries) = std::fs::read_dir(root) else {
+        return sample;
+    };
+    let mut entries: Vec<_> = entries.flatten().collect();
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let dir = entry.path();
+        let read = |file: &str| {
+            std::fs::read_to_string(dir.join(file))
+                .ok()
+                .map(|text| text.trim().to_string())
+        };
+        if read("scope").as_deref() == Some("Device") {
+            continue;
+        }
+        match read("type").as_deref() {
+            Some("Mains") => {
+                let online = read("online").as_deref() == Some("1");
+                sample.ac_online = Some(sample.ac_online.unwrap_or(false) || online);
+            }
+            Some("Battery") if read("present").as_deref() != Some("0") => {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                sample.batteries.push(read_battery(name, &read));
+            }
+            _ => {}
+        }
+    }
+    sample
+}
+
+/// Drivers report either energy (µWh) and power (µW), or charge (µAh) and
+/// current (µA) to be multiplied by the voltage (µV).
+fn read_battery(name: String, read: &dyn Fn(&str) -> Option<String>) -> Battery {
+    let micro = |file: &str| read(file)?.parse::<f64>().ok();
+    let volts = micro("voltage_now")
+        .or_else(|| micro("voltage_min_design"))
+        .map(|v| v / 1e6);
+    let watt_hours = |energy: &str, charge: &str| {
+        micro(energy)
+            .map(|e| e / 1e6)
+            .or_else(|| Some(micro(charge)? / 1e6 * volts?))
+    };
+    let energy_now = watt_hours("energy_now", "charge_now");
+    let energy_full = watt_hours("energy_full", "charge_full");
+    let energy_full_design = watt_hours("energy_full_design", "charge_full_design");
+    // Some firmware reports the discharge rate as negative.
+    let power = micro("power_now")
+        .map(|p| p / 1e6)
+        .or_else(|| Some(micro("current_now")? / 1e6 * volts?))
+        .map(f64::abs);
+
+    let percent = micro("capacity").or_else(|| {
+        let (now, full) = (energy_now?, energy_full?);
+        (full > 0.0).then(|| now / full * 100.0)
+    });
+    let status = read("status").unwrap_or_else(|| "Unknown".to_string());
+    let seconds = |file: &str| read(file)?.parse::<u64>().ok();
+    let time_remaining = match status.as_str() {
+        "Discharging" => seconds("time_to_empty_now").or_else(|| time_at_rate(energy_now?, power?)),
+        "Charging" => {
+            seconds("time_to_full_now").or_else(|| time_at_rate(energy_full? - energy_now?, power?))
+        }
+        _ => None,
+    };
+
+    Battery {
+        name,
+        status,
+        percent: percent.unwrap_or(0.0).clamp(0.0, 100.0),
+        energy_now,
+        energy_full,
+        energy_full_design,
+        power,
+        time_remaining,
+    }
+}
+
+/// Seconds to move `energy` watt-hours at `power` watts.
+fn time_at_rate(energy: f64, power: f64) -> Option<u64> {
+    (power > 0.0 && energy >= 0.0).then(|| (energy / power * 3600.0).round() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn fixtures() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/power")
+    }
+
+    #[test]
+    fn reads_batteries_and_adapter() {
+        let sample = read(&fixtures().join("laptop"));
+        assert_eq!(sample.ac_online, Some(false));
+        // The mouse battery is left out.
+        assert_eq!(sample.batteries.len(), 2);
+
+        let bat0 = &sample.batteries[0];
+        assert_eq!(bat0.name, "BAT0");
+        assert!(bat0.discharging());
+        assert_eq!(bat0.percent, 62.0);
+        assert_eq!(bat0.energy_full, Some(45.0));
+        assert_eq!(bat0.power, Some(9.0));
+        assert!((bat0.health().unwrap() - 90.0).abs() < 0.01);
+        // 27.9 Wh at 9 W.
+        assert_eq!(bat0.time_remaining, Some(11_160));
+
+        // Charge and current are converted with the voltage.
+        let bat1 = &sample.batteries[1];
+        assert!(bat1.charging());
+        assert!((bat1.energy_now.unwrap() - 20.0).abs() < 0.001);
+        assert!((bat1.power.unwrap() - 10.0).abs() < 0.001);
+        assert!((bat1.percent - 50.0).abs() < 0.001);
+        assert_eq!(bat1.time_remaining, Some(7_200));
+    }
+
+    #[test]
+    fn desktops_have_no_battery() {
+        let sample = read(&fixtures().join("desktop"));
+        assert!(sample.batteries.is_empty());
+        assert_eq!(sample.ac_online, Some(true));
+        assert_eq!(read(&fixtures().join("missing")), PowerSample::default());
+    }
+}
@@ -0,0 +1,170 @@
+/// Fields from `/proc/<pid>/stat` that sysinfo does not expose.
+pub struct ProcStat {
+    /// One letter: `R` running, `S` sleeping, `D` disk sleep, ...
+    pub state: char,
+    pub priority: i32,
+    pub nice: i32,
+    /// User + system CPU time, in seconds.
+    pub cpu_time: f64,
+}
+
+impl ProcStat {
+    /// The state as sysinfo names it.
+    pub fn status(&self) -> &'static str {
+        match self.state {
+            'R' => "Run",
+            'S' => "Sleep",
+            'D' => "UninterruptibleDiskSleep",
+            'T' => "Stop",
+            't' => "Tracing",
+            'Z' => "Zombie",
+            'X' => "Dead",
+            'I' => "Idle",
+            _ => "Unknown",
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub fn read_stat(pid: u32) -> Option<ProcStat> {
+    read_stat_file(&format!("/proc/{}/stat", pid))
+}
+
+/// `read_stat` for one thread of a process.
+#[cfg(target_os = "linux")]
+pub fn read_thread_stat(pid: u32, tid: u32) -> Option<ProcStat> {
+    read_stat_file(&format!("/proc/{}/task/{}/stat", pid, tid))
+}
+
+#[cfg(target_os = "linux")]
+fn read_stat_file(path: &str) -> Option<ProcStat> {
+    use std::sync::OnceLock;
+
+    static CLK_TCK: OnceLock<f64> = OnceLock::new();
+    let ticks = *CLK_TCK.get_or_init(|| {
+        // SAFETY: sysconf has no preconditions.
+        let v = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+        if v > 0 {
+            v as f64
+        } else {
+            100.0
+        }
+    });
+
+    let stat = std::fs::read_to_string(path).ok()?;
+    // The command name may contain spaces and parentheses, so split after the last ')'.
+    let rest = &stat[stat.rfind(')')? + 1..];
+    let fields: Vec<&str> = rest.split_whitespace().collect();
+    // fields[0] is field 3 (state) in proc(5) numbering.
+    let state = fields.first()?.chars().next()?;
+    let utime: u64 = fields.get(11)?.parse().ok()?;
+    let stime: u64 = fields.get(12)?.parse().ok()?;
+    let priority: i32 = fields.get(15)?.parse().ok()?;
+    let nice: i32 = fields.get(16)?.parse().ok()?;
+
+    Some(ProcStat {
+        state,
+        priority,
+        nice,
+        cpu_time: (utime + stime) as f64 / ticks,
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn read_stat(_pid: u32) -> Option<ProcStat> {
+    None
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn read_thread_stat(_pid: u32, _tid: u32) -> Option<ProcStat> {
+    None
+}
+
+/// Memory from `/proc/<pid>/smaps_rollup`, in bytes.
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct Smaps {
+    /// Proportional set size: resident memory with each shared page split
+    /// between the processes mapping it.
+    pub pss: u64,
+    /// Unique set size: resident memory no other process maps.
+    pub uss: u64,
+    pub swap: u64,
+}
+
+pub fn parse_smaps_rollup(text: &str) -> Option<Smaps> {
+    let mut smaps = Smaps::default();
+    let mut found = false;
+    for line in text.lines() {
+        let Some((key, rest)) = line.split_once(':') else {
+            continue;
+        };
+        let Some(Ok(kb)) = rest.split_whitespace().next().map(str::parse::<u64>) else {
+            continue;
+        };
+        match key {
+            "Pss" => {
+                smaps.pss = kb * 1024;
+                found = true;
+            }
+            "Private_Clean" | "Private_Dirty" => smaps.uss += kb * 1024,
+            "Swap" => smaps.swap = kb * 1024,
+            _ => {}
+        }
+    }
+    found.then_some(smaps)
+}
+
+/// Needs the same access as ptrace, so this fails for other users'
+/// processes unless running as root.
+#[cfg(target_os = "linux")]
+pub fn read_smaps(pid: u32) -> Option<Smaps> {
+    parse_smaps_rollup(&std::fs::read_to_string(format!("/proc/{}/smaps_rollup", pid)).ok()?)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn read_smaps(_pid: u32) -> Option<Smaps> {
+    None
+}
+
+/// The process's cgroup path, preferring the unified (v2) hierarchy.
+#[cfg(target_os = "linux")]
+pub fn read_cgroup(pid: u32) -> Option<String> {
+    let text = std::fs::read_to_string(format!("/proc/{}/cgroup", pid)).ok()?;
+    let mut fallback = None;
+    for line in text.lines() {
+        // hierarchy-ID:controller-list:cgroup-path
+        let mut parts = line.splitn(3, ':');
+        let (id, controllers, path) = (parts.next()?, parts.next()?, parts.next()?);
+        if id == "0" && controllers.is_empty() {
+            return Some(path.to_string());
+        }
+        if fallback.is_none() || controllers == "name=systemd" {
+            fallback = Some(path.to_string());
+        }
+    }
+    fallback
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn read_cgroup(_pid: u32) -> Option<String> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_smaps_rollup() {
+        let text = std::fs::read_to_string(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/procfs/smaps_rollup"
+        ))
+        .unwrap();
+        let smaps = parse_smaps_rollup(&text).unwrap();
+        assert_eq!(smaps.pss, 61_870 * 1024);
+        assert_eq!(smaps.uss, (6_880 + 40_904) * 1024);
+        assert_eq!(smaps.swap, 3_072 * 1024);
+        assert!(parse_smaps_rollup("").is_none());
+    }
+}
@@ -0,0 +1,172 @@
+//! Pressure stall information: the share of time tasks were stalled waiting
+//! for CPU, memory or IO, from `/proc/pressure/*` and, for a cgroup, its
+//! `*.pressure` files on the unified (v2) hierarchy.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+const PROC_PRESSURE: &str = "/proc/pressure";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Resource {
+    Cpu,
+    Memory,
+    Io,
+}
+
+impl Resource {
+    pub const ALL: [Resource; 3] = [Resource::Cpu, Resource::Memory, Resource::Io];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Resource::Cpu => "cpu",
+            Resource::Memory => "memory",
+            Resource::Io => "io",
+        }
+    }
+}
+
+/// Stall percentages averaged over 10 s, 60 s and 300 s.
+#[derive(Clone, Copy, Default, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PsiAverages {
+    pub avg10: f64,
+    pub avg60: f64,
+    pub avg300: f64,
+    /// Total stall time in microseconds.
+    pub total: u64,
+}
+
+/// One pressure file: time some task was stalled, and time all were.
+#[derive(Clone, Default, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Psi {
+    pub some: PsiAverages,
+    /// Missing from the system-wide CPU file before Linux 5.13.
+    pub full: Option<PsiAverages>,
+}
+
+#[derive(Clone, Default, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Pressure {
+    pub cpu: Psi,
+    pub memory: Psi,
+    pub io: Psi,
+}
+
+impl Pressure {
+    pub fn get(&self, resource: Resource) -> &Psi {
+        match resource {
+            Resource::Cpu => &self.cpu,
+            Resource::Memory => &self.memory,
+            Resource::Io => &self.io,
+        }
+    }
+}
+
+/// Pressure of the cgroup being looked at in the process table.
+#[derive(Clone, Default, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CgroupPressure {
+    pub path: String,
+    pub pressure: Pressure,
+}
+
+/// Parses a pressure file: a `some` line and an optional `full` line of
+/// `avg10=.. avg60=.. avg300=.. total=..`.
+pub fn parse(text: &str) -> Option<Psi> {
+    let mut some = None;
+    let mut full = None;
+    for line in text.lines() {
+        let mut fields = line.split_whitespace();
+        let slot = match fields.next() {
+            Some("some") => &mut some,
+            Some("full") => &mut full,
+            _ => continue,
+        };
+        let mut averages = PsiAverages::default();
+        for field in fields {
+            let (key, value) = field.split_once('=')?;
+            match key {
+                "avg10" => averages.avg10 = value.parse().ok()?,
+                "avg60" => averages.avg60 = value.parse().ok()?,
+                "avg300" => averages.avg300 = value.parse().ok()?,
+                "total" => averages.total = value.parse().ok()?,
+                _ => {}
+            }
+        }
+        *slot = Some(averages);
+    }
+    Some(Psi { some: some?, full })
+}
+
+/// System-wide pressure; `None` on kernels without PSI (or with `psi=0`).
+pub fn read_system() -> Option<Pressure> {
+    read_dir(Path::new(PROC_PRESSURE), "")
+}
+
+/// Pressure of the cgroup at `path` (as in `/proc/<pid>/cgroup`) under the
+/// v2 hierarchy mounted at `root`.
+pub fn read_cgroup(root: &Path, path: &str) -> Option<Pressure> {
+    read_dir(&root.join(path.trim_start_matches('/')), ".pressure")
+}
+
+/// Where the unified hierarchy is mounted: `/sys/fs/cgroup` on v2-only
+/// systems, `/sys/fs/cgroup/unified` on hybrid ones.
+pub fn cgroup_root() -> PathBuf {
+    let root = Path::new("/sys/fs/cgroup");
+    if root.join("cgroup.controllers").exists() {
+        root.to_path_buf()
+    } else {
+        root.join("unified")
+    }
+}
+
+fn read_dir(dir: &Path, suffix: &str) -> Option<Pressure> {
+    let read = |resource: Resource| {
+        let text =
+            std::fs::read_to_string(dir.join(format!("{}{}", resource.name(), suffix))).ok()?;
+        parse(&text)
+    };
+    Some(Pressure {
+        cpu: read(Resource::Cpu)?,
+        memory: read(Resource::Memory)?,
+        io: read(Resource::Io)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixtures() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/psi")
+    }
+
+    #[test]
+    fn reads_system_files() {
+        let pressure = read_dir(&fixtures().join("proc"), "").unwrap();
+        assert_eq!(pressure.cpu.some.avg10, 1.53);
+        assert_eq!(pressure.cpu.some.total, 84_571_134);
+        // Older kernels have no `full` line for CPU.
+        assert_eq!(pressure.cpu.full, None);
+        assert_eq!(pressure.io.full.unwrap().avg60, 6.02);
+        assert_eq!(pressure.get(Resource::Memory).some.avg300, 0.05);
+    }
+
+    #[test]
+    fn reads_cgroup_files() {
+        let pressure = read_cgroup(&fixtures(), "/cgroup").unwrap();
+        assert_eq!(pressure.cpu.full.unwrap().avg10, 20.1);
+        assert_eq!(pressure.memory.some, PsiAverages::default());
+        assert_eq!(pressure.io.some.avg10, 3.1);
+    }
+
+    #[test]
+    fn missing_or_malformed_is_none() {
+        assert!(read_cgroup(&fixtures(), "/no/such/cgroup").is_none());
+        assert!(parse("").is_none());
+        assert!(parse("full avg10=0.00 avg60=0.00 avg300=0.00 total=0\n").is_none());
+        assert!(parse("some avg10=x avg60=0.00 avg300=0.00 total=0\n").is_none());
+    }
+}
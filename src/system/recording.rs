@@ -0,0 +1,414 @@
+//! Session recordings: every snapshot written as gzip-compressed
+//! newline-delimited JSON, after a one-line header.
+
+use crate::system::power::PowerSample;
+use crate::system::psi::{CgroupPressure, Pressure};
+use crate::system::rollup;
+use crate::system::snapshot::{CpuSample, GpuSample, MemorySample, NetworkSample, Snapshot};
+use crate::system::swap::SwapSample;
+use color_eyre::{
+    eyre::{bail, eyre, WrapErr},
+    Result,
+};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+const FORMAT: &str = "nexmon-recording";
+const VERSION: u32 = 1;
+
+/// First line of a recording.
+#[derive(Serialize, Deserialize)]
+pub struct Header {
+    pub format: String,
+    pub version: u32,
+    /// Sampling interval the recording was made with.
+    pub interval_ms: u64,
+}
+
+/// Appends snapshots to a recording as they are collected.
+pub struct Recorder {
+    out: GzEncoder<BufWriter<File>>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path, interval: Duration) -> Result<Self> {
+        let file = File::create(path).wrap_err_with(|| format!("creating {}", path.display()))?;
+        let mut recorder = Self {
+            out: GzEncoder::new(BufWriter::new(file), Compression::default()),
+        };
+        recorder.write_line(&Header {
+            format: FORMAT.to_string(),
+            version: VERSION,
+            interval_ms: interval.as_millis() as u64,
+        })?;
+        Ok(recorder)
+    }
+
+    pub fn write(&mut self, snapshot: &Snapshot) -> Result<()> {
+        self.write_line(snapshot)
+    }
+
+    /// Writes the gzip trailer. Dropping without this still leaves a
+    /// readable file, since every line is flushed.
+    pub fn finish(self) -> Result<()> {
+        self.out.finish()?.flush()?;
+        Ok(())
+    }
+
+    fn write_line(&mut self, value: &impl Serialize) -> Result<()> {
+        serde_json::to_writer(&mut self.out, value)?;
+        self.out.write_all(b"\n")?;
+        // A sync flush keeps the file readable if nexmon is killed.
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+type Lines = std::io::Lines<BufReader<GzDecoder<File>>>;
+
+/// Opens a recording and checks its header, leaving the reader at the
+/// first sample.
+fn open(path: &Path) -> Result<(Header, Lines)> {
+    let file = File::open(path).wrap_err_with(|| format!("opening {}", path.display()))?;
+    let mut lines = BufReader::new(GzDecoder::new(file)).lines();
+
+    let header: Header = match lines.next() {
+        Some(line) => line
+            .map_err(color_eyre::Report::from)
+            .and_then(|line| Ok(serde_json::from_str(&line)?))
+            .wrap_err_with(|| format!("{} is not a nexmon recording", path.display()))?,
+        None => bail!("{} is empty", path.display()),
+    };
+    if header.format != FORMAT {
+        bail!("{} is not a nexmon recording", path.display());
+    }
+    if header.version > VERSION {
+        bail!(
+            "{} was recorded by a newer nexmon (format version {})",
+            path.display(),
+            header.version
+        );
+    }
+    Ok((header, lines))
+}
+
+/// The parts of a sample that charts draw, to index a recording without
+/// decoding every snapshot in full.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct Summary {
+    timestamp: u64,
+    overall_cpu: f32,
+    cpus: Vec<CpuSample>,
+    memory: MemorySample,
+    swap: SwapSample,
+    networks: Vec<NetworkSample>,
+    gpus: Vec<GpuSample>,
+    power: PowerSample,
+    pressure: Option<Pressure>,
+    cgroup_pressure: Option<CgroupPressure>,
+}
+
+impl Summary {
+    fn points(self) -> Vec<(String, f64)> {
+        rollup::points(&Snapshot {
+            timestamp: self.timestamp,
+            overall_cpu: self.overall_cpu,
+            cpus: self.cpus,
+            memory: self.memory,
+            swap: self.swap,
+            networks: self.networks,
+            gpus: self.gpus,
+            power: self.power,
+            pressure: self.pressure,
+            cgroup_pressure: self.cgroup_pressure,
+            ..Snapshot::default()
+        })
+    }
+}
+
+/// Playback position and controls shown in the footer during replay.
+#[derive(Clone, Copy)]
+pub struct ReplayStatus {
+    pub position: usize,
+    pub len: usize,
+    pub speed: f64,
+    pub playing: bool,
+}
+
+/// Steps through a recording at an adjustable speed. Samples are decoded
+/// as they are shown; only their timestamps and chart metrics are kept, so
+/// seeking refills the charts without decoding every sample again. Gzip
+/// cannot seek, so stepping back reads the file again from the start.
+pub struct Player {
+    path: PathBuf,
+    /// Time of every complete sample. A truncated last line, as left by a
+    /// killed recorder, is not counted.
+    timestamps: Vec<u64>,
+    /// Chart series keys, indexed by the points below.
+    keys: Vec<String>,
+    /// Chart metrics of every sample, as indices into `keys` and values.
+    points: Vec<Box<[(u32, f32)]>>,
+    /// Positioned before sample `next`.
+    lines: Lines,
+    next: usize,
+    interval: Duration,
+    position: usize,
+    speed: f64,
+    playing: bool,
+}
+
+impl Player {
+    pub const SPEEDS: [f64; 7] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0, 16.0];
+
+    /// Opens `path` and reads through it once to index its samples.
+    pub fn open(path: &Path) -> Result<Self> {
+        let (header, lines) = open(path)?;
+        let mut timestamps = Vec::new();
+        let mut keys = Vec::new();
+        let mut ids = HashMap::new();
+        let mut points = Vec::new();
+        let summaries = lines.map_while(|line| serde_json::from_str::<Summary>(&line.ok()?).ok());
+        for summary in summaries {
+            timestamps.push(summary.timestamp);
+            let sample = summary.points().into_iter().map(|(key, value)| {
+                let id = *ids.entry(key).or_insert_with_key(|key| {
+                    keys.push(key.clone());
+                    keys.len() as u32 - 1
+                });
+                (id, value as f32)
+            });
+            points.push(sample.collect());
+        }
+        if timestamps.is_empty() {
+            bail!("{} contains no samples", path.display());
+        }
+        let (_, lines) = open(path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            timestamps,
+            keys,
+            points,
+            lines,
+            next: 0,
+            interval: Duration::from_millis(header.interval_ms.max(1)),
+            position: 0,
+            speed: 1.0,
+            playing: true,
+        })
+    }
+
+    /// Decodes sample `index`, reading forward to it.
+    fn sample(&mut self, index: usize) -> Result<Snapshot> {
+        if index < self.next {
+            (_, self.lines) = open(&self.path)?;
+            self.next = 0;
+        }
+        let missing = || eyre!("{} changed during replay", self.path.display());
+        while self.next < index {
+            self.lines.next().ok_or_else(missing)??;
+            self.next += 1;
+        }
+        let line = self.lines.next().ok_or_else(missing)??;
+        self.next += 1;
+        Ok(serde_json::from_str(&line)?)
+    }
+
+    /// The sample at the current position.
+    pub fn current(&mut self) -> Result<Snapshot> {
+        self.sample(self.position)
+    }
+
+    /// The last `count` samples up to and including the current one.
+    pub fn recent(&mut self, count: usize) -> impl Iterator<Item = Result<Snapshot>> + '_ {
+        let start = (self.position + 1).saturating_sub(count);
+        (start..=self.position).map(|index| self.sample(index))
+    }
+
+    /// Time and chart metrics of the samples up to and including the
+    /// current one that are less than `span` older than it, from the index.
+    pub fn recent_points(
+        &self,
+        span: Duration,
+    ) -> impl Iterator<Item = (u64, impl Iterator<Item = (&str, f64)>)> {
+        let since = self.timestamps[self.position].saturating_sub(span.as_millis() as u64);
+        let start = self.timestamps[..self.position].partition_point(|&t| t < since);
+        (start..=self.position).map(|index| {
+            let points = self.points[index]
+                .iter()
+                .map(|&(id, value)| (self.keys[id as usize].as_str(), value as f64));
+            (self.timestamps[index], points)
+        })
+    }
+
+    pub fn status(&self) -> ReplayStatus {
+        ReplayStatus {
+            position: self.position,
+            len: self.timestamps.len(),
+            speed: self.speed,
+            playing: self.playing,
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Wall-clock time between samples at the current speed.
+    pub fn tick_rate(&self) -> Duration {
+        self.interval.div_f64(self.speed)
+    }
+
+    /// Moves to the next sample if playing; stops at the end, or if the
+    /// file can no longer be read.
+    pub fn advance(&mut self) -> Result<Option<Snapshot>> {
+        if !self.playing {
+            return Ok(None);
+        }
+        if self.position + 1 >= self.timestamps.len() {
+            self.playing = false;
+            return Ok(None);
+        }
+        self.position += 1;
+        let sample = self.current();
+        if sample.is_err() {
+            self.playing = false;
+        }
+        sample.map(Some)
+    }
+
+    /// Plays or pauses. Playing from the end starts over, in which case
+    /// this returns true.
+    pub fn toggle_playing(&mut self) -> bool {
+        let restart = !self.playing && self.position + 1 >= self.timestamps.len();
+        if restart {
+            self.position = 0;
+        }
+        self.playing = !self.playing;
+        restart
+    }
+
+    pub fn seek(&mut self, delta: i64) {
+        let last = self.timestamps.len() as i64 - 1;
+        self.position = (self.position as i64 + delta).clamp(0, last) as usize;
+    }
+
+    /// Jumps to `tenths` tenths of the way through the recording.
+    pub fn seek_fraction(&mut self, tenths: usize) {
+        self.position = (self.timestamps.len() - 1) * tenths.min(10) / 10;
+    }
+
+    /// Moves one step through `SPEEDS`.
+    pub fn change_speed(&mut self, faster: bool) {
+        let index = Self::SPEEDS
+            .iter()
+            .position(|&s| s == self.speed)
+            .unwrap_or(2);
+        let index = if faster {
+            (index + 1).min(Self::SPEEDS.len() - 1)
+        } else {
+            index.saturating_sub(1)
+        };
+        self.speed = Self::SPEEDS[index];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(timestamp: u64, cpu: f32) -> Snapshot {
+        Snapshot {
+            timestamp,
+            overall_cpu: cpu,
+            ..Snapshot::default()
+        }
+    }
+
+    /// Records `count` samples a second apart, returning the path.
+    fn record(name: &str, count: u64) -> (PathBuf, Recorder) {
+        let path = std::env::temp_dir().join(format!("nexmon-{}-{}.gz", name, std::process::id()));
+        let mut recorder = Recorder::create(&path, Duration::from_millis(1000)).unwrap();
+        for i in 0..count {
+            recorder
+                .write(&snapshot(1000 * (i + 1), i as f32 * 10.0))
+                .unwrap();
+        }
+        (path, recorder)
+    }
+
+    #[test]
+    fn round_trip_survives_truncation() {
+        let (path, recorder) = record("rec", 3);
+        // Cut off the gzip trailer, as when nexmon is killed mid-recording.
+        let flushed = std::fs::metadata(&path).unwrap().len();
+        recorder.finish().unwrap();
+        let file = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(flushed).unwrap();
+
+        let mut player = Player::open(&path).unwrap();
+        assert_eq!(player.interval(), Duration::from_millis(1000));
+        assert_eq!(player.status().len, 3);
+        player.seek(2);
+        let last = player.current().unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(last.timestamp, 3000);
+        assert_eq!(last.overall_cpu, 20.0);
+    }
+
+    #[test]
+    fn player_seeks_and_stops_at_end() {
+        let (path, recorder) = record("player", 11);
+        recorder.finish().unwrap();
+        let mut player = Player::open(&path).unwrap();
+        player.seek_fraction(5);
+        assert_eq!(player.status().position, 5);
+        assert_eq!(player.current().unwrap().timestamp, 6000);
+        // Stepping back reads the file again.
+        player.seek(-10);
+        assert_eq!(player.status().position, 0);
+        assert_eq!(player.current().unwrap().timestamp, 1000);
+        player.seek(9);
+        let next = player.advance().unwrap().map(|s| s.timestamp);
+        assert_eq!(next, Some(11_000));
+        assert!(player.advance().unwrap().is_none());
+        assert!(!player.status().playing);
+
+        // The last three samples, and the charts of those within four
+        // seconds from the index.
+        let recent = |player: &mut Player, count| -> Vec<u64> {
+            player.recent(count).map(|s| s.unwrap().timestamp).collect()
+        };
+        let cpu = |player: &Player, secs| -> Vec<(u64, f64)> {
+            player
+                .recent_points(Duration::from_secs(secs))
+                .map(|(ts, mut points)| (ts, points.find(|p| p.0 == "cpu").unwrap().1))
+                .collect()
+        };
+        assert_eq!(recent(&mut player, 3), [9000, 10_000, 11_000]);
+        assert_eq!(
+            cpu(&player, 4),
+            [
+                (7000, 60.0),
+                (8000, 70.0),
+                (9000, 80.0),
+                (10_000, 90.0),
+                (11_000, 100.0)
+            ]
+        );
+        player.seek_fraction(0);
+        assert_eq!(recent(&mut player, 3), [1000]);
+        assert_eq!(cpu(&player, 60), [(1000, 0.0)]);
+        std::fs::remove_file(&path).ok();
+
+        player.change_speed(true);
+        assert_eq!(player.tick_rate(), Duration::from_millis(500));
+    }
+}
@@ -0,0 +1,496 @@
+//! Streaming snapshots over TCP from a headless `nexmon agent` to the TUI
+//! of `nexmon connect`.
+//!
+//! The protocol is newline-delimited JSON, like recordings. The agent
+//! starts with a `Hello` line and then sends every snapshot it collects,
+//! beginning with the latest one; a client slow to read skips the older
+//! ones it has not taken yet. The client sends a `Settings` line
+//! whenever what it shows changes, so the agent only does the extra work
+//! (walking cgroups, listing services, reading smaps) a client needs.
+
+use crate::system::snapshot::Snapshot;
+use color_eyre::{
+    eyre::{bail, eyre, WrapErr},
+    Result,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashSet, VecDeque},
+    io::{BufRead, BufReader, Write},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        mpsc::{self, Receiver, TryRecvError},
+        Arc, Condvar, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+const FORMAT: &str = "nexmon-stream";
+const VERSION: u32 = 1;
+/// How long to wait for an agent to accept and greet, or for a client to
+/// take a snapshot before it is dropped.
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// Snapshots queued for a client that is slow to read; older ones are
+/// dropped to make room.
+const QUEUE: usize = 4;
+
+/// First line from the agent.
+#[derive(Serialize, Deserialize)]
+struct Hello {
+    format: String,
+    version: u32,
+    /// Sampling interval of the agent.
+    interval_ms: u64,
+}
+
+/// What a client needs collected beyond the basics; the `Collector`
+/// setters of the same names.
+#[derive(Clone, Default, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub pressure_cgroup: Option<String>,
+    pub walk_cgroups: bool,
+    pub list_services: bool,
+    /// `None` means all processes.
+    pub smaps_pids: Option<HashSet<u32>>,
+}
+
+impl Settings {
+    /// Enough to satisfy both `self` and `other`.
+    fn merge(mut self, other: &Settings) -> Settings {
+        self.pressure_cgroup = self.pressure_cgroup.or(other.pressure_cgroup.clone());
+        self.walk_cgroups |= other.walk_cgroups;
+        self.list_services |= other.list_services;
+        self.smaps_pids = match (self.smaps_pids, &other.smaps_pids) {
+            (Some(mut pids), Some(more)) => {
+                pids.extend(more);
+                Some(pids)
+            }
+            _ => None,
+        };
+        self
+    }
+}
+
+/// Lines waiting for a client's writer thread.
+#[derive(Default)]
+struct Outbox {
+    lines: VecDeque<Arc<str>>,
+    /// Set once the client has gone or the agent shuts down.
+    closed: bool,
+}
+
+struct Client {
+    stream: TcpStream,
+    outbox: Arc<(Mutex<Outbox>, Condvar)>,
+    /// `None` until the client sends its first.
+    settings: Arc<Mutex<Option<Settings>>>,
+}
+
+impl Client {
+    /// Queues `line`, dropping the oldest queued one if the client is
+    /// behind. False once the client has gone.
+    fn send(&self, line: &Arc<str>) -> bool {
+        let (outbox, ready) = &*self.outbox;
+        let mut outbox = outbox.lock().unwrap();
+        if outbox.closed {
+            return false;
+        }
+        if outbox.lines.len() >= QUEUE {
+            outbox.lines.pop_front();
+        }
+        outbox.lines.push_back(line.clone());
+        ready.notify_one();
+        true
+    }
+
+    fn close(&self) {
+        let (outbox, ready) = &*self.outbox;
+        outbox.lock().unwrap().closed = true;
+        ready.notify_one();
+        self.stream.shutdown(Shutdown::Both).ok();
+    }
+}
+
+/// The listening end: accepts clients in the background and sends each
+/// published snapshot to all of them.
+pub struct Agent {
+    addr: SocketAddr,
+    clients: Arc<Mutex<Vec<Client>>>,
+    /// The last snapshot published, as sent, for clients that join later.
+    /// Locked after `clients` wherever both are.
+    latest: Arc<Mutex<Option<Arc<str>>>>,
+}
+
+impl Agent {
+    pub fn bind(addr: &str, interval: Duration) -> Result<Self> {
+        let listener =
+            TcpListener::bind(addr).wrap_err_with(|| format!("listening on {}", addr))?;
+        let agent = Self {
+            addr: listener.local_addr()?,
+            clients: Arc::new(Mutex::new(Vec::new())),
+            latest: Arc::new(Mutex::new(None)),
+        };
+        let hello = serde_json::to_string(&Hello {
+            format: FORMAT.to_string(),
+            version: VERSION,
+            interval_ms: interval.as_millis() as u64,
+        })?;
+        let clients = agent.clients.clone();
+        let latest = agent.latest.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                // Held until the client is added so it cannot miss a snapshot;
+                // greeting only queues, so a slow client holds up nothing.
+                let mut clients = clients.lock().unwrap();
+                let latest = latest.lock().unwrap();
+                if let Ok(client) = greet(stream, &hello, latest.as_ref()) {
+                    clients.push(client);
+                }
+            }
+        });
+        Ok(agent)
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// What the connected clients need, together.
+    pub fn settings(&self) -> Settings {
+        self.clients
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|c| c.settings.lock().unwrap().clone())
+            .reduce(|a, b| a.merge(&b))
+            // Without clients there is nothing to read smaps for.
+            .unwrap_or(Settings {
+                smaps_pids: Some(HashSet::new()),
+                ..Settings::default()
+            })
+    }
+
+    pub fn clients(&self) -> usize {
+        self.clients.lock().unwrap().len()
+    }
+
+    /// Queues `snapshot` for every client, dropping those that have gone
+    /// or stopped reading. Each client is written to on its own thread.
+    pub fn publish(&self, snapshot: &Snapshot) -> Result<()> {
+        let mut line = serde_json::to_string(snapshot)?;
+        line.push('\n');
+        let line: Arc<str> = line.into();
+        // Held across the sends, as when a client joins.
+        let mut clients = self.clients.lock().unwrap();
+        clients.retain(|c| c.send(&line));
+        *self.latest.lock().unwrap() = Some(line);
+        Ok(())
+    }
+}
+
+impl Drop for Agent {
+    fn drop(&mut self) {
+        for client in self.clients.lock().unwrap().drain(..) {
+            client.close();
+        }
+    }
+}
+
+/// Starts a writer thread that sends the hello and then the latest and
+/// every later snapshot to a new client, and a reader for its settings.
+fn greet(stream: TcpStream, hello: &str, latest: Option<&Arc<str>>) -> Result<Client> {
+    stream.set_nodelay(true)?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let outbox = Arc::new((
+        Mutex::new(Outbox {
+            lines: latest.cloned().into_iter().collect(),
+            closed: false,
+        }),
+        Condvar::new(),
+    ));
+    let writer = stream.try_clone()?;
+    let hello = format!("{}\n", hello);
+    let queued = outbox.clone();
+    thread::spawn(move || write_queued(writer, &hello, &queued));
+
+    let reader = stream.try_clone()?;
+    let settings = Arc::new(Mutex::new(None));
+    let shared = settings.clone();
+    thread::spawn(move || {
+        for line in BufReader::new(reader).lines() {
+            let Ok(line) = line else { break };
+            if let Ok(update) = serde_json::from_str(&line) {
+                *shared.lock().unwrap() = Some(update);
+            }
+        }
+    });
+    Ok(Client {
+        stream,
+        outbox,
+        settings,
+    })
+}
+
+/// Writes `hello`, then queued lines as they come, until the client goes
+/// or the agent closes the outbox.
+fn write_queued(mut stream: TcpStream, hello: &str, outbox: &(Mutex<Outbox>, Condvar)) {
+    let (lock, ready) = outbox;
+    let mut line: Arc<str> = hello.into();
+    loop {
+        if stream.write_all(line.as_bytes()).is_err() {
+            lock.lock().unwrap().closed = true;
+            return;
+        }
+        let mut queued = lock.lock().unwrap();
+        line = loop {
+            if queued.closed {
+                return;
+            }
+            if let Some(line) = queued.lines.pop_front() {
+                break line;
+            }
+            queued = ready.wait(queued).unwrap();
+        };
+    }
+}
+
+/// The connecting end: receives snapshots from an agent in the background.
+pub struct Remote {
+    addr: String,
+    stream: TcpStream,
+    interval: Duration,
+    snapshots: Receiver<Snapshot>,
+    /// Settings as last sent, to only send changes.
+    sent: Option<Settings>,
+}
+
+impl Remote {
+    pub fn connect(addr: &str) -> Result<Self> {
+        let target = addr
+            .to_socket_addrs()
+            .wrap_err_with(|| format!("resolving {}", addr))?
+            .next()
+            .ok_or_else(|| eyre!("{} has no address", addr))?;
+        let stream = TcpStream::connect_timeout(&target, TIMEOUT)
+            .wrap_err_with(|| format!("connecting to {}", addr))?;
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut line = String::new();
+        reader
+            .read_line(&mut line)
+            .wrap_err_with(|| format!("waiting for {} to greet", addr))?;
+        let hello = match serde_json::from_str::<Hello>(&line) {
+            Ok(hello) if hello.format == FORMAT => hello,
+            _ => bail!("{} is not a nexmon agent", addr),
+        };
+        if hello.version > VERSION {
+            bail!(
+                "{} runs a newer nexmon (protocol version {})",
+                addr,
+                hello.version
+            );
+        }
+        // Snapshots may be far apart, so only the greeting has a deadline.
+        stream.set_read_timeout(None)?;
+
+        let (sender, snapshots) = mpsc::channel();
+        thread::spawn(move || {
+            for line in reader.lines() {
+                let Ok(line) = line else { break };
+                let Ok(snapshot) = serde_json::from_str(&line) else {
+                    break;
+                };
+                if sender.send(snapshot).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Self {
+            addr: addr.to_string(),
+            stream,
+            interval: Duration::from_millis(hello.interval_ms),
+            snapshots,
+            sent: None,
+        })
+    }
+
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+
+    /// Sampling interval of the agent.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// The next snapshot, waiting up to `timeout` for it.
+    pub fn wait(&self, timeout: Duration) -> Result<Snapshot> {
+        self.snapshots
+            .recv_timeout(timeout)
+            .map_err(|_| eyre!("no samples from {}", self.addr))
+    }
+
+    /// Snapshots received since the last call, oldest first, or an error
+    /// once the connection is gone and they have all been taken.
+    pub fn receive(&self) -> Result<Vec<Snapshot>, String> {
+        let mut received = Vec::new();
+        loop {
+            match self.snapshots.try_recv() {
+                Ok(snapshot) => received.push(snapshot),
+                Err(TryRecvError::Empty) => return Ok(received),
+                Err(TryRecvError::Disconnected) if received.is_empty() => {
+                    return Err(format!("connection to {} lost", self.addr))
+                }
+                Err(TryRecvError::Disconnected) => return Ok(received),
+            }
+        }
+    }
+
+    /// Tells the agent what to collect, if that changed.
+    pub fn send_settings(&mut self, settings: Settings) -> Result<(), String> {
+        if self.sent.as_ref() == Some(&settings) {
+            return Ok(());
+        }
+        let mut line = serde_json::to_string(&settings).map_err(|err| err.to_string())?;
+        line.push('\n');
+        self.stream
+            .write_all(line.as_bytes())
+            .map_err(|err| format!("{}: {}", self.addr, err))?;
+        self.sent = Some(settings);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn snapshot(timestamp: u64) -> Snapshot {
+        Snapshot {
+            timestamp,
+            overall_cpu: 42.0,
+            ..Snapshot::default()
+        }
+    }
+
+    /// Polls `condition` for up to the protocol timeout.
+    fn eventually(condition: impl Fn() -> bool) -> bool {
+        let start = Instant::now();
+        while start.elapsed() < TIMEOUT {
+            if condition() {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        false
+    }
+
+    #[test]
+    fn streams_snapshots_to_clients() {
+        let agent = Agent::bind("127.0.0.1:0", Duration::from_millis(250)).unwrap();
+        agent.publish(&snapshot(1)).unwrap();
+        let addr = agent.local_addr().to_string();
+
+        // A client that joins late starts with the latest snapshot.
+        let mut remote = Remote::connect(&addr).unwrap();
+        assert_eq!(remote.interval(), Duration::from_millis(250));
+        assert_eq!(remote.wait(TIMEOUT).unwrap().timestamp, 1);
+        assert!(eventually(|| agent.clients() == 1));
+
+        agent.publish(&snapshot(2)).unwrap();
+        agent.publish(&snapshot(3)).unwrap();
+        assert_eq!(remote.wait(TIMEOUT).unwrap().timestamp, 2);
+        let third = remote.wait(TIMEOUT).unwrap();
+        assert_eq!(third.timestamp, 3);
+        assert_eq!(third.overall_cpu, 42.0);
+        assert!(remote.receive().unwrap().is_empty());
+
+        // What the client shows decides what the agent collects.
+        assert_eq!(agent.settings().smaps_pids, Some(HashSet::new()));
+        let settings = Settings {
+            walk_cgroups: true,
+            smaps_pids: Some([7].into()),
+            ..Settings::default()
+        };
+        remote.send_settings(settings.clone()).unwrap();
+        assert!(eventually(|| agent.settings() == settings));
+
+        // A client counts once it says what it needs.
+        let mut other = Remote::connect(&addr).unwrap();
+        assert!(eventually(|| agent.clients() == 2));
+        assert_eq!(agent.settings(), settings);
+        other
+            .send_settings(Settings {
+                list_services: true,
+                smaps_pids: None,
+                ..Settings::default()
+            })
+            .unwrap();
+        assert!(eventually(|| agent.settings().list_services));
+        assert!(agent.settings().walk_cgroups);
+        assert_eq!(agent.settings().smaps_pids, None);
+
+        // Once the agent is gone the client says so.
+        drop(agent);
+        assert!(eventually(|| remote.receive().is_err()));
+    }
+
+    #[test]
+    fn slow_clients_hold_up_nothing() {
+        let agent = Agent::bind("127.0.0.1:0", Duration::from_millis(250)).unwrap();
+        let mut big = Snapshot {
+            processes: vec![Default::default(); 1000],
+            ..snapshot(0)
+        };
+        agent.publish(&big).unwrap();
+
+        // Connects but never reads, so its socket buffers fill up.
+        let _stuck = TcpStream::connect(agent.local_addr()).unwrap();
+        assert!(eventually(|| agent.clients() == 1));
+        let start = Instant::now();
+        for timestamp in 1..50 {
+            big.timestamp = timestamp;
+            agent.publish(&big).unwrap();
+        }
+        assert!(start.elapsed() < TIMEOUT / 2);
+
+        // Others still join and get the latest snapshot at once.
+        let remote = Remote::connect(&agent.local_addr().to_string()).unwrap();
+        assert_eq!(remote.wait(TIMEOUT).unwrap().timestamp, 49);
+        let clients = agent.clients.lock().unwrap();
+        let (outbox, _) = &*clients[0].outbox;
+        assert!(outbox.lock().unwrap().lines.len() <= QUEUE);
+    }
+
+    #[test]
+    fn rejects_other_servers() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(b"SSH-2.0-OpenSSH_9.6\r\n").unwrap();
+        });
+        let err = Remote::connect(&addr).err().unwrap();
+        assert!(err.to_string().contains("not a nexmon agent"));
+        server.join().unwrap();
+
+        let merged = Settings {
+            pressure_cgroup: Some("/system.slice".to_string()),
+            smaps_pids: Some([1].into()),
+            ..Settings::default()
+        }
+        .merge(&Settings {
+            list_services: true,
+            smaps_pids: Some([2].into()),
+            ..Settings::default()
+        });
+        assert!(merged.list_services && !merged.walk_cgroups);
+        assert_eq!(merged.pressure_cgroup.as_deref(), Some("/system.slice"));
+        assert_eq!(merged.smaps_pids, Some([1, 2].into()));
+    }
+}
@@ -0,0 +1,430 @@
+//! Multi-resolution metric history.
+//!
+//! Every numeric series (overall CPU, each core, memory, each interface, each
+//! GPU, pressure) is kept at three resolutions: raw samples, 10-second
+//! buckets and 1-minute buckets, each holding the average and maximum of the
+//! samples that fell into it. Each tier has a fixed capacity, so memory stays bounded no
+//! matter how long the retention is. Charts draw the maximum, so spikes
+//! stay visible when zoomed out.
+
+use crate::system::psi::Resource;
+use crate::system::snapshot::Snapshot;
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+/// Longest span kept at full resolution.
+const RAW_SPAN: Duration = Duration::from_secs(10 * 60);
+/// Longest span kept at 10-second resolution.
+const TEN_SECOND_SPAN: Duration = Duration::from_secs(6 * 60 * 60);
+/// Bucket width of each tier; raw keeps every sample as its own bucket.
+const TIER_WIDTHS_MS: [u64; 3] = [0, 10_000, 60_000];
+
+/// Resolution the charts are drawn at.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Zoom {
+    Raw,
+    TenSeconds,
+    Minute,
+    /// The whole retention squeezed into the width of each chart.
+    All,
+}
+
+impl Zoom {
+    pub fn next(self) -> Zoom {
+        match self {
+            Zoom::Raw => Zoom::TenSeconds,
+            Zoom::TenSeconds => Zoom::Minute,
+            Zoom::Minute => Zoom::All,
+            Zoom::All => Zoom::Raw,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Zoom::Raw => "raw",
+            Zoom::TenSeconds => "10s",
+            Zoom::Minute => "1m",
+            Zoom::All => "all",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Bucket {
+    /// Unix time in milliseconds the bucket starts at.
+    pub start: u64,
+    pub sum: f64,
+    pub max: f64,
+    pub count: u32,
+}
+
+impl Bucket {
+    pub fn avg(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.sum / self.count as f64
+        }
+    }
+}
+
+struct Tier {
+    width_ms: u64,
+    capacity: usize,
+    buckets: VecDeque<Bucket>,
+}
+
+impl Tier {
+    fn add(&mut self, timestamp: u64, value: f64) {
+        let start = match self.width_ms {
+            0 => timestamp,
+            width => timestamp - timestamp % width,
+        };
+        if let Some(last) = self.buckets.back_mut() {
+            if self.width_ms > 0 && last.start == start {
+                last.sum += value;
+                last.max = last.max.max(value);
+                last.count += 1;
+                return;
+            }
+        }
+
+        if self.buckets.len() >= self.capacity {
+            self.buckets.pop_front();
+        }
+        self.buckets.push_back(Bucket {
+            start,
+            sum: value,
+            max: value,
+            count: 1,
+        });
+    }
+}
+
+/// Series keys for the metrics taken from each snapshot.
+pub mod keys {
+    pub const CPU: &str = "cpu";
+    pub const MEM_USED: &str = "mem.used";
+    pub const SWAP_USED: &str = "swap.used";
+    /// Pages swapped in per second.
+    pub const SWAP_IN: &str = "swap.in";
+    /// Pages swapped out per second.
+    pub const SWAP_OUT: &str = "swap.out";
+
+    pub fn core(index: usize) -> String {
+        format!("cpu.{}", index)
+    }
+
+    pub fn net_rx(interface: &str) -> String {
+        format!("net.{}.rx", interface)
+    }
+
+    pub fn net_tx(interface: &str) -> String {
+        format!("net.{}.tx", interface)
+    }
+
+    pub fn gpu_util(index: usize) -> String {
+        format!("gpu.{}.util", index)
+    }
+
+    pub fn gpu_mem(index: usize) -> String {
+        format!("gpu.{}.mem", index)
+    }
+
+    pub fn gpu_temp(index: usize) -> String {
+        format!("gpu.{}.temp", index)
+    }
+
+    /// Charge of a battery, in percent.
+    pub fn battery(name: &str) -> String {
+        format!("battery.{}", name)
+    }
+
+    /// `some` avg10 pressure of `resource`, in hundredths of a percent.
+    pub fn psi(resource: &str) -> String {
+        format!("psi.{}", resource)
+    }
+
+    /// `psi` for the cgroup at `path`.
+    pub fn cgroup_psi(path: &str, resource: &str) -> String {
+        format!("psi.{}.{}", path, resource)
+    }
+}
+
+/// Every metric of `snapshot` that charts draw, by series key.
+pub fn points(snapshot: &Snapshot) -> Vec<(String, f64)> {
+    let mut points = vec![(keys::CPU.to_string(), snapshot.overall_cpu as f64)];
+    for (i, cpu) in snapshot.cpus.iter().enumerate() {
+        points.push((keys::core(i), cpu.usage as f64));
+    }
+    points.push((keys::MEM_USED.to_string(), snapshot.memory.used as f64));
+    points.push((
+        keys::SWAP_USED.to_string(),
+        snapshot.memory.used_swap as f64,
+    ));
+    points.push((keys::SWAP_IN.to_string(), snapshot.swap.pages_in));
+    points.push((keys::SWAP_OUT.to_string(), snapshot.swap.pages_out));
+    for net in &snapshot.networks {
+        points.push((keys::net_rx(&net.name), net.rx_bytes as f64));
+        points.push((keys::net_tx(&net.name), net.tx_bytes as f64));
+    }
+    for (i, gpu) in snapshot.gpus.iter().enumerate() {
+        points.push((keys::gpu_util(i), gpu.usage as f64));
+        points.push((keys::gpu_mem(i), gpu.mem_used_mb));
+        points.push((keys::gpu_temp(i), gpu.temp_c as f64));
+    }
+    for battery in &snapshot.power.batteries {
+        points.push((keys::battery(&battery.name), battery.percent));
+    }
+    if let Some(pressure) = &snapshot.pressure {
+        for resource in Resource::ALL {
+            let avg10 = pressure.get(resource).some.avg10;
+            points.push((keys::psi(resource.name()), avg10 * 100.0));
+        }
+    }
+    if let Some(cgroup) = &snapshot.cgroup_pressure {
+        for resource in Resource::ALL {
+            let avg10 = cgroup.pressure.get(resource).some.avg10;
+            let key = keys::cgroup_psi(&cgroup.path, resource.name());
+            points.push((key, avg10 * 100.0));
+        }
+    }
+    points
+}
+
+pub struct Rollups {
+    retention: Duration,
+    interval: Duration,
+    /// Capacity of each tier, in buckets.
+    capacities: [usize; 3],
+    series: HashMap<String, [Tier; 3]>,
+}
+
+impl Rollups {
+    pub fn new(retention: Duration, interval: Duration) -> Self {
+        let count = |span: Duration, width: Duration| {
+            (span.min(retention).as_millis() / width.as_millis().max(1)).max(1) as usize
+        };
+        Self {
+            retention,
+            interval,
+            capacities: [
+                count(RAW_SPAN, interval),
+                count(TEN_SECOND_SPAN, Duration::from_secs(10)),
+                count(retention, Duration::from_secs(60)),
+            ],
+            series: HashMap::new(),
+        }
+    }
+
+    pub fn retention(&self) -> Duration {
+        self.retention
+    }
+
+    /// The tier drawn at `zoom`. `All` takes the coarsest tier needed to
+    /// reach back over the whole retention.
+    fn tier(&self, zoom: Zoom) -> usize {
+        match zoom {
+            Zoom::Raw => 0,
+            Zoom::TenSeconds => 1,
+            Zoom::Minute => 2,
+            Zoom::All if self.retention <= RAW_SPAN => 0,
+            Zoom::All if self.retention <= TEN_SECOND_SPAN => 1,
+            Zoom::All => 2,
+        }
+    }
+
+    /// Time each point of a chart at `zoom` covers, given `window` points.
+    pub fn bucket_width(&self, zoom: Zoom, window: usize) -> Duration {
+        match (zoom, TIER_WIDTHS_MS[self.tier(zoom)]) {
+            (Zoom::All, _) => self.retention / window.max(1) as u32,
+            (_, 0) => self.interval,
+            (_, width) => Duration::from_millis(width),
+        }
+    }
+
+    /// Most buckets a series keeps at `zoom`, and so how far back charts
+    /// can be scrolled.
+    pub fn capacity(&self, zoom: Zoom) -> usize {
+        self.capacities[self.tier(zoom)]
+    }
+
+    pub fn add(&mut self, key: &str, timestamp: u64, value: f64) {
+        let capacities = self.capacities;
+        let tiers = self.series.entry(key.to_string()).or_insert_with(|| {
+            std::array::from_fn(|i| Tier {
+                width_ms: TIER_WIDTHS_MS[i],
+                capacity: capacities[i],
+                buckets: VecDeque::new(),
+            })
+        });
+        for tier in tiers.iter_mut() {
+            tier.add(timestamp, value);
+        }
+    }
+
+    /// Adds every metric in `snapshot` and drops series that stopped
+    /// reporting longer than the retention ago.
+    pub fn record(&mut self, snapshot: &Snapshot) {
+        let points = points(snapshot);
+        let points = points.iter().map(|(key, value)| (key.as_str(), *value));
+        self.record_points(snapshot.timestamp, points);
+    }
+
+    /// Like `record`, for the `points` of a sample taken at `timestamp`.
+    pub fn record_points<'a>(
+        &mut self,
+        timestamp: u64,
+        points: impl IntoIterator<Item = (&'a str, f64)>,
+    ) {
+        for (key, value) in points {
+            self.add(key, timestamp, value);
+        }
+
+        let cutoff = timestamp.saturating_sub(self.retention.as_millis() as u64);
+        self.series.retain(|_, tiers| {
+            tiers
+                .iter()
+                .filter_map(|t| t.buckets.back())
+                .any(|b| b.start >= cutoff)
+        });
+    }
+
+    /// Buckets of `key` at `zoom` that start at or before `until`.
+    pub fn buckets(&self, key: &str, zoom: Zoom, until: u64) -> Vec<Bucket> {
+        self.series
+            .get(key)
+            .map(|tiers| {
+                tiers[self.tier(zoom)]
+                    .buckets
+                    .iter()
+                    .filter(|b| b.start <= until)
+                    .copied()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// `window` bucket maximums of `key` up to `until`, leaving out the
+    /// last `back` buckets, zero-padded at the front so sparklines stay
+    /// right-aligned. Scrolling stops at the oldest bucket. At `Zoom::All`
+    /// each point is the maximum over its share of the retention, and
+    /// `back` is ignored.
+    pub fn series(
+        &self,
+        key: &str,
+        zoom: Zoom,
+        until: u64,
+        window: usize,
+        back: usize,
+    ) -> Vec<u64> {
+        if window == 0 {
+            return Vec::new();
+        }
+        let buckets = self.buckets(key, zoom, until);
+        if zoom == Zoom::All {
+            let width = self.bucket_width(zoom, window).as_millis().max(1) as u64;
+            let since = until.saturating_sub(width * window as u64);
+            let mut data = vec![0.0f64; window];
+            for b in buckets.iter().filter(|b| b.start >= since) {
+                let point = (((b.start - since) / width) as usize).min(window - 1);
+                data[point] = data[point].max(b.max);
+            }
+            return data.into_iter().map(|v| v.round() as u64).collect();
+        }
+        let end = buckets.len() - back.min(buckets.len().saturating_sub(window));
+        let start = end.saturating_sub(window);
+        let mut data = vec![0; window - (end - start)];
+        data.extend(buckets[start..end].iter().map(|b| b.max.round() as u64));
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rollups(retention_secs: u64) -> Rollups {
+        Rollups::new(Duration::from_secs(retention_secs), Duration::from_secs(1))
+    }
+
+    #[test]
+    fn buckets_hold_average_and_max() {
+        let mut r = rollups(3600);
+        for (i, value) in [10.0, 30.0, 20.0].into_iter().enumerate() {
+            r.add("cpu", 60_000 + i as u64 * 1000, value);
+        }
+        r.add("cpu", 70_000, 90.0);
+
+        let ten = r.buckets("cpu", Zoom::TenSeconds, u64::MAX);
+        assert_eq!(ten.len(), 2);
+        assert_eq!(ten[0].start, 60_000);
+        assert_eq!(ten[0].avg(), 20.0);
+        assert_eq!(ten[0].max, 30.0);
+
+        let minute = r.buckets("cpu", Zoom::Minute, u64::MAX);
+        assert_eq!(minute.len(), 1);
+        assert_eq!(minute[0].avg(), 37.5);
+        assert_eq!(minute[0].max, 90.0);
+
+        assert_eq!(r.buckets("cpu", Zoom::Raw, u64::MAX).len(), 4);
+    }
+
+    #[test]
+    fn tiers_are_bounded_by_retention() {
+        let mut r = rollups(120);
+        for i in 0..1000u64 {
+            r.add("cpu", i * 1000, i as f64);
+        }
+        assert_eq!(r.buckets("cpu", Zoom::Raw, u64::MAX).len(), 120);
+        assert_eq!(r.buckets("cpu", Zoom::TenSeconds, u64::MAX).len(), 12);
+        assert_eq!(r.buckets("cpu", Zoom::Minute, u64::MAX).len(), 2);
+    }
+
+    #[test]
+    fn series_is_padded_and_ends_at_until() {
+        let mut r = rollups(600);
+        for i in 0..5u64 {
+            r.add("cpu", i * 1000, i as f64);
+        }
+        assert_eq!(r.series("cpu", Zoom::Raw, 2000, 4, 0), vec![0, 0, 1, 2]);
+        assert_eq!(r.series("missing", Zoom::Raw, 2000, 2, 0), vec![0, 0]);
+    }
+
+    #[test]
+    fn series_scrolls_back_to_the_oldest_bucket() {
+        let mut r = rollups(600);
+        for i in 0..10u64 {
+            r.add("cpu", i * 1000, i as f64);
+        }
+        assert_eq!(r.series("cpu", Zoom::Raw, u64::MAX, 3, 2), vec![5, 6, 7]);
+        assert_eq!(r.series("cpu", Zoom::Raw, u64::MAX, 3, 99), vec![0, 1, 2]);
+        assert_eq!(
+            r.series("cpu", Zoom::Raw, u64::MAX, 20, 5),
+            r.series("cpu", Zoom::Raw, u64::MAX, 20, 0)
+        );
+        assert_eq!(r.capacity(Zoom::Raw), 600);
+        assert_eq!(r.bucket_width(Zoom::TenSeconds, 3), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn coarse_zooms_keep_spikes() {
+        let mut r = rollups(24 * 3600);
+        // Idle for a day but for one spike twelve hours in.
+        for minute in 0..24 * 60u64 {
+            let value = if minute == 12 * 60 { 95.0 } else { 5.0 };
+            r.add("cpu", minute * 60_000 + 1, value);
+        }
+        let until = 24 * 3_600_000;
+        let minute = r.series("cpu", Zoom::Minute, until, 24 * 60, 0);
+        assert_eq!(minute.iter().filter(|&&v| v == 95).count(), 1);
+
+        // The whole day fits 48 points of half an hour each.
+        assert_eq!(r.tier(Zoom::All), 2);
+        assert_eq!(r.bucket_width(Zoom::All, 48), Duration::from_secs(1800));
+        let all = r.series("cpu", Zoom::All, until, 48, 0);
+        assert_eq!(all.len(), 48);
+        assert_eq!(all[24], 95);
+        assert!(all.iter().enumerate().all(|(i, &v)| i == 24 || v == 5));
+    }
+}
@@ -0,0 +1,139 @@
+//! systemd service units from `systemctl`, and restarting or stopping them.
+
+use serde::{Deserialize, Serialize};
+use std::process::Command;
+
+/// A service unit as `systemctl list-units` reports it.
+#[derive(Clone, Default, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServiceUnit {
+    /// Such as `ssh.service`; also the name of its cgroup.
+    pub unit: String,
+    /// `loaded`, `not-found`, `masked`, ...
+    pub load: String,
+    /// `active`, `inactive`, `failed`, `activating`, ...
+    pub active: String,
+    /// The finer state: `running`, `exited`, `dead`, ...
+    pub sub: String,
+    pub description: String,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ServiceAction {
+    Restart,
+    Stop,
+}
+
+impl ServiceAction {
+    pub fn verb(self) -> &'static str {
+        match self {
+            ServiceAction::Restart => "restart",
+            ServiceAction::Stop => "stop",
+        }
+    }
+}
+
+/// Parses `systemctl list-units --output=json`.
+pub fn parse_json(text: &str) -> Result<Vec<ServiceUnit>, String> {
+    serde_json::from_str(text).map_err(|err| format!("bad systemctl output: {}", err))
+}
+
+/// Parses `systemctl list-units --plain --no-legend`, for systemd versions
+/// without JSON output: unit, load, active and sub state, then the
+/// description.
+pub fn parse_plain(text: &str) -> Vec<ServiceUnit> {
+    text.lines()
+        .filter_map(|line| {
+            // Failed units are marked with a bullet unless --plain is honored.
+            let line = line.trim_start().trim_start_matches('●').trim_start();
+            let mut rest = line;
+            let mut fields = [""; 4];
+            for field in &mut fields {
+                let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                *field = &rest[..end];
+                rest = rest[end..].trim_start();
+            }
+            let [unit, load, active, sub] = fields;
+            // Every unit name has a type suffix, unlike a summary line.
+            if sub.is_empty() || !unit.contains('.') {
+                return None;
+            }
+            Some(ServiceUnit {
+                unit: unit.to_string(),
+                load: load.to_string(),
+                active: active.to_string(),
+                sub: sub.to_string(),
+                description: rest.trim_end().to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Every service unit systemd has loaded, active or not.
+pub fn list_units() -> Result<Vec<ServiceUnit>, String> {
+    let base = ["list-units", "--type=service", "--all", "--no-pager"];
+    let json = run(&[&base[..], &["--output=json"]].concat()).and_then(|text| parse_json(&text));
+    match json {
+        Ok(units) => Ok(units),
+        // Older systemd versions have no JSON output for list-units.
+        Err(_) => Ok(parse_plain(&run(&[
+            &base[..],
+            &["--plain", "--no-legend"],
+        ]
+        .concat())?)),
+    }
+}
+
+/// Queues `action` on `unit` without waiting for it to finish or asking
+/// for a password on the terminal.
+pub fn control(action: ServiceAction, unit: &str) -> Result<(), String> {
+    run(&["--no-ask-password", "--no-block", action.verb(), "--", unit]).map(|_| ())
+}
+
+fn run(args: &[&str]) -> Result<String, String> {
+    let output = Command::new("systemctl")
+        .args(args)
+        .output()
+        .map_err(|err| format!("systemctl: {}", err))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(stderr
+            .lines()
+            .next()
+            .unwrap_or("systemctl failed")
+            .to_string());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> String {
+        std::fs::read_to_string(
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("tests/fixtures/services")
+                .join(name),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn parses_json_output() {
+        let units = parse_json(&fixture("list-units.json")).unwrap();
+        assert_eq!(units.len(), 7);
+        assert_eq!(units[0].unit, "cron.service");
+        assert_eq!(units[4].active, "failed");
+        assert_eq!(units[5].load, "not-found");
+        assert_eq!(units[6].description, "OpenBSD Secure Shell server");
+        assert!(parse_json("ssh.service loaded active running").is_err());
+    }
+
+    #[test]
+    fn plain_output_matches_json() {
+        let plain = parse_plain(&fixture("list-units.txt"));
+        assert_eq!(plain, parse_json(&fixture("list-units.json")).unwrap());
+        assert!(parse_plain("\n0 loaded units listed.\n").is_empty());
+    }
+}
@@ -0,0 +1,257 @@
+use crate::alert::Alert;
+use crate::app::ProcessInfo;
+use crate::system::cgroups::CgroupSample;
+use crate::system::meminfo::MemInfo;
+use crate::system::power::PowerSample;
+use crate::system::psi::{CgroupPressure, Pressure};
+use crate::system::services::ServiceUnit;
+use crate::system::swap::SwapSample;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+/// Everything the UI shows for one tick. Fields default when missing so
+/// older recordings still load.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Snapshot {
+    /// Position in the history; assigned by `History::push`.
+    pub seq: u64,
+    /// Unix time in milliseconds.
+    pub timestamp: u64,
+    pub system: SystemSample,
+    pub overall_cpu: f32,
+    pub cpus: Vec<CpuSample>,
+    pub memory: MemorySample,
+    pub swap: SwapSample,
+    /// Sorted by interface name.
+    pub networks: Vec<NetworkSample>,
+    pub gpus: Vec<GpuSample>,
+    pub disks: Vec<DiskSample>,
+    /// Batteries and AC adapter.
+    pub power: PowerSample,
+    /// `None` on kernels without pressure stall information.
+    pub pressure: Option<Pressure>,
+    pub cgroup_pressure: Option<CgroupPressure>,
+    /// Every cgroup, parents first; empty unless the Containers or Services view is open.
+    pub cgroups: Vec<CgroupSample>,
+    /// systemd services; empty unless the Services view is open.
+    pub services: Vec<ServiceUnit>,
+    /// Alert rules firing as of this sample.
+    pub alerts: Vec<Alert>,
+    /// Every process, threads included.
+    pub processes: Vec<ProcessInfo>,
+}
+
+/// Host and CPU identity, load and task counts.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SystemSample {
+    pub host: String,
+    pub os: String,
+    pub kernel: String,
+    pub cpu_brand: String,
+    pub cpu_vendor: String,
+    /// Unix time in seconds.
+    pub boot_time: u64,
+    /// Seconds since boot.
+    pub uptime: u64,
+    /// 1, 5 and 15 minute load averages.
+    pub load: [f64; 3],
+    pub tasks: TaskCounts,
+}
+
+/// Processes by state, plus the total number of threads.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TaskCounts {
+    pub total: usize,
+    pub threads: usize,
+    pub running: usize,
+    pub sleeping: usize,
+    pub stopped: usize,
+    pub zombie: usize,
+}
+
+impl TaskCounts {
+    pub fn count(processes: &[ProcessInfo]) -> TaskCounts {
+        let mut counts = TaskCounts::default();
+        for p in processes {
+            // A process already stands for its main thread.
+            if p.thread_of == Some(p.pid) {
+                continue;
+            }
+            counts.threads += 1;
+            if p.thread_of.is_some() {
+                continue;
+            }
+            counts.total += 1;
+            match p.status.as_str() {
+                "Run" => counts.running += 1,
+                "Zombie" => counts.zombie += 1,
+                "Stop" | "Tracing" => counts.stopped += 1,
+                _ => counts.sleeping += 1,
+            }
+        }
+        counts
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CpuSample {
+    pub name: String,
+    pub usage: f32,
+    /// Current frequency in MHz.
+    pub frequency: u64,
+    /// Scaling governor and limits; `None` without cpufreq.
+    pub governor: Option<String>,
+    pub min_frequency: Option<u64>,
+    pub max_frequency: Option<u64>,
+    /// Thermally throttled since the previous sample.
+    pub throttled: bool,
+    pub socket: Option<u32>,
+    /// Physical core within the socket, shared by SMT siblings.
+    pub core: Option<u32>,
+    pub node: Option<u32>,
+}
+
+/// Sizes in bytes.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MemorySample {
+    pub total: u64,
+    pub used: u64,
+    pub available: u64,
+    pub total_swap: u64,
+    pub used_swap: u64,
+    pub free_swap: u64,
+    /// `None` where `/proc/meminfo` is unavailable.
+    pub details: Option<MemInfo>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NetworkSample {
+    pub name: String,
+    /// Bytes received since the previous sample.
+    pub rx_bytes: u64,
+    /// Bytes transmitted since the previous sample.
+    pub tx_bytes: u64,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GpuSample {
+    pub name: String,
+    pub usage: f32,
+    pub mem_used_mb: f64,
+    pub mem_total_mb: f64,
+    pub temp_c: u32,
+}
+
+/// A mounted filesystem; sizes in bytes.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiskSample {
+    pub mount: String,
+    pub total: u64,
+    pub available: u64,
+}
+
+/// Ring buffer of the most recent snapshots. Never empty.
+pub struct History {
+    snapshots: VecDeque<Arc<Snapshot>>,
+    capacity: usize,
+}
+
+impl History {
+    pub fn new(capacity: usize, mut first: Snapshot) -> Self {
+        let capacity = capacity.max(1);
+        first.seq = 0;
+        let mut snapshots = VecDeque::with_capacity(capacity);
+        snapshots.push_back(Arc::new(first));
+        Self {
+            snapshots,
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, mut snapshot: Snapshot) {
+        snapshot.seq = self.latest().seq + 1;
+        if self.snapshots.len() >= self.capacity {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(Arc::new(snapshot));
+    }
+
+    pub fn latest(&self) -> &Arc<Snapshot> {
+        self.snapshots.back().expect("history is never empty")
+    }
+
+    pub fn oldest(&self) -> &Arc<Snapshot> {
+        self.snapshots.front().expect("history is never empty")
+    }
+
+    /// The snapshot with sequence number `seq`, clamped to what is retained.
+    pub fn get(&self, seq: u64) -> &Arc<Snapshot> {
+        let oldest = self.oldest().seq;
+        let index = seq.saturating_sub(oldest) as usize;
+        &self.snapshots[index.min(self.snapshots.len() - 1)]
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(timestamp: u64) -> Snapshot {
+        Snapshot {
+            timestamp,
+            ..Snapshot::default()
+        }
+    }
+
+    #[test]
+    fn history_numbers_snapshots_and_keeps_the_newest() {
+        let mut history = History::new(3, at(100));
+        assert_eq!(history.len(), 1);
+        assert!(!history.is_empty());
+        assert_eq!(history.latest().seq, 0);
+
+        for timestamp in [200, 300, 400, 500] {
+            history.push(at(timestamp));
+        }
+        assert_eq!(history.len(), 3);
+        assert_eq!(history.capacity(), 3);
+        assert_eq!(history.oldest().seq, 2);
+        assert_eq!(history.oldest().timestamp, 300);
+        assert_eq!(history.latest().seq, 4);
+        assert_eq!(history.latest().timestamp, 500);
+
+        // Sequence numbers outside what is retained are clamped.
+        assert_eq!(history.get(3).timestamp, 400);
+        assert_eq!(history.get(0).seq, 2);
+        assert_eq!(history.get(99).seq, 4);
+    }
+
+    #[test]
+    fn history_keeps_at_least_one_snapshot() {
+        let mut history = History::new(0, at(100));
+        history.push(at(200));
+        assert_eq!(history.len(), 1);
+        assert_eq!(history.latest().timestamp, 200);
+    }
+}
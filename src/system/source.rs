@@ -0,0 +1,214 @@
+//! Where snapshots come from: the live system, optionally recorded to a
+//! file and logged as CSV, a recording being replayed, or an agent on
+//! another machine.
+
+use crate::alert::AlertEngine;
+use crate::app::{AppState, BottomView};
+use crate::hooks::Hooks;
+use crate::system::collector::Collector;
+use crate::system::csvlog::CsvLog;
+use crate::system::recording::{Player, Recorder};
+use crate::system::remote::{Remote, Settings};
+use crate::system::rollup::Rollups;
+use crate::system::snapshot::Snapshot;
+use color_eyre::Result;
+use crossterm::event::KeyCode;
+use std::time::Duration;
+
+pub enum Source {
+    Live(Box<Live>),
+    Replay(Box<Player>),
+    Remote(Remote),
+}
+
+pub struct Live {
+    pub collector: Collector,
+    pub interval: Duration,
+    pub recorder: Option<Recorder>,
+    pub csv: Option<CsvLog>,
+    pub alerts: AlertEngine,
+    pub hooks: Hooks,
+}
+
+impl Source {
+    /// The first snapshot to show.
+    pub fn first(&mut self) -> Result<Snapshot> {
+        match self {
+            Source::Live(live) => {
+                let Live {
+                    collector,
+                    recorder,
+                    csv,
+                    alerts,
+                    hooks,
+                    ..
+                } = live.as_mut();
+                let mut snapshot = collector.collect();
+                hooks.dispatch(&alerts.evaluate(&mut snapshot));
+                if let Some(recorder) = recorder {
+                    recorder.write(&snapshot)?;
+                }
+                if let Some(csv) = csv {
+                    csv.write(&snapshot)?;
+                }
+                Ok(snapshot)
+            }
+            Source::Replay(player) => player.current(),
+            // The agent sends its latest sample on connecting.
+            Source::Remote(remote) => remote.wait(remote.interval() + Duration::from_secs(5)),
+        }
+    }
+
+    /// Interval between samples, as recorded for a replay.
+    pub fn interval(&self) -> Duration {
+        match self {
+            Source::Live(live) => live.interval,
+            Source::Replay(player) => player.interval(),
+            Source::Remote(remote) => remote.interval(),
+        }
+    }
+
+    /// Wall-clock time between ticks, which follows the replay speed.
+    pub fn tick_rate(&self) -> Duration {
+        match self {
+            Source::Live(live) => live.interval,
+            Source::Replay(player) => player.tick_rate(),
+            Source::Remote(remote) => remote.interval(),
+        }
+    }
+
+    /// Hands the next sample, if any, to `app`.
+    pub fn tick(&mut self, app: &mut AppState) {
+        match self {
+            Source::Live(live) => {
+                let Live {
+                    collector,
+                    recorder,
+                    csv,
+                    alerts,
+                    hooks,
+                    ..
+                } = live.as_mut();
+                collector.set_pressure_cgroup(app.viewed_cgroup());
+                collector.set_walk_cgroups(app.bottom_view != BottomView::Processes);
+                collector.set_list_services(app.bottom_view == BottomView::Services);
+                collector.set_smaps_pids(app.smaps_pids());
+                let mut snapshot = collector.collect();
+                if let Some(err) = collector.take_engine_error() {
+                    app.status_message = Some(format!("Container names unavailable: {}", err));
+                }
+                if let Some(err) = collector.take_services_error() {
+                    app.status_message = Some(format!("Services unavailable: {}", err));
+                }
+                hooks.dispatch(&alerts.evaluate(&mut snapshot));
+                if let Some(r) = recorder {
+                    if let Err(err) = r.write(&snapshot) {
+                        app.status_message = Some(format!("Recording stopped: {}", err));
+                        *recorder = None;
+                    }
+                }
+                if let Some(log) = csv {
+                    if let Err(err) = log.write(&snapshot) {
+                        app.status_message = Some(format!("CSV logging stopped: {:#}", err));
+                        *csv = None;
+                    }
+                }
+                if let Some(failure) = hooks.failure_message() {
+                    app.status_message = Some(failure);
+                }
+                app.push_snapshot(snapshot);
+            }
+            Source::Replay(player) => {
+                match player.advance() {
+                    Ok(Some(snapshot)) => app.push_snapshot(snapshot),
+                    Ok(None) => {}
+                    Err(err) => app.status_message = Some(format!("Replay stopped: {:#}", err)),
+                }
+                app.replay = Some(player.status());
+            }
+            Source::Remote(remote) => {
+                let sent = remote.send_settings(Settings {
+                    pressure_cgroup: app.viewed_cgroup(),
+                    walk_cgroups: app.bottom_view != BottomView::Processes,
+                    list_services: app.bottom_view == BottomView::Services,
+                    smaps_pids: app.smaps_pids(),
+                });
+                match sent.and_then(|()| remote.receive()) {
+                    Ok(snapshots) => {
+                        for snapshot in snapshots {
+                            app.push_snapshot(snapshot);
+                        }
+                    }
+                    Err(err) => app.status_message = Some(format!("Disconnected: {}", err)),
+                }
+            }
+        }
+    }
+
+    /// Handles replay controls; returns false for keys it leaves to the app.
+    pub fn handle_key(&mut self, app: &mut AppState, code: KeyCode) -> bool {
+        let Source::Replay(player) = self else {
+            return false;
+        };
+        let seeked = match code {
+            KeyCode::Char(' ') => player.toggle_playing(),
+            KeyCode::Char('[') => {
+                player.seek(-1);
+                true
+            }
+            KeyCode::Char(']') => {
+                player.seek(1);
+                true
+            }
+            KeyCode::Char('{') => {
+                player.seek(-10);
+                true
+            }
+            KeyCode::Char('}') => {
+                player.seek(10);
+                true
+            }
+            KeyCode::Char(c @ '0'..='9') => {
+                player.seek_fraction(c as usize - '0' as usize);
+                true
+            }
+            KeyCode::Char('<') | KeyCode::Char(',') => {
+                player.change_speed(false);
+                false
+            }
+            KeyCode::Char('>') | KeyCode::Char('.') => {
+                player.change_speed(true);
+                false
+            }
+            _ => return false,
+        };
+        if seeked {
+            // Charts come from the index; only the history is decoded.
+            let mut rollups = Rollups::new(app.rollups.retention(), player.interval());
+            for (timestamp, points) in player.recent_points(app.rollups.retention()) {
+                rollups.record_points(timestamp, points);
+            }
+            let mut failed = None;
+            let recent = player
+                .recent(app.history.capacity())
+                .map_while(|sample| sample.map_err(|err| failed = Some(err)).ok());
+            app.reset_history(rollups, recent);
+            if let Some(err) = failed {
+                app.status_message = Some(format!("Replay stopped: {:#}", err));
+            }
+        }
+        app.replay = Some(player.status());
+        true
+    }
+
+    /// Completes the recording file, if recording.
+    pub fn finish(self) -> Result<()> {
+        match self {
+            Source::Live(live) => match live.recorder {
+                Some(recorder) => recorder.finish(),
+                None => Ok(()),
+            },
+            Source::Replay(_) | Source::Remote(_) => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,202 @@
+//! Swap devices from `/proc/swaps`, zram devices from `/sys/block`, zswap,
+//! and swap-in/out counters from `/proc/vmstat`.
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+pub const PROC_SWAPS: &str = "/proc/swaps";
+pub const SYS_BLOCK: &str = "/sys/block";
+pub const PROC_VMSTAT: &str = "/proc/vmstat";
+pub const ZSWAP_ENABLED: &str = "/sys/module/zswap/parameters/enabled";
+
+/// Sizes in bytes.
+#[derive(Clone, Default, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SwapDevice {
+    pub name: String,
+    /// `partition` or `file`.
+    pub kind: String,
+    pub size: u64,
+    pub used: u64,
+    /// Higher priorities are used first.
+    pub priority: i32,
+}
+
+/// A compressed RAM block device, in bytes.
+#[derive(Clone, Default, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ZramDevice {
+    pub name: String,
+    pub disksize: u64,
+    /// Data stored, before compression.
+    pub original: u64,
+    pub compressed: u64,
+    /// RAM taken, including allocator overhead.
+    pub mem_used: u64,
+}
+
+impl ZramDevice {
+    pub fn ratio(&self) -> f64 {
+        if self.compressed == 0 {
+            0.0
+        } else {
+            self.original as f64 / self.compressed as f64
+        }
+    }
+}
+
+/// The compressed cache in front of swap, in bytes.
+#[derive(Clone, Default, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Zswap {
+    pub enabled: bool,
+    /// RAM taken by the compressed pool.
+    pub pool: u64,
+    /// Data stored, before compression.
+    pub stored: u64,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SwapSample {
+    pub devices: Vec<SwapDevice>,
+    pub zram: Vec<ZramDevice>,
+    /// `None` when the kernel has no zswap.
+    pub zswap: Option<Zswap>,
+    /// Pages swapped in per second since the previous sample.
+    pub pages_in: f64,
+    /// Pages swapped out per second since the previous sample.
+    pub pages_out: f64,
+}
+
+/// Active swap areas. Sizes in `/proc/swaps` are in KiB and spaces in
+/// names are escaped as `\040`.
+pub fn parse_swaps(text: &str) -> Vec<SwapDevice> {
+    text.lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [name, kind, size, used, priority] = fields[..] else {
+                return None;
+            };
+            Some(SwapDevice {
+                name: name.replace("\\040", " "),
+                kind: kind.to_string(),
+                size: size.parse::<u64>().ok()? * 1024,
+                used: used.parse::<u64>().ok()? * 1024,
+                priority: priority.parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+pub fn read_swaps(path: &Path) -> Vec<SwapDevice> {
+    std::fs::read_to_string(path)
+        .map(|text| parse_swaps(&text))
+        .unwrap_or_default()
+}
+
+/// Initialized zram devices under `sys_block`, by name.
+pub fn read_zram(sys_block: &Path) -> Vec<ZramDevice> {
+    let Ok(entries) = std::fs::read_dir(sys_block) else {
+        return Vec::new();
+    };
+    let mut devices: Vec<ZramDevice> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !name.starts_with("zram") {
+                return None;
+            }
+            let read = |file: &str| std::fs::read_to_string(entry.path().join(file)).ok();
+            let disksize: u64 = read("disksize")?.trim().parse().ok()?;
+            if disksize == 0 {
+                return None;
+            }
+            // orig_data_size compr_data_size mem_used_total mem_limit ...
+            let stat = read("mm_stat")?;
+            let mut fields = stat.split_whitespace().map(|f| f.parse::<u64>().ok());
+            Some(ZramDevice {
+                name,
+                disksize,
+                original: fields.next()??,
+                compressed: fields.next()??,
+                mem_used: fields.next()??,
+            })
+        })
+        .collect();
+    devices.sort_by(|a, b| a.name.cmp(&b.name));
+    devices
+}
+
+/// Whether zswap is enabled, or `None` if the kernel lacks it.
+pub fn read_zswap_enabled(path: &Path) -> Option<bool> {
+    let text = std::fs::read_to_string(path).ok()?;
+    Some(matches!(text.trim(), "Y" | "1"))
+}
+
+/// Total pages swapped in and out since boot.
+pub fn parse_vmstat(text: &str) -> Option<(u64, u64)> {
+    let mut pages_in = None;
+    let mut pages_out = None;
+    for line in text.lines() {
+        match line.split_once(' ') {
+            Some(("pswpin", value)) => pages_in = value.trim().parse().ok(),
+            Some(("pswpout", value)) => pages_out = value.trim().parse().ok(),
+            _ => {}
+        }
+    }
+    Some((pages_in?, pages_out?))
+}
+
+pub fn read_vmstat(path: &Path) -> Option<(u64, u64)> {
+    parse_vmstat(&std::fs::read_to_string(path).ok()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn fixtures() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/swap")
+    }
+
+    #[test]
+    fn reads_swap_devices() {
+        let devices = read_swaps(&fixtures().join("swaps"));
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[0].name, "/dev/zram0");
+        assert_eq!(devices[0].kind, "partition");
+        assert_eq!(devices[0].used, 2_516_480 * 1024);
+        assert_eq!(devices[0].priority, 100);
+        assert_eq!(devices[1].priority, -2);
+        assert_eq!(
+            parse_swaps("Filename Type Size Used Priority\n/mnt/my\\040swap file 8 4 -3\n")[0].name,
+            "/mnt/my swap"
+        );
+    }
+
+    #[test]
+    fn reads_initialized_zram_devices() {
+        let zram = read_zram(&fixtures().join("block"));
+        assert_eq!(zram.len(), 1);
+        assert_eq!(zram[0].name, "zram0");
+        assert_eq!(zram[0].original, 2_576_980_377);
+        assert_eq!(zram[0].mem_used, 655_360_000);
+        assert!((zram[0].ratio() - 4.1).abs() < 0.01);
+    }
+
+    #[test]
+    fn reads_zswap_and_vmstat() {
+        assert_eq!(
+            read_zswap_enabled(&fixtures().join("zswap/enabled")),
+            Some(true)
+        );
+        assert_eq!(read_zswap_enabled(&fixtures().join("missing")), None);
+        assert_eq!(
+            read_vmstat(&fixtures().join("vmstat")),
+            Some((18_231, 52_007))
+        );
+    }
+}
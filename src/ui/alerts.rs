@@ -0,0 +1,86 @@
+use crate::{
+    alert::EventKind,
+    app::AppState,
+    ui::{build_block, centered_rect, format_time, ALERT_COLOR},
+};
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Clear, Paragraph},
+    Frame,
+};
+
+/// Firing alerts followed by the fire/resolve history, newest first.
+pub fn render(f: &mut Frame, app: &AppState) {
+    if !app.show_alerts {
+        return;
+    }
+
+    let snapshot = app.snapshot();
+    let bold = Style::default().add_modifier(Modifier::BOLD);
+    let mut lines = vec![Line::styled(
+        format!("Firing ({})", snapshot.alerts.len()),
+        bold,
+    )];
+    if snapshot.alerts.is_empty() {
+        lines.push(Line::styled(
+            "  nothing firing",
+            Style::default().fg(Color::DarkGray),
+        ));
+    }
+    for alert in &snapshot.alerts {
+        let secs = snapshot.timestamp.saturating_sub(alert.since) / 1000;
+        lines.push(Line::from(vec![
+            Span::styled("  ● ", Style::default().fg(ALERT_COLOR)),
+            Span::styled(format!("{:<14} ", alert.rule), bold),
+            Span::raw(format!(
+                "{} = {}  ({})  for {}s",
+                alert.metric,
+                format_value(alert.value),
+                alert.condition,
+                secs
+            )),
+        ]));
+    }
+
+    lines.push(Line::raw(""));
+    lines.push(Line::styled("History", bold));
+    if app.alert_log.is_empty() {
+        lines.push(Line::styled(
+            "  no alerts yet",
+            Style::default().fg(Color::DarkGray),
+        ));
+    }
+    for event in app.alert_log.iter().rev() {
+        let (label, color) = match event.kind {
+            EventKind::Fired => ("FIRED   ", ALERT_COLOR),
+            EventKind::Resolved => ("RESOLVED", Color::Green),
+        };
+        lines.push(Line::from(vec![
+            Span::raw(format!("  {}  ", format_time(event.at))),
+            Span::styled(label, Style::default().fg(color)),
+            Span::raw(format!(
+                "  {:<14} {} = {}",
+                event.alert.rule,
+                event.alert.metric,
+                format_value(event.alert.value)
+            )),
+        ]));
+    }
+
+    let area = centered_rect(96, lines.len() as u16 + 2, f.size());
+    let p = Paragraph::new(lines)
+        .style(Style::default().fg(Color::Gray))
+        .block(build_block(" Alerts  [Esc]close "));
+    f.render_widget(Clear, area);
+    f.render_widget(p, area);
+}
+
+/// Whole numbers for large values such as byte counts, one decimal otherwise.
+fn format_value(value: f64) -> String {
+    if value.abs() >= 1000.0 {
+        format!("{:.0}", value)
+    } else {
+        format!("{:.1}", value)
+    }
+}
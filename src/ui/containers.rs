@@ -0,0 +1,146 @@
+use crate::{
+    app::AppState,
+    system::cgroups::{CgroupKind, CgroupSample},
+    ui::{
+        build_block,
+        processes::{format_mb, format_rate},
+    },
+};
+use ratatui::{
+    layout::{Constraint, Rect},
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{Paragraph, Row, Table},
+    Frame,
+};
+use std::collections::HashMap;
+
+/// The cgroup tree with the usage of each cgroup, indented by depth.
+pub fn render(f: &mut Frame, app: &mut AppState, area: Rect) {
+    let snapshot = app.snapshot().clone();
+    let cgroups = &snapshot.cgroups;
+    let title = format!(
+        " Containers ({} cgroups) [Enter]processes [Tab]services ",
+        cgroups.len()
+    );
+    let block = build_block(&title);
+    // Borders, header and its bottom margin take four lines.
+    app.cgroup_page_size = area.height.saturating_sub(4) as usize;
+
+    if cgroups.is_empty() {
+        let text = if app.replay.is_some() {
+            "This recording has no cgroups: they are only recorded while the Containers view is open."
+        } else {
+            "Reading cgroups... (nothing to show without a cgroup v2 hierarchy)"
+        };
+        let p =
+            Paragraph::new(Line::styled(text, Style::default().fg(Color::DarkGray))).block(block);
+        f.render_widget(p, area);
+        return;
+    }
+
+    let processes = process_counts(app);
+    let header = Row::new(vec![
+        "Cgroup", "Kind", "Image", "Project", "CPU%", "Thrtl%", "MEM", "MAX", "Read/s", "Write/s",
+        "Tasks", "Procs",
+    ])
+    .style(
+        Style::default()
+            .fg(Color::Cyan)
+            .add_modifier(Modifier::BOLD),
+    )
+    .bottom_margin(1);
+
+    let rows: Vec<Row> = cgroups
+        .iter()
+        .map(|c| {
+            let opt = |v: Option<u64>, f: fn(u64) -> String| v.map_or("-".to_string(), f);
+            let procs = processes.get(c.path.as_str()).copied().unwrap_or(0);
+            let mut style = Style::default();
+            if c.throttled > 0.0 {
+                style = style.fg(Color::Yellow);
+            } else if procs == 0 {
+                style = style.fg(Color::DarkGray);
+            }
+            if c.container_id.is_some() || c.kind == CgroupKind::Pod {
+                style = style.add_modifier(Modifier::BOLD);
+            }
+            Row::new(vec![
+                format!("{}{}", "  ".repeat(c.depth()), label(c)),
+                c.kind.label().to_string(),
+                c.container
+                    .as_ref()
+                    .map_or_else(String::new, |i| i.image.clone()),
+                c.container
+                    .as_ref()
+                    .and_then(|i| i.project.clone())
+                    .unwrap_or_default(),
+                format!("{:.1}", c.cpu_usage),
+                format!("{:.1}", c.throttled),
+                opt(c.memory, format_mb),
+                opt(c.memory_max, format_mb),
+                format_rate(c.read_rate),
+                format_rate(c.write_rate),
+                opt(c.pids, |v| v.to_string()),
+                procs.to_string(),
+            ])
+            .style(style)
+        })
+        .collect();
+
+    let widths = [
+        Constraint::Min(24),
+        Constraint::Length(10),
+        Constraint::Length(20),
+        Constraint::Length(12),
+        Constraint::Length(7),
+        Constraint::Length(7),
+        Constraint::Length(11),
+        Constraint::Length(11),
+        Constraint::Length(11),
+        Constraint::Length(11),
+        Constraint::Length(6),
+        Constraint::Length(6),
+    ];
+    let table = Table::new(rows, widths)
+        .header(header)
+        .block(block)
+        .highlight_style(
+            Style::default()
+                .bg(Color::DarkGray)
+                .fg(Color::Cyan)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol(">> ");
+
+    app.cgroup_table_state.select(Some(app.cgroup_index()));
+    f.render_stateful_widget(table, area, &mut app.cgroup_table_state);
+}
+
+/// The container name, else the last path component with container IDs
+/// cut to the 12 characters `docker ps` shows.
+fn label(c: &CgroupSample) -> String {
+    match (&c.container, &c.container_id) {
+        (Some(info), _) if !info.name.is_empty() => info.name.clone(),
+        (_, Some(id)) => id[..12.min(id.len())].to_string(),
+        _ => c.name().to_string(),
+    }
+}
+
+/// Processes in each cgroup and the cgroups below it, by path.
+fn process_counts(app: &AppState) -> HashMap<&str, usize> {
+    let mut counts = HashMap::new();
+    for p in &app.snapshot().processes {
+        if p.thread_of.is_some() || p.cgroup.is_empty() {
+            continue;
+        }
+        *counts.entry("/").or_insert(0) += 1;
+        for (i, _) in p.cgroup.match_indices('/').skip(1) {
+            *counts.entry(&p.cgroup[..i]).or_insert(0) += 1;
+        }
+        if p.cgroup != "/" {
+            *counts.entry(p.cgroup.as_str()).or_insert(0) += 1;
+        }
+    }
+    counts
+}
@@ -1,67 +1,361 @@
-use crate::{app::AppState, ui::build_block};
+use crate::{
+    alert::Panel,
+    app::{AppState, CoreGrouping, CpuView},
+    system::{rollup::keys, snapshot::CpuSample},
+    ui::{alert_block, chart_title},
+};
 use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
-    widgets::{Gauge, Sparkline},
+    text::{Line, Span},
+    widgets::{Gauge, Paragraph, Sparkline},
     Frame,
 };
+use std::collections::{BTreeMap, BTreeSet};
 
 pub fn render(f: &mut Frame, app: &AppState, area: Rect) {
-    let block = build_block(" CPU ");
+    let block = alert_block(&chart_title("CPU", app), app, Panel::Cpu);
     let inner_area = block.inner(area);
     f.render_widget(block, area);
 
+    let snapshot = app.snapshot();
+    let rows = core_rows(&snapshot.cpus, app.core_grouping);
+    // Overall gauge, model line and sparkline take four lines.
+    let gauge_lines = inner_area.height.saturating_sub(4) as usize;
+    let view = match app.cpu_view {
+        CpuView::Auto if rows.len() > gauge_lines => CpuView::Heatmap,
+        CpuView::Auto => CpuView::Gauges,
+        view => view,
+    };
+
+    // The heatmaps take the sparkline's lines too.
+    let sparkline_height = if view == CpuView::Gauges { 2 } else { 0 };
     let layout = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
-            Constraint::Length(1), // Overall CPU
-            Constraint::Length(1), // Spacer
-            Constraint::Min(0),    // Cores
-            Constraint::Length(2), // Overall Sparkline
+            Constraint::Length(1),                // Overall CPU
+            Constraint::Length(1),                // Model and topology
+            Constraint::Min(0),                   // Cores
+            Constraint::Length(sparkline_height), // Overall Sparkline
         ])
         .split(inner_area);
 
-    let overall_color = get_color(app.overall_cpu);
+    let overall_color = get_color(snapshot.overall_cpu);
     let overall_gauge = Gauge::default()
         .gauge_style(
             Style::default()
                 .fg(overall_color)
                 .add_modifier(Modifier::BOLD),
         )
-        .percent((app.overall_cpu).clamp(0.0, 100.0) as u16)
-        .label(format!("Overall CPU [{:.1}%]", app.overall_cpu));
+        .percent((snapshot.overall_cpu).clamp(0.0, 100.0) as u16)
+        .label(format!("Overall CPU [{:.1}%]", snapshot.overall_cpu));
     f.render_widget(overall_gauge, layout[0]);
 
-    if app.cpu_history.is_empty() {
+    let info = Paragraph::new(summary(&snapshot.system.cpu_brand, &snapshot.cpus))
+        .style(Style::default().fg(Color::Gray));
+    f.render_widget(info, layout[1]);
+
+    if rows.is_empty() {
         return;
     }
 
-    let core_constraints = vec![Constraint::Length(1); app.cpu_history.len()];
+    match view {
+        CpuView::Heatmap => render_heatmap(f, app, &rows, layout[2]),
+        CpuView::Timeline => render_timeline(f, app, &rows, layout[2]),
+        _ => {
+            render_gauges(f, &rows, layout[2]);
+            let history_data = app.series(keys::CPU, layout[3].width);
+            let spark = Sparkline::default()
+                .data(&history_data)
+                .style(Style::default().fg(Color::Cyan));
+            f.render_widget(spark, layout[3]);
+        }
+    }
+}
+
+/// Number of cores (or groups of them) the panel lists.
+pub fn core_count(app: &AppState) -> usize {
+    core_rows(&app.snapshot().cpus, app.core_grouping).len()
+}
+
+fn render_gauges(f: &mut Frame, rows: &[CoreRow], area: Rect) {
+    let core_constraints = vec![Constraint::Length(1); rows.len()];
     let cores_layout = Layout::default()
         .direction(Direction::Vertical)
         .constraints(core_constraints)
-        .split(layout[2]);
+        .split(area);
 
-    for (i, core) in app.cpu_history.iter().enumerate() {
-        if i >= cores_layout.len() {
-            break;
-        }
-        let color = get_color(core.usage);
+    for (row, chunk) in rows.iter().zip(cores_layout.iter()) {
+        let color = if row.throttled {
+            Color::Red
+        } else {
+            get_color(row.usage)
+        };
         let g = Gauge::default()
             .gauge_style(Style::default().fg(color))
-            .percent((core.usage).clamp(0.0, 100.0) as u16)
-            .label(format!("{} [{:.1}%]", core.core_name, core.usage));
-        f.render_widget(g, cores_layout[i]);
+            .percent((row.usage).clamp(0.0, 100.0) as u16)
+            .label(format!(
+                "{} [{:.1}%]{}",
+                row.label,
+                row.usage,
+                row.details()
+            ));
+        f.render_widget(g, *chunk);
+    }
+}
+
+/// The core under the cursor, or the busiest one.
+fn cursor(app: &AppState, rows: &[CoreRow]) -> usize {
+    match app.cpu_cursor {
+        Some(i) => i.min(rows.len() - 1),
+        None => (0..rows.len())
+            .max_by(|&a, &b| rows[a].usage.total_cmp(&rows[b].usage))
+            .unwrap_or(0),
     }
+}
+
+/// Exact values of the core under the cursor, below the heatmaps.
+fn cursor_line(row: &CoreRow, suffix: &str) -> Line<'static> {
+    Line::from(vec![
+        Span::styled(
+            format!("{} {:.1}%{}", row.label, row.usage, row.details()),
+            Style::default().add_modifier(Modifier::BOLD),
+        ),
+        Span::styled(
+            format!("{}  ( ) select  [v] view", suffix),
+            Style::default().fg(Color::DarkGray),
+        ),
+    ])
+}
 
-    // overall sparkline mapping first core's history as rough average, to avoid creating new state var
-    if let Some(first_core) = app.cpu_history.first() {
-        let history_data: Vec<u64> = first_core.history.iter().copied().collect();
-        let spark = Sparkline::default()
-            .data(&history_data)
-            .style(Style::default().fg(Color::Cyan));
-        f.render_widget(spark, layout[3]);
+/// One colored cell per core, two columns wide when there is room.
+fn render_heatmap(f: &mut Frame, app: &AppState, rows: &[CoreRow], area: Rect) {
+    if area.height < 2 || area.width == 0 {
+        return;
     }
+    let grid_lines = (area.height - 1) as usize;
+    let per_line_wide = (area.width / 2) as usize;
+    let cell_width = if per_line_wide * grid_lines >= rows.len() {
+        2
+    } else {
+        1
+    };
+    let per_line = (area.width / cell_width) as usize;
+    let selected = cursor(app, rows);
+
+    let mut lines: Vec<Line> = rows
+        .chunks(per_line)
+        .take(grid_lines)
+        .enumerate()
+        .map(|(line, cells)| {
+            Line::from(
+                cells
+                    .iter()
+                    .enumerate()
+                    .map(|(i, row)| {
+                        let style = Style::default().bg(heat_color(row.usage));
+                        if line * per_line + i == selected {
+                            let mark = if cell_width == 2 { "◆ " } else { "◆" };
+                            Span::styled(mark, style.fg(Color::White))
+                        } else {
+                            Span::styled(" ".repeat(cell_width as usize), style)
+                        }
+                    })
+                    .collect::<Vec<_>>(),
+            )
+        })
+        .collect();
+    lines.resize(grid_lines, Line::raw(""));
+    lines.push(cursor_line(&rows[selected], ""));
+    f.render_widget(Paragraph::new(lines), area);
+}
+
+/// Cores down (two per line, using half blocks) and time across, newest on
+/// the right. Cores that do not fit scroll to keep the cursor in view.
+fn render_timeline(f: &mut Frame, app: &AppState, rows: &[CoreRow], area: Rect) {
+    if area.height < 2 || area.width == 0 {
+        return;
+    }
+    let visible = (area.height - 1) as usize * 2;
+    let selected = cursor(app, rows);
+    let first = selected
+        .saturating_sub(visible / 2)
+        .min(rows.len().saturating_sub(visible));
+    let shown = &rows[first..rows.len().min(first + visible)];
+
+    let width = area.width as usize;
+    let history: Vec<Vec<u64>> = shown
+        .iter()
+        .map(|row| {
+            let series: Vec<Vec<u64>> = row
+                .cpus
+                .iter()
+                .map(|&i| app.series(&keys::core(i), area.width))
+                .collect();
+            (0..width)
+                .map(|t| series.iter().map(|s| s[t]).sum::<u64>() / series.len().max(1) as u64)
+                .collect()
+        })
+        .collect();
+
+    let mut lines: Vec<Line> = history
+        .chunks(2)
+        .map(|pair| {
+            Line::from(
+                (0..width)
+                    .map(|t| {
+                        let mut style = Style::default().fg(heat_color(pair[0][t] as f32));
+                        if let Some(lower) = pair.get(1) {
+                            style = style.bg(heat_color(lower[t] as f32));
+                        }
+                        Span::styled("▀", style)
+                    })
+                    .collect::<Vec<_>>(),
+            )
+        })
+        .collect();
+    lines.resize(area.height as usize - 1, Line::raw(""));
+    let range = format!(
+        "  │ {}-{} of {}",
+        shown[0].label,
+        shown[shown.len() - 1].label,
+        rows.len()
+    );
+    lines.push(cursor_line(&rows[selected], &range));
+    f.render_widget(Paragraph::new(lines), area);
+}
+
+/// Dark blue when idle through yellow at 50% to red when saturated.
+fn heat_color(usage: f32) -> Color {
+    let t = (usage / 100.0).clamp(0.0, 1.0);
+    let (from, to, t) = if t < 0.5 {
+        ((20.0, 40.0, 90.0), (230.0, 200.0, 0.0), t * 2.0)
+    } else {
+        ((230.0, 200.0, 0.0), (255.0, 0.0, 0.0), t * 2.0 - 1.0)
+    };
+    let mix = |a: f32, b: f32| (a + (b - a) * t) as u8;
+    Color::Rgb(mix(from.0, to.0), mix(from.1, to.1), mix(from.2, to.2))
+}
+
+/// Model, topology and scaling policy, e.g. "Ryzen 7 │ 8c/16t, 1 socket │
+/// schedutil 0.4-4.9GHz".
+fn summary(brand: &str, cpus: &[CpuSample]) -> String {
+    let mut parts = Vec::new();
+    if !brand.is_empty() {
+        parts.push(brand.to_string());
+    }
+
+    let sockets: BTreeSet<_> = cpus.iter().filter_map(|c| c.socket).collect();
+    let cores: BTreeSet<_> = cpus
+        .iter()
+        .filter_map(|c| Some((c.socket?, c.core?)))
+        .collect();
+    let nodes: BTreeSet<_> = cpus.iter().filter_map(|c| c.node).collect();
+    if cores.is_empty() {
+        parts.push(plural(cpus.len(), "CPU"));
+    } else {
+        let mut topology = format!(
+            "{}c/{}t, {}",
+            cores.len(),
+            cpus.len(),
+            plural(sockets.len(), "socket")
+        );
+        if nodes.len() > 1 {
+            topology.push_str(&format!(", {} NUMA nodes", nodes.len()));
+        }
+        parts.push(topology);
+    }
+
+    let governors: BTreeSet<_> = cpus.iter().filter_map(|c| c.governor.as_deref()).collect();
+    if !governors.is_empty() {
+        let governors: Vec<_> = governors.into_iter().collect();
+        let min = cpus.iter().filter_map(|c| c.min_frequency).min();
+        let max = cpus.iter().filter_map(|c| c.max_frequency).max();
+        let mut policy = governors.join("/");
+        if let (Some(min), Some(max)) = (min, max) {
+            policy.push_str(&format!(
+                " {:.1}-{:.1}GHz",
+                min as f64 / 1000.0,
+                max as f64 / 1000.0
+            ));
+        }
+        parts.push(policy);
+    }
+    parts.join(" │ ")
+}
+
+/// One core, or group of cores, in the panel.
+struct CoreRow {
+    label: String,
+    /// Indices of the logical CPUs it covers.
+    cpus: Vec<usize>,
+    usage: f32,
+    /// Average of the grouped CPUs, in MHz.
+    frequency: u64,
+    max_frequency: Option<u64>,
+    throttled: bool,
+}
+
+impl CoreRow {
+    /// Frequency against its maximum, and whether it is being throttled.
+    fn details(&self) -> String {
+        let mut text = String::new();
+        if self.frequency > 0 {
+            text.push_str(&format!(" {:.2}", self.frequency as f64 / 1000.0));
+            if let Some(max) = self.max_frequency {
+                text.push_str(&format!("/{:.1}", max as f64 / 1000.0));
+            }
+            text.push_str("GHz");
+        }
+        if self.throttled {
+            text.push_str(" THROTTLED");
+        }
+        text
+    }
+}
+
+/// Cores listed per logical CPU or averaged per physical core or socket.
+/// CPUs whose topology is unknown stay on rows of their own.
+fn core_rows(cpus: &[CpuSample], grouping: CoreGrouping) -> Vec<CoreRow> {
+    let sockets: BTreeSet<_> = cpus.iter().filter_map(|c| c.socket).collect();
+    let mut groups: BTreeMap<(u32, u32, usize), Vec<usize>> = BTreeMap::new();
+    for (i, cpu) in cpus.iter().enumerate() {
+        let key = match (grouping, cpu.socket, cpu.core) {
+            (CoreGrouping::Physical, Some(socket), Some(core)) => (socket, core, 0),
+            (CoreGrouping::Socket, Some(socket), _) => (socket, 0, 0),
+            _ => (u32::MAX, u32::MAX, i),
+        };
+        groups.entry(key).or_default().push(i);
+    }
+
+    groups
+        .into_iter()
+        .map(|((socket, core, _), indices)| {
+            let members: Vec<&CpuSample> = indices.iter().map(|&i| &cpus[i]).collect();
+            let names: Vec<&str> = members.iter().map(|c| c.name.as_str()).collect();
+            let label = match grouping {
+                _ if members.len() == 1 && socket == u32::MAX => names[0].to_string(),
+                CoreGrouping::Physical if sockets.len() > 1 => {
+                    format!("s{} core {} ({})", socket, core, names.join("+"))
+                }
+                CoreGrouping::Physical => format!("core {} ({})", core, names.join("+")),
+                _ => format!("socket {} ({})", socket, plural(members.len(), "CPU")),
+            };
+            let count = members.len() as u64;
+            CoreRow {
+                label,
+                cpus: indices,
+                usage: members.iter().map(|c| c.usage).sum::<f32>() / count as f32,
+                frequency: members.iter().map(|c| c.frequency).sum::<u64>() / count,
+                max_frequency: members.iter().filter_map(|c| c.max_frequency).max(),
+                throttled: members.iter().any(|c| c.throttled),
+            }
+        })
+        .collect()
+}
+
+fn plural(count: usize, noun: &str) -> String {
+    format!("{} {}{}", count, noun, if count == 1 { "" } else { "s" })
 }
 
 fn get_color(usage: f32) -> Color {
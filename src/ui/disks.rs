@@ -0,0 +1,49 @@
+use crate::{alert::Panel, app::AppState, system::snapshot::DiskSample, ui::alert_block};
+use ratatui::{
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Style},
+    widgets::Gauge,
+    Frame,
+};
+
+/// A usage gauge per mounted filesystem.
+pub fn render(f: &mut Frame, app: &AppState, area: Rect) {
+    let block = alert_block(" Disks ", app, Panel::Disk);
+    let inner_area = block.inner(area);
+    f.render_widget(block, area);
+
+    let disks: Vec<&DiskSample> = app
+        .snapshot()
+        .disks
+        .iter()
+        .filter(|d| d.total > 0)
+        .collect();
+    let layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(vec![Constraint::Length(1); disks.len()])
+        .split(inner_area);
+
+    for (disk, area) in disks.into_iter().zip(layout.iter()) {
+        let used = disk.total.saturating_sub(disk.available);
+        let percent = used as f64 / disk.total as f64 * 100.0;
+        let gauge = Gauge::default()
+            .gauge_style(Style::default().fg(usage_color(percent)))
+            .percent(percent.round().clamp(0.0, 100.0) as u16)
+            .label(format!(
+                "{} {:.1}/{:.1} GB ({:.0}%)",
+                disk.mount,
+                used as f64 / 1_073_741_824.0,
+                disk.total as f64 / 1_073_741_824.0,
+                percent
+            ));
+        f.render_widget(gauge, *area);
+    }
+}
+
+fn usage_color(percent: f64) -> Color {
+    match percent {
+        p if p >= 90.0 => Color::Red,
+        p if p >= 75.0 => Color::Yellow,
+        _ => Color::Green,
+    }
+}
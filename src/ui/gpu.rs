@@ -1,4 +1,9 @@
-use crate::{app::AppState, ui::build_block};
+use crate::{
+    alert::Panel,
+    app::AppState,
+    system::rollup::keys,
+    ui::{alert_block, chart_title},
+};
 use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
     style::Color,
@@ -8,15 +13,12 @@ use ratatui::{
 };
 
 pub fn render(f: &mut Frame, app: &AppState, area: Rect) {
-    let block = build_block(" GPU ");
+    let block = alert_block(&chart_title("GPU", app), app, Panel::Gpu);
     let inner_area = block.inner(area);
     f.render_widget(block, area);
 
-    if let Some(gpus) = &app.gpus {
-        if gpus.is_empty() {
-            return;
-        }
-
+    let gpus = &app.snapshot().gpus;
+    if !gpus.is_empty() {
         let layout = Layout::default()
             .direction(Direction::Vertical)
             .constraints([
@@ -61,7 +63,7 @@ pub fn render(f: &mut Frame, app: &AppState, area: Rect) {
             ));
         f.render_widget(mem_gauge, layout[2]);
 
-        let history_data: Vec<u64> = gpu.history.iter().copied().collect();
+        let history_data = app.series(&keys::gpu_util(0), layout[3].width);
         let spark = Sparkline::default()
             .data(&history_data)
             .style(Style::default().fg(usage_color));
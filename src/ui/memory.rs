@@ -1,28 +1,35 @@
-use crate::{app::AppState, ui::build_block};
+use crate::{
+    alert::Panel,
+    app::AppState,
+    system::{meminfo::MemInfo, rollup::keys},
+    ui::{alert_block, build_block, centered_rect, chart_title},
+};
 use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
-    style::Color,
-    style::Style,
-    widgets::{Gauge, Row, Table},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Clear, Gauge, Paragraph, Row, Sparkline, Table},
     Frame,
 };
 
 pub fn render(f: &mut Frame, app: &AppState, area: Rect) {
-    let block = build_block(" Memory ");
+    let block = alert_block(&chart_title("Memory", app), app, Panel::Memory);
     let inner_area = block.inner(area);
     f.render_widget(block, area);
 
     let layout = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
-            Constraint::Length(2), // RAM Gauge
-            Constraint::Length(2), // Swap Gauge
-            Constraint::Min(0),    // Table
+            Constraint::Length(1), // RAM Gauge
+            Constraint::Length(1), // Swap Gauge
+            Constraint::Length(3), // Table
+            Constraint::Min(0),    // Used Sparkline
         ])
         .split(inner_area);
 
-    let ram_used = app.system.used_memory() as f64 / 1_073_741_824.0;
-    let ram_total = app.system.total_memory() as f64 / 1_073_741_824.0;
+    let memory = &app.snapshot().memory;
+    let ram_used = memory.used as f64 / 1_073_741_824.0;
+    let ram_total = memory.total as f64 / 1_073_741_824.0;
     let ram_percent = if ram_total > 0.0 {
         (ram_used / ram_total * 100.0) as u16
     } else {
@@ -38,8 +45,8 @@ pub fn render(f: &mut Frame, app: &AppState, area: Rect) {
         ));
     f.render_widget(ram_gauge, layout[0]);
 
-    let swap_used = app.system.used_swap() as f64 / 1_073_741_824.0;
-    let swap_total = app.system.total_swap() as f64 / 1_073_741_824.0;
+    let swap_used = memory.used_swap as f64 / 1_073_741_824.0;
+    let swap_total = memory.total_swap as f64 / 1_073_741_824.0;
     let swap_percent = if swap_total > 0.0 {
         (swap_used / swap_total * 100.0) as u16
     } else {
@@ -61,16 +68,13 @@ pub fn render(f: &mut Frame, app: &AppState, area: Rect) {
             "RAM".to_string(),
             format!("{:.1} GB", ram_used),
             format!("{:.1} GB", ram_total),
-            format!(
-                "{:.1} GB",
-                app.system.available_memory() as f64 / 1_073_741_824.0
-            ),
+            format!("{:.1} GB", memory.available as f64 / 1_073_741_824.0),
         ]),
         Row::new(vec![
             "SWAP".to_string(),
             format!("{:.1} GB", swap_used),
             format!("{:.1} GB", swap_total),
-            format!("{:.1} GB", app.system.free_swap() as f64 / 1_073_741_824.0),
+            format!("{:.1} GB", memory.free_swap as f64 / 1_073_741_824.0),
         ]),
     ];
 
@@ -84,4 +88,260 @@ pub fn render(f: &mut Frame, app: &AppState, area: Rect) {
         ],
     );
     f.render_widget(table, layout[2]);
+
+    let history_data = app.series(keys::MEM_USED, layout[3].width);
+    let spark = Sparkline::default()
+        .data(&history_data)
+        .max(memory.total)
+        .style(Style::default().fg(Color::Cyan));
+    f.render_widget(spark, layout[3]);
+}
+
+/// Stacked parts of RAM, in the order the bar draws them.
+fn segments(info: &MemInfo) -> [(&'static str, u64, Color); 10] {
+    [
+        ("Apps", info.apps(), Color::Cyan),
+        ("Shmem", info.shmem, Color::Magenta),
+        ("Page cache", info.page_cache(), Color::Blue),
+        ("Buffers", info.buffers, Color::LightBlue),
+        ("Slab (reclaimable)", info.slab_reclaimable, Color::Yellow),
+        (
+            "Slab (unreclaimable)",
+            info.slab_unreclaimable,
+            Color::LightRed,
+        ),
+        ("Kernel stacks", info.kernel_stack, Color::Red),
+        ("Page tables", info.page_tables, Color::LightMagenta),
+        ("Hugepages", info.hugepages, Color::Green),
+        ("Free", info.free, Color::DarkGray),
+    ]
+}
+
+/// The `d` popup: RAM and swap side by side.
+pub fn render_details(f: &mut Frame, app: &AppState) {
+    if !app.show_memory_details {
+        return;
+    }
+
+    let area = centered_rect(124, 26, f.size());
+    let block = build_block(&format!(
+        "{} [Esc]close ",
+        chart_title("Memory details", app)
+    ));
+    let inner = block.inner(area);
+    f.render_widget(Clear, area);
+    f.render_widget(block, area);
+
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(inner);
+    render_breakdown(f, app, columns[0]);
+    render_swap(f, app, columns[1]);
+}
+
+/// RAM broken down from `/proc/meminfo` as a stacked bar and a table, above
+/// a history chart of used memory.
+fn render_breakdown(f: &mut Frame, app: &AppState, area: Rect) {
+    let memory = &app.snapshot().memory;
+    let Some(info) = &memory.details else {
+        let p = Paragraph::new("/proc/meminfo is not available on this system.")
+            .style(Style::default().fg(Color::DarkGray));
+        f.render_widget(p, area);
+        return;
+    };
+
+    let layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(1),  // Stacked bar
+            Constraint::Length(1),  // Spacer
+            Constraint::Length(14), // Table
+            Constraint::Length(1),  // Chart label
+            Constraint::Min(0),     // Used Sparkline
+        ])
+        .split(area);
+
+    let segments = segments(info);
+    f.render_widget(
+        Paragraph::new(stacked_bar(&segments, info.total, layout[0].width)),
+        layout[0],
+    );
+
+    let percent = |bytes: u64| bytes as f64 / info.total.max(1) as f64 * 100.0;
+    let mut rows =
+        vec![Row::new(vec!["", "Size", "% of RAM"]).style(Style::default().fg(Color::DarkGray))];
+    rows.extend(segments.iter().map(|(name, bytes, color)| {
+        Row::new(vec![
+            Line::from(vec![
+                Span::styled("■ ", Style::default().fg(*color)),
+                Span::raw(*name),
+            ]),
+            Line::raw(format_size(*bytes)),
+            Line::raw(format!("{:.1}%", percent(*bytes))),
+        ])
+    }));
+    for (name, bytes) in [
+        ("  of which dirty", info.dirty),
+        ("  of which writeback", info.writeback),
+    ] {
+        rows.push(
+            Row::new(vec![
+                name.to_string(),
+                format_size(bytes),
+                format!("{:.1}%", percent(bytes)),
+            ])
+            .style(Style::default().fg(Color::Gray)),
+        );
+    }
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(24),
+            Constraint::Length(12),
+            Constraint::Length(10),
+        ],
+    );
+    f.render_widget(table, layout[2]);
+
+    f.render_widget(
+        Paragraph::new(format!(
+            "Used: {}  Available: {}  Total: {}",
+            format_size(memory.used),
+            format_size(info.available),
+            format_size(info.total)
+        ))
+        .style(Style::default().add_modifier(Modifier::BOLD)),
+        layout[3],
+    );
+    let history_data = app.series(keys::MEM_USED, layout[4].width);
+    let spark = Sparkline::default()
+        .data(&history_data)
+        .max(memory.total)
+        .style(Style::default().fg(Color::Cyan));
+    f.render_widget(spark, layout[4]);
+}
+
+/// Swap devices, zram and zswap compression, and swap-in/out rates.
+fn render_swap(f: &mut Frame, app: &AppState, area: Rect) {
+    let swap = &app.snapshot().swap;
+    let dim = Style::default().fg(Color::DarkGray);
+    let bold = Style::default().add_modifier(Modifier::BOLD);
+
+    let mut lines = vec![Line::styled("Swap devices", bold)];
+    if swap.devices.is_empty() {
+        lines.push(Line::styled("  no swap in use", dim));
+    }
+    for device in &swap.devices {
+        lines.push(Line::raw(format!(
+            "  {:<16} {:<9} {:>9} / {:<9} prio {}",
+            device.name,
+            device.kind,
+            format_size(device.used),
+            format_size(device.size),
+            device.priority
+        )));
+    }
+
+    if !swap.zram.is_empty() {
+        lines.push(Line::raw(""));
+        lines.push(Line::styled("zram", bold));
+    }
+    for zram in &swap.zram {
+        lines.push(Line::raw(format!(
+            "  {:<7} {} stored in {} ({:.1}x), {} of RAM",
+            zram.name,
+            format_size(zram.original),
+            format_size(zram.compressed),
+            zram.ratio(),
+            format_size(zram.mem_used)
+        )));
+    }
+
+    if let Some(zswap) = &swap.zswap {
+        lines.push(Line::raw(""));
+        let state = if zswap.enabled { "enabled" } else { "disabled" };
+        let text = if zswap.stored > 0 {
+            format!(
+                "zswap {}: {} stored in {} ({:.1}x)",
+                state,
+                format_size(zswap.stored),
+                format_size(zswap.pool),
+                zswap.stored as f64 / zswap.pool.max(1) as f64
+            )
+        } else {
+            format!("zswap {}", state)
+        };
+        lines.push(Line::styled(text, bold));
+    }
+
+    let height = lines.len() as u16 + 1;
+    let layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(height), // Devices
+            Constraint::Length(1),      // Rates
+            Constraint::Min(0),         // Sparklines
+        ])
+        .split(area);
+    f.render_widget(Paragraph::new(lines), layout[0]);
+
+    f.render_widget(
+        Paragraph::new(Line::from(vec![
+            Span::styled("Swap in ", bold),
+            Span::styled(
+                format!("{:.0} pages/s", swap.pages_in),
+                Style::default().fg(Color::Green),
+            ),
+            Span::styled("  out ", bold),
+            Span::styled(
+                format!("{:.0} pages/s", swap.pages_out),
+                Style::default().fg(Color::Yellow),
+            ),
+        ])),
+        layout[1],
+    );
+    let sparks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(layout[2]);
+    for (key, color, chunk) in [
+        (keys::SWAP_IN, Color::Green, sparks[0]),
+        (keys::SWAP_OUT, Color::Yellow, sparks[1]),
+    ] {
+        let data = app.series(key, chunk.width);
+        let spark = Sparkline::default()
+            .data(&data)
+            .style(Style::default().fg(color));
+        f.render_widget(spark, chunk);
+    }
+}
+
+/// One row of `width` cells, each segment taking its share of `total`.
+fn stacked_bar(segments: &[(&str, u64, Color)], total: u64, width: u16) -> Line<'static> {
+    let total = total.max(1) as f64;
+    let mut spans = Vec::new();
+    let mut sum = 0;
+    let mut drawn = 0;
+    for (_, bytes, color) in segments {
+        sum += bytes;
+        let end = ((sum as f64 / total * width as f64).round() as usize).min(width as usize);
+        if end > drawn {
+            spans.push(Span::styled(
+                "█".repeat(end - drawn),
+                Style::default().fg(*color),
+            ));
+            drawn = end;
+        }
+    }
+    Line::from(spans)
+}
+
+fn format_size(bytes: u64) -> String {
+    let b = bytes as f64;
+    if b >= 1_073_741_824.0 {
+        format!("{:.2} GB", b / 1_073_741_824.0)
+    } else {
+        format!("{:.1} MB", b / 1_048_576.0)
+    }
 }
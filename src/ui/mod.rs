@@ -1,13 +1,23 @@
+pub mod alerts;
+pub mod containers;
 pub mod cpu;
+pub mod disks;
 pub mod gpu;
 pub mod memory;
 pub mod network;
+pub mod power;
+pub mod pressure;
 pub mod processes;
+pub mod services;
 
-use crate::app::AppState;
+use crate::alert::Panel;
+use crate::app::{format_duration, AppState, BottomView};
+use crate::system::rollup::Zoom;
+use chrono::TimeZone;
 use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
+    text::{Line, Span},
     widgets::{Block, BorderType, Borders, Paragraph},
     Frame,
 };
@@ -16,27 +26,24 @@ pub fn render(f: &mut Frame, app: &mut AppState) {
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
-            Constraint::Length(3),  // Header
+            Constraint::Length(4),  // Header
             Constraint::Length(10), // top row (CPU + Memory)
-            Constraint::Length(10), // middle (Network)
+            Constraint::Length(10), // middle (Network, Pressure, Disks)
             Constraint::Min(10),    // bottom (Processes)
             Constraint::Length(3),  // Footer
         ])
         .split(f.size());
 
-    render_header(f, chunks[0]);
+    render_header(f, app, chunks[0]);
+    // Charts span about half the screen.
+    app.chart_page = (chunks[1].width / 2).max(1) as usize;
 
-    let has_gpu = app.gpus.is_some() && !app.gpus.as_ref().unwrap().is_empty();
+    let has_gpu = !app.snapshot().gpus.is_empty();
+    let has_battery = !app.snapshot().power.batteries.is_empty();
 
-    let top_row_constraints = if has_gpu {
-        vec![
-            Constraint::Percentage(33),
-            Constraint::Percentage(34),
-            Constraint::Percentage(33),
-        ]
-    } else {
-        vec![Constraint::Percentage(50), Constraint::Percentage(50)]
-    };
+    // CPU and memory, then the GPU and battery panels when there are any.
+    let panels = 2 + has_gpu as u32 + has_battery as u32;
+    let top_row_constraints = vec![Constraint::Ratio(1, panels); panels as usize];
 
     let top_chunks = Layout::default()
         .direction(Direction::Horizontal)
@@ -49,11 +56,48 @@ pub fn render(f: &mut Frame, app: &mut AppState) {
     if has_gpu {
         gpu::render(f, app, top_chunks[2]);
     }
+    if has_battery {
+        power::render(f, app, top_chunks[top_chunks.len() - 1]);
+    }
 
-    network::render(f, app, chunks[2]);
-    processes::render(f, app, chunks[3]);
+    // Network, then pressure and disks when there are any.
+    let mut side: Vec<fn(&mut Frame, &AppState, Rect)> = Vec::new();
+    if app.snapshot().pressure.is_some() {
+        side.push(pressure::render);
+    }
+    if app.snapshot().disks.iter().any(|d| d.total > 0) {
+        side.push(disks::render);
+    }
+    let middle_constraints = match side.len() {
+        0 => vec![Constraint::Percentage(100)],
+        1 => vec![Constraint::Percentage(60), Constraint::Percentage(40)],
+        _ => vec![
+            Constraint::Percentage(40),
+            Constraint::Percentage(35),
+            Constraint::Percentage(25),
+        ],
+    };
+    let middle = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(middle_constraints)
+        .split(chunks[2]);
+    network::render(f, app, middle[0]);
+    for (render, area) in side.into_iter().zip(middle.iter().skip(1)) {
+        render(f, app, *area);
+    }
+    match app.bottom_view {
+        BottomView::Processes => processes::render(f, app, chunks[3]),
+        BottomView::Containers => containers::render(f, app, chunks[3]),
+        BottomView::Services => services::render(f, app, chunks[3]),
+    }
 
     render_footer(f, app, chunks[4]);
+
+    processes::render_column_picker(f, app);
+    processes::render_affinity_dialog(f, app);
+    alerts::render(f, app);
+    memory::render_details(f, app);
+    services::render_confirm(f, app);
 }
 
 pub fn build_block(title: &str) -> Block<'static> {
@@ -64,28 +108,234 @@ pub fn build_block(title: &str) -> Block<'static> {
         .border_type(BorderType::Rounded)
 }
 
-fn render_header(f: &mut Frame, area: Rect) {
-    let host = sysinfo::System::host_name().unwrap_or_else(|| "unknown".to_string());
-    let title = format!(" ◈ NEXMON v{} │ host: {} ", env!("CARGO_PKG_VERSION"), host);
-    let p = Paragraph::new(title)
-        .style(
+/// `build_block` for a panel whose border flashes red while an alert on it
+/// is firing.
+pub fn alert_block(title: &str, app: &AppState, panel: Panel) -> Block<'static> {
+    let block = build_block(title);
+    if !app.panel_alerting(panel) {
+        return block;
+    }
+    let millis = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+    let color = if (millis / 500).is_multiple_of(2) {
+        ALERT_COLOR
+    } else {
+        Color::Yellow
+    };
+    block
+        .border_style(Style::default().fg(color).add_modifier(Modifier::BOLD))
+        .border_type(BorderType::Double)
+}
+
+pub const ALERT_COLOR: Color = Color::Rgb(255, 40, 40);
+
+/// Title of a panel with history charts, noting the zoom when not raw and
+/// how far back the charts are scrolled.
+pub fn chart_title(name: &str, app: &AppState) -> String {
+    let mut notes = Vec::new();
+    if app.zoom != Zoom::Raw {
+        notes.push(format!("{} max", app.zoom.label()));
+    }
+    if app.chart_offset > 0 {
+        notes.push(format!("{} ago", format_duration(app.chart_scroll())));
+    }
+    match notes.is_empty() {
+        true => format!(" {} ", name),
+        false => format!(" {} [{}] ", name, notes.join(", ")),
+    }
+}
+
+/// A `width` x `height` rect centered in `area`, clamped to fit.
+pub fn centered_rect(width: u16, height: u16, area: Rect) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
+}
+
+fn render_header(f: &mut Frame, app: &AppState, area: Rect) {
+    let system = &app.snapshot().system;
+    let title = format!(
+        " ◈ NEXMON v{} │ host: {} │ {} │ kernel {} │ up {} (booted {}) ",
+        env!("CARGO_PKG_VERSION"),
+        or_unknown(&system.host),
+        or_unknown(&system.os),
+        or_unknown(&system.kernel),
+        format_uptime(system.uptime),
+        chrono::Local
+            .timestamp_opt(system.boot_time as i64, 0)
+            .single()
+            .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_default(),
+    );
+    let mut spans = vec![Span::raw(title)];
+    let firing = app.snapshot().alerts.len();
+    if firing > 0 {
+        spans.push(Span::styled(
+            format!(
+                " ⚠ {} alert{} [!] ",
+                firing,
+                if firing == 1 { "" } else { "s" }
+            ),
+            Style::default()
+                .fg(Color::Black)
+                .bg(ALERT_COLOR)
+                .add_modifier(Modifier::BOLD),
+        ));
+    }
+
+    let [one, five, fifteen] = system.load;
+    let tasks = &system.tasks;
+    let stats = Line::from(vec![
+        Span::raw(format!(
+            " load {:.2} {:.2} {:.2} │ tasks {}, {} thr: {} running, {} sleeping, {} stopped, ",
+            one,
+            five,
+            fifteen,
+            tasks.total,
+            tasks.threads,
+            tasks.running,
+            tasks.sleeping,
+            tasks.stopped
+        )),
+        Span::styled(
+            format!("{} zombie", tasks.zombie),
+            if tasks.zombie > 0 {
+                Style::default().fg(Color::Yellow)
+            } else {
+                Style::default()
+            },
+        ),
+    ])
+    .style(Style::default().fg(Color::Gray));
+
+    let p = Paragraph::new(vec![
+        Line::from(spans).style(
             Style::default()
                 .fg(Color::Rgb(0, 255, 180))
                 .add_modifier(Modifier::BOLD),
-        )
-        .block(build_block(""));
+        ),
+        stats,
+    ])
+    .block(build_block(""));
     f.render_widget(p, area);
 }
 
+fn or_unknown(value: &str) -> &str {
+    if value.is_empty() {
+        "unknown"
+    } else {
+        value
+    }
+}
+
+/// "3d 04:12" or "04:12" (hours and minutes).
+fn format_uptime(secs: u64) -> String {
+    let days = secs / 86_400;
+    let hm = format!("{:02}:{:02}", secs % 86_400 / 3600, secs % 3600 / 60);
+    if days > 0 {
+        format!("{}d {}", days, hm)
+    } else {
+        hm
+    }
+}
+
 fn render_footer(f: &mut Frame, app: &AppState, area: Rect) {
-    let text = if app.search_mode {
-        format!("Search: {}_ (Press Enter to exit search)", app.search_query)
+    let line = if let Some(name) = &app.filter_name_input {
+        Line::from(format!(
+            "Save filter as: @{}_ (Enter to save, Esc to cancel)",
+            name
+        ))
+    } else if app.search_mode {
+        let mut spans = vec![Span::raw(format!("Filter: {}_ ", app.search_query))];
+        match &app.filter_error {
+            Some(err) => spans.push(Span::styled(
+                err.clone(),
+                Style::default().fg(Color::Rgb(255, 60, 120)),
+            )),
+            None => spans.push(Span::raw("(Enter to apply, Ctrl+S to save)")),
+        }
+        Line::from(spans)
+    } else if let Some(msg) = &app.status_message {
+        Line::from(msg.clone())
+    } else if let Some(replay) = app.replay {
+        let (badge, color) = if replay.playing {
+            (" ▶ REPLAY ", Color::Rgb(0, 255, 180))
+        } else {
+            (" ⏸ REPLAY ", Color::Rgb(255, 60, 120))
+        };
+        Line::from(vec![
+            Span::styled(
+                badge,
+                Style::default()
+                    .fg(Color::Black)
+                    .bg(color)
+                    .add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(format!(
+                "  {}  {}/{}  {}x  [Space]play/pause  [[/]]step  [{{/}}]step 10  [0-9]seek  [</>]speed  [Q]uit",
+                format_time(app.snapshot().timestamp),
+                replay.position + 1,
+                replay.len,
+                replay.speed
+            )),
+        ])
+    } else if app.is_paused() {
+        let snapshot = app.snapshot();
+        let latest = app.history.latest();
+        let behind = latest.seq - snapshot.seq;
+        let secs = latest.timestamp.saturating_sub(snapshot.timestamp) as f64 / 1000.0;
+        let time = format_time(snapshot.timestamp);
+        Line::from(vec![
+            Span::styled(
+                " ⏸ PAUSED ",
+                Style::default()
+                    .fg(Color::Black)
+                    .bg(Color::Rgb(255, 60, 120))
+                    .add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(format!(
+                "  {}  -{} samples ({:.1}s behind, {} kept)  [Space]resume  [[/]]step  [{{/}}]step 10",
+                time,
+                behind,
+                secs,
+                app.history.len()
+            )),
+        ])
     } else {
-        "[Q]uit  [J/K/↑/↓]scroll  [C]pu  [M]em  [P]id  [N]ame  [/]search  [R]everse".to_string()
+        let hints = "[Q]uit  [J/K/↑/↓]scroll  [C]pu  [M]em  [P]id  [N]ame  [/]filter  [R]everse  [G]roup  [T]hreads  [+/-]nice  [X]affinity  [Shift+F]ollow  [A]rgs  [F]ields  [Tab]view  [Space]pause";
+        match &app.remote {
+            Some(addr) => Line::from(vec![
+                Span::styled(
+                    format!(" ⇄ {} ", addr),
+                    Style::default()
+                        .fg(Color::Black)
+                        .bg(Color::Rgb(0, 255, 180))
+                        .add_modifier(Modifier::BOLD),
+                ),
+                Span::raw(format!("  {}", hints)),
+            ]),
+            None => Line::from(hints),
+        }
     };
 
-    let p = Paragraph::new(text)
+    let p = Paragraph::new(line)
         .style(Style::default().fg(Color::Gray))
         .block(build_block(""));
     f.render_widget(p, area);
 }
+
+/// Local wall-clock time of a snapshot timestamp.
+pub(crate) fn format_time(timestamp: u64) -> String {
+    chrono::Local
+        .timestamp_millis_opt(timestamp as i64)
+        .single()
+        .map(|t| t.format("%H:%M:%S").to_string())
+        .unwrap_or_default()
+}
@@ -1,4 +1,9 @@
-use crate::{app::AppState, ui::build_block};
+use crate::{
+    alert::Panel,
+    app::AppState,
+    system::rollup::keys,
+    ui::{alert_block, chart_title},
+};
 use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Style},
@@ -7,15 +12,16 @@ use ratatui::{
 };
 
 pub fn render(f: &mut Frame, app: &AppState, area: Rect) {
-    let block = build_block(" Network ");
+    let block = alert_block(&chart_title("Network", app), app, Panel::Network);
     let inner_area = block.inner(area);
     f.render_widget(block, area);
 
-    if app.network_history.is_empty() {
+    let networks = &app.snapshot().networks;
+    if networks.is_empty() {
         return;
     }
 
-    let rows_count = app.network_history.len() as u16;
+    let rows_count = networks.len() as u16;
     let constraints: Vec<_> = (0..rows_count).map(|_| Constraint::Length(4)).collect();
 
     let layout = Layout::default()
@@ -23,7 +29,7 @@ pub fn render(f: &mut Frame, app: &AppState, area: Rect) {
         .constraints(constraints)
         .split(inner_area);
 
-    for (i, net) in app.network_history.iter().enumerate() {
+    for (i, net) in networks.iter().enumerate() {
         if i >= layout.len() {
             break;
         }
@@ -44,17 +50,17 @@ pub fn render(f: &mut Frame, app: &AppState, area: Rect) {
 
         let header = Paragraph::new(format!(
             "↓ {}  RX: {:.2} MB/s  TX: {:.2} MB/s",
-            net.interface_name, rx_mb, tx_mb
+            net.name, rx_mb, tx_mb
         ));
         f.render_widget(header, sub_chunks[0]);
 
-        let rx_data: Vec<u64> = net.rx_history.iter().copied().collect();
+        let rx_data = app.series(&keys::net_rx(&net.name), sub_chunks[1].width);
         let rx_spark = Sparkline::default()
             .data(&rx_data)
             .style(Style::default().fg(Color::Green));
         f.render_widget(rx_spark, sub_chunks[1]);
 
-        let tx_data: Vec<u64> = net.tx_history.iter().copied().collect();
+        let tx_data = app.series(&keys::net_tx(&net.name), sub_chunks[2].width);
         let tx_spark = Sparkline::default()
             .data(&tx_data)
             .style(Style::default().fg(Color::Yellow));
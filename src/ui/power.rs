@@ -0,0 +1,100 @@
+use crate::{
+    app::AppState,
+    system::{power::Battery, rollup::keys},
+    ui::{build_block, chart_title},
+};
+use ratatui::{
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Style},
+    text::{Line, Span},
+    widgets::{Gauge, Paragraph, Sparkline},
+    Frame,
+};
+
+/// Charge, rate, time left and health of each battery, and the AC adapter.
+pub fn render(f: &mut Frame, app: &AppState, area: Rect) {
+    let block = build_block(&chart_title("Battery", app));
+    let inner_area = block.inner(area);
+    f.render_widget(block, area);
+
+    let power = &app.snapshot().power;
+    let Some(first) = power.batteries.first() else {
+        return;
+    };
+    // A gauge and a line of details per battery, the adapter, then the
+    // charge history of the first battery.
+    let mut constraints = vec![Constraint::Length(2); power.batteries.len()];
+    constraints.push(Constraint::Length(1));
+    constraints.push(Constraint::Min(0));
+    let layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(constraints)
+        .split(inner_area);
+
+    for (battery, area) in power.batteries.iter().zip(layout.iter()) {
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(1), Constraint::Length(1)])
+            .split(*area);
+        let gauge = Gauge::default()
+            .gauge_style(Style::default().fg(charge_color(battery)))
+            .percent(battery.percent.round() as u16)
+            .label(format!(
+                "{} {:.0}% {}",
+                battery.name, battery.percent, battery.status
+            ));
+        f.render_widget(gauge, rows[0]);
+        f.render_widget(Paragraph::new(details(battery)), rows[1]);
+    }
+
+    let adapter = layout[power.batteries.len()];
+    let ac = match power.ac_online {
+        Some(true) => Span::styled("AC online", Style::default().fg(Color::Green)),
+        Some(false) => Span::styled("on battery", Style::default().fg(Color::Yellow)),
+        None => Span::styled("no AC adapter", Style::default().fg(Color::DarkGray)),
+    };
+    f.render_widget(Paragraph::new(Line::from(ac)), adapter);
+
+    let history_area = layout[power.batteries.len() + 1];
+    let history_data = app.series(&keys::battery(&first.name), history_area.width);
+    let spark = Sparkline::default()
+        .data(&history_data)
+        .max(100)
+        .style(Style::default().fg(charge_color(first)));
+    f.render_widget(spark, history_area);
+}
+
+fn charge_color(battery: &Battery) -> Color {
+    match battery.percent {
+        _ if battery.charging() => Color::Cyan,
+        p if p < 15.0 => Color::Red,
+        p if p < 40.0 => Color::Yellow,
+        _ => Color::Green,
+    }
+}
+
+/// "9.5 W · 3:06 left · health 90% (45.0/50.0 Wh)", leaving out what the
+/// driver does not report.
+fn details(battery: &Battery) -> Line<'static> {
+    let mut parts = Vec::new();
+    if let Some(power) = battery.power.filter(|&p| p > 0.0) {
+        parts.push(format!("{:.1} W", power));
+    }
+    if let Some(secs) = battery.time_remaining {
+        let until = if battery.charging() {
+            "to full"
+        } else {
+            "left"
+        };
+        parts.push(format!("{}:{:02} {}", secs / 3600, secs % 3600 / 60, until));
+    }
+    if let Some(health) = battery.health() {
+        parts.push(format!(
+            "health {:.0}% ({:.1}/{:.1} Wh)",
+            health,
+            battery.energy_full.unwrap_or_default(),
+            battery.energy_full_design.unwrap_or_default()
+        ));
+    }
+    Line::styled(parts.join(" · "), Style::default().fg(Color::Gray))
+}
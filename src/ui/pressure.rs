@@ -0,0 +1,85 @@
+use crate::{
+    app::AppState,
+    system::{
+        psi::{PsiAverages, Resource},
+        rollup::keys,
+    },
+    ui::{build_block, chart_title},
+};
+use ratatui::{
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Style},
+    widgets::{Gauge, Sparkline},
+    Frame,
+};
+
+/// Stall gauges and `some` avg10 history for CPU, memory and IO; those of
+/// the viewed cgroup when there is one, otherwise system-wide.
+pub fn render(f: &mut Frame, app: &AppState, area: Rect) {
+    let snapshot = app.snapshot();
+    let (title, pressure, cgroup) = match (&snapshot.cgroup_pressure, &snapshot.pressure) {
+        (Some(cgroup), _) => (
+            format!("Pressure: {}", cgroup.path),
+            &cgroup.pressure,
+            Some(cgroup.path.as_str()),
+        ),
+        (None, Some(pressure)) => ("Pressure".to_string(), pressure, None),
+        (None, None) => return,
+    };
+
+    let block = build_block(&chart_title(&title, app));
+    let inner_area = block.inner(area);
+    f.render_widget(block, area);
+
+    let layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(2); 3])
+        .split(inner_area);
+
+    for (resource, chunk) in Resource::ALL.into_iter().zip(layout.iter()) {
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(1), Constraint::Length(1)])
+            .split(*chunk);
+
+        let psi = pressure.get(resource);
+        let full = psi
+            .full
+            .map(|full| format!("  full {}", averages(&full)))
+            .unwrap_or_default();
+        let gauge = Gauge::default()
+            .gauge_style(Style::default().fg(get_color(psi.some.avg10)))
+            .percent(psi.some.avg10.clamp(0.0, 100.0) as u16)
+            .label(format!(
+                "{:<6} some {}{}",
+                resource.name(),
+                averages(&psi.some),
+                full
+            ));
+        f.render_widget(gauge, rows[0]);
+
+        let key = match cgroup {
+            Some(path) => keys::cgroup_psi(path, resource.name()),
+            None => keys::psi(resource.name()),
+        };
+        let data = app.series(&key, rows[1].width);
+        let spark = Sparkline::default()
+            .data(&data)
+            .style(Style::default().fg(Color::Magenta));
+        f.render_widget(spark, rows[1]);
+    }
+}
+
+fn averages(a: &PsiAverages) -> String {
+    format!("{:.2}/{:.2}/{:.2}", a.avg10, a.avg60, a.avg300)
+}
+
+fn get_color(stall: f64) -> Color {
+    if stall > 40.0 {
+        Color::Red
+    } else if stall > 10.0 {
+        Color::Yellow
+    } else {
+        Color::Green
+    }
+}
@@ -1,36 +1,104 @@
 use crate::{
-    app::{AppState, SortColumn},
-    ui::build_block,
+    alert::Panel,
+    app::{AppState, GroupBy, ProcessColumn, ProcessGroup, ProcessInfo, ProcessRow},
+    ui::{alert_block, build_block, centered_rect},
 };
 use ratatui::{
     layout::{Constraint, Rect},
     style::{Color, Modifier, Style},
-    widgets::{Row, Table},
+    widgets::{Clear, List, ListItem, ListState, Row, Table},
     Frame,
 };
 
 pub fn render(f: &mut Frame, app: &mut AppState, area: Rect) {
-    let block = build_block(" Processes ");
+    let threads = if app.show_threads { " + threads" } else { "" };
+    let title = match app.group_by {
+        GroupBy::None => format!(" Processes{} ", threads),
+        group_by => format!(" Processes{} by {} ", threads, group_by.label()),
+    };
+    let title = match app.followed_pid() {
+        Some(pid) => format!("{}[follow {}] ", title, pid),
+        None => title,
+    };
+    let block = alert_block(&title, app, Panel::Processes);
+    // Borders, header and its bottom margin take four lines.
+    app.process_page_size = area.height.saturating_sub(4) as usize;
 
-    let sort_indicator = |col: SortColumn| -> &str {
-        if app.sort_column == col {
-            if app.sort_ascending {
-                "▲"
-            } else {
-                "▼"
+    let sort_indicator = |col: ProcessColumn| -> &str {
+        match col.sort_column() {
+            Some(sort) if sort == app.sort_column => {
+                if app.sort_ascending {
+                    "▲"
+                } else {
+                    "▼"
+                }
             }
-        } else {
-            ""
+            _ => "",
         }
     };
 
-    let header_cells = vec![
-        format!("PID {}", sort_indicator(SortColumn::Pid)),
-        format!("Name {}", sort_indicator(SortColumn::Name)),
-        format!("CPU% {}", sort_indicator(SortColumn::Cpu)),
-        format!("MEM {}", sort_indicator(SortColumn::Memory)),
-        "Status".to_string(),
-    ];
+    let header_cells: Vec<String> = app
+        .columns
+        .iter()
+        .map(|&col| format!("{} {}", col.title(), sort_indicator(col)))
+        .collect();
+
+    let total_memory = app.snapshot().memory.total;
+    let label_column = group_label_column(app);
+    let cells: Vec<Vec<String>> = app
+        .process_rows
+        .iter()
+        .map(|row| match row {
+            ProcessRow::Process(_) | ProcessRow::Member(_) | ProcessRow::Thread(_) => {
+                let p = row_process(app, row).expect("non-group row");
+                let indent = match row {
+                    ProcessRow::Member(_) => "  ",
+                    ProcessRow::Thread(_) => "  ↳ ",
+                    _ => "",
+                };
+                app.columns
+                    .iter()
+                    .map(|&col| match col {
+                        ProcessColumn::Name => format!("{}{}", indent, app.display_name(p)),
+                        _ => cell_text(col, p, total_memory),
+                    })
+                    .collect()
+            }
+            ProcessRow::Group(g) => app
+                .columns
+                .iter()
+                .map(|&col| {
+                    if Some(col) == label_column {
+                        let arrow = if g.expanded { "▾" } else { "▸" };
+                        let key = if g.key.is_empty() { "?" } else { &g.key };
+                        format!("{} {} ({})", arrow, key, g.count)
+                    } else {
+                        group_cell_text(col, g, total_memory)
+                    }
+                })
+                .collect(),
+        })
+        .collect();
+
+    // Size each column to its widest cell; the command line takes whatever is left.
+    let widths: Vec<Constraint> = app
+        .columns
+        .iter()
+        .enumerate()
+        .map(|(i, &col)| {
+            let content = cells
+                .iter()
+                .map(|row| row[i].chars().count())
+                .max()
+                .unwrap_or(0);
+            let width = content.max(header_cells[i].chars().count()) as u16;
+            if col == ProcessColumn::Command || (col == ProcessColumn::Name && app.show_cmdline) {
+                Constraint::Min(width.min(20))
+            } else {
+                Constraint::Length(width)
+            }
+        })
+        .collect();
 
     let header = Row::new(header_cells)
         .style(
@@ -41,48 +109,260 @@ pub fn render(f: &mut Frame, app: &mut AppState, area: Rect) {
         .bottom_margin(1);
 
     let rows: Vec<Row> = app
-        .processes
+        .process_rows
         .iter()
-        .map(|p| {
-            let mem_mb = p.memory as f64 / 1_048_576.0;
+        .zip(cells)
+        .map(|(row, cells)| {
+            let (cpu_usage, memory, is_thread) = match row {
+                ProcessRow::Group(g) => (g.cpu_usage, g.memory, false),
+                _ => {
+                    let p = row_process(app, row).expect("non-group row");
+                    (p.cpu_usage, p.memory, p.thread_of.is_some())
+                }
+            };
+            let mem_mb = memory as f64 / 1_048_576.0;
 
             let mut row_style = Style::default();
-            if p.cpu_usage > 50.0 {
+            if cpu_usage > 50.0 {
                 row_style = row_style.fg(Color::Rgb(255, 60, 120)); // neon pink
+            } else if is_thread {
+                // Threads share their process's memory, so have none to flag.
+                row_style = row_style.fg(Color::Gray);
             } else if mem_mb > 1024.0 {
                 row_style = row_style.fg(Color::Yellow);
             }
+            if matches!(row, ProcessRow::Group(_)) {
+                row_style = row_style.add_modifier(Modifier::BOLD);
+            }
 
-            Row::new(vec![
-                p.pid.to_string(),
-                p.name.clone(),
-                format!("{:.1}%", p.cpu_usage),
-                format!("{:.1} MB", mem_mb),
-                p.status.clone(),
-            ])
-            .style(row_style)
+            Row::new(cells).style(row_style)
         })
         .collect();
 
-    let table = Table::new(
-        rows,
-        [
-            Constraint::Percentage(10),
-            Constraint::Percentage(40),
-            Constraint::Percentage(15),
-            Constraint::Percentage(15),
-            Constraint::Percentage(20),
-        ],
-    )
-    .header(header)
-    .block(block)
-    .highlight_style(
-        Style::default()
-            .bg(Color::DarkGray)
-            .fg(Color::Cyan)
-            .add_modifier(Modifier::BOLD),
-    )
-    .highlight_symbol(">> ");
+    let table = Table::new(rows, widths)
+        .header(header)
+        .block(block)
+        .highlight_style(
+            Style::default()
+                .bg(Color::DarkGray)
+                .fg(Color::Cyan)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol(">> ");
 
     f.render_stateful_widget(table, area, &mut app.process_table_state);
 }
+
+fn row_process<'a>(app: &'a AppState, row: &ProcessRow) -> Option<&'a ProcessInfo> {
+    match row {
+        ProcessRow::Process(i) | ProcessRow::Member(i) => Some(&app.processes[*i]),
+        ProcessRow::Thread(i) => Some(&app.threads[*i]),
+        ProcessRow::Group(_) => None,
+    }
+}
+
+/// The visible column a group's key is written into: the one matching the
+/// grouping, else Name or Command, else the first column.
+fn group_label_column(app: &AppState) -> Option<ProcessColumn> {
+    let preferred = match app.group_by {
+        GroupBy::None => return None,
+        GroupBy::Name => ProcessColumn::Name,
+        GroupBy::User => ProcessColumn::User,
+        GroupBy::Cgroup => ProcessColumn::Cgroup,
+    };
+    [preferred, ProcessColumn::Name, ProcessColumn::Command]
+        .into_iter()
+        .find(|c| app.columns.contains(c))
+        .or_else(|| app.columns.first().copied())
+}
+
+fn group_cell_text(column: ProcessColumn, g: &ProcessGroup, total_memory: u64) -> String {
+    match column {
+        ProcessColumn::Cpu => format!("{:.1}%", g.cpu_usage),
+        ProcessColumn::Mem => format_mb(g.memory),
+        ProcessColumn::MemPercent if total_memory > 0 => {
+            format!("{:.1}%", g.memory as f64 / total_memory as f64 * 100.0)
+        }
+        ProcessColumn::Virt => format_mb(g.virtual_memory),
+        ProcessColumn::Pss => format_mb(g.pss),
+        ProcessColumn::Uss => format_mb(g.uss),
+        ProcessColumn::Swap => format_mb(g.swap),
+        ProcessColumn::Read => format_rate(g.read_rate),
+        ProcessColumn::Write => format_rate(g.write_rate),
+        _ => String::new(),
+    }
+}
+
+fn cell_text(column: ProcessColumn, p: &ProcessInfo, total_memory: u64) -> String {
+    let opt = |v: Option<String>| v.unwrap_or_else(|| "-".to_string());
+    // Threads share their process's memory.
+    let thread = p.thread_of.is_some();
+    match column {
+        ProcessColumn::Pid => p.pid.to_string(),
+        ProcessColumn::Ppid => opt(p.ppid.map(|v| v.to_string())),
+        ProcessColumn::User => p.user.clone(),
+        ProcessColumn::Name => p.name.clone(),
+        ProcessColumn::Command => {
+            if p.cmd.is_empty() {
+                format!("[{}]", p.name)
+            } else {
+                p.cmd.clone()
+            }
+        }
+        ProcessColumn::Exe => opt(Some(p.exe.clone()).filter(|e| !e.is_empty())),
+        ProcessColumn::Cpu => format!("{:.1}%", p.cpu_usage),
+        ProcessColumn::Mem | ProcessColumn::MemPercent | ProcessColumn::Virt if thread => {
+            "-".to_string()
+        }
+        ProcessColumn::Mem => format_mb(p.memory),
+        ProcessColumn::MemPercent => {
+            if total_memory > 0 {
+                format!("{:.1}%", p.memory as f64 / total_memory as f64 * 100.0)
+            } else {
+                "-".to_string()
+            }
+        }
+        ProcessColumn::Virt => format_mb(p.virtual_memory),
+        ProcessColumn::Pss => opt(p.pss.map(format_mb)),
+        ProcessColumn::Uss => opt(p.uss.map(format_mb)),
+        ProcessColumn::Swap => opt(p.swap.map(format_mb)),
+        ProcessColumn::Threads => opt(p.threads.map(|v| v.to_string())),
+        ProcessColumn::State => p.status.clone(),
+        ProcessColumn::Nice => opt(p.nice.map(|v| v.to_string())),
+        ProcessColumn::Priority => opt(p.priority.map(|v| v.to_string())),
+        ProcessColumn::Start => format_start(p.start_time),
+        ProcessColumn::CpuTime => opt(p.cpu_time.map(format_cpu_time)),
+        ProcessColumn::Read => format_rate(p.read_rate),
+        ProcessColumn::Write => format_rate(p.write_rate),
+        ProcessColumn::GpuMem => opt(p.gpu_memory.map(format_mb)),
+        ProcessColumn::Cgroup => opt(Some(p.cgroup.clone()).filter(|c| !c.is_empty())),
+        ProcessColumn::Container => opt(Some(p.container.clone()).filter(|c| !c.is_empty())),
+    }
+}
+
+pub(crate) fn format_mb(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / 1_048_576.0)
+}
+
+pub(crate) fn format_rate(bytes_per_sec: u64) -> String {
+    let b = bytes_per_sec as f64;
+    if b >= 1_048_576.0 {
+        format!("{:.1} MB/s", b / 1_048_576.0)
+    } else if b >= 1024.0 {
+        format!("{:.1} KB/s", b / 1024.0)
+    } else {
+        format!("{} B/s", bytes_per_sec)
+    }
+}
+
+/// Start time as a local clock time for today, otherwise as a date.
+fn format_start(epoch_secs: u64) -> String {
+    use chrono::{Local, TimeZone};
+
+    match Local.timestamp_opt(epoch_secs as i64, 0).single() {
+        Some(start) if start.date_naive() == Local::now().date_naive() => {
+            start.format("%H:%M").to_string()
+        }
+        Some(start) => start.format("%b%d").to_string(),
+        None => "-".to_string(),
+    }
+}
+
+/// CPU time in htop's `M:SS.cc` form, switching to `HhMM:SS` past an hour.
+fn format_cpu_time(secs: f64) -> String {
+    let whole = secs as u64;
+    if whole >= 3600 {
+        format!(
+            "{}h{:02}:{:02}",
+            whole / 3600,
+            (whole % 3600) / 60,
+            whole % 60
+        )
+    } else {
+        format!("{}:{:05.2}", whole / 60, secs % 60.0)
+    }
+}
+
+pub fn render_column_picker(f: &mut Frame, app: &AppState) {
+    let Some(picker) = &app.column_picker else {
+        return;
+    };
+
+    let area = centered_rect(40, picker.entries.len() as u16 + 4, f.size());
+    let items: Vec<ListItem> = picker
+        .entries
+        .iter()
+        .map(|(col, on)| {
+            let mark = if *on { "[x]" } else { "[ ]" };
+            let style = if *on {
+                Style::default().fg(Color::Cyan)
+            } else {
+                Style::default().fg(Color::DarkGray)
+            };
+            ListItem::new(format!("{} {}", mark, col.title())).style(style)
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(build_block(" Columns  [Space]toggle [J/K]move [S]ave "))
+        .highlight_style(
+            Style::default()
+                .bg(Color::DarkGray)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol(">> ");
+
+    let mut state = ListState::default();
+    state.select(Some(picker.cursor));
+
+    f.render_widget(Clear, area);
+    f.render_stateful_widget(list, area, &mut state);
+}
+
+pub fn render_affinity_dialog(f: &mut Frame, app: &AppState) {
+    let Some(dialog) = &app.affinity_dialog else {
+        return;
+    };
+
+    let area = centered_rect(44, dialog.cpus.len() as u16 + 4, f.size());
+    let items: Vec<ListItem> = dialog
+        .cpus
+        .iter()
+        .enumerate()
+        .map(|(i, on)| {
+            let mark = if *on { "[x]" } else { "[ ]" };
+            let (name, usage) = app
+                .snapshot()
+                .cpus
+                .get(i)
+                .map(|c| (c.name.clone(), c.usage))
+                .unwrap_or_else(|| (format!("cpu{}", i), 0.0));
+            let style = if *on {
+                Style::default().fg(Color::Cyan)
+            } else {
+                Style::default().fg(Color::DarkGray)
+            };
+            ListItem::new(format!("{} {:<8} {:>5.1}%", mark, name, usage)).style(style)
+        })
+        .collect();
+
+    let title = format!(
+        " Affinity of {}{}  [Space]toggle [A]ll [Enter]apply ",
+        if dialog.thread { "thread " } else { "" },
+        dialog.pid
+    );
+    let list = List::new(items)
+        .block(build_block(&title))
+        .highlight_style(
+            Style::default()
+                .bg(Color::DarkGray)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol(">> ");
+
+    let mut state = ListState::default();
+    state.select(Some(dialog.cursor));
+
+    f.render_widget(Clear, area);
+    f.render_stateful_widget(list, area, &mut state);
+}
@@ -0,0 +1,154 @@
+use crate::{
+    app::AppState,
+    system::cgroups::CgroupSample,
+    ui::{
+        build_block, centered_rect,
+        processes::{format_mb, format_rate},
+    },
+};
+use ratatui::{
+    layout::{Constraint, Rect},
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{Clear, Paragraph, Row, Table},
+    Frame,
+};
+use std::collections::HashMap;
+
+/// systemd services with their state and the usage of their cgroups.
+pub fn render(f: &mut Frame, app: &mut AppState, area: Rect) {
+    let snapshot = app.snapshot().clone();
+    let units = &snapshot.services;
+    let failed = units.iter().filter(|u| u.active == "failed").count();
+    let title = format!(
+        " Services ({} units, {} failed) [Enter]processes [r]estart [s]top [Tab]processes ",
+        units.len(),
+        failed
+    );
+    let block = build_block(&title);
+    // Borders, header and its bottom margin take four lines.
+    app.service_page_size = area.height.saturating_sub(4) as usize;
+
+    if units.is_empty() {
+        let text = if app.replay.is_some() {
+            "This recording has no services: they are only recorded while the Services view is open."
+        } else {
+            "Asking systemd for its services..."
+        };
+        let p =
+            Paragraph::new(Line::styled(text, Style::default().fg(Color::DarkGray))).block(block);
+        f.render_widget(p, area);
+        return;
+    }
+
+    let cgroups = unit_cgroups(&snapshot.cgroups);
+    let header = Row::new(vec![
+        "Unit",
+        "Active",
+        "Sub",
+        "CPU%",
+        "MEM",
+        "Read/s",
+        "Write/s",
+        "Tasks",
+        "Description",
+    ])
+    .style(
+        Style::default()
+            .fg(Color::Cyan)
+            .add_modifier(Modifier::BOLD),
+    )
+    .bottom_margin(1);
+
+    let rows: Vec<Row> = units
+        .iter()
+        .map(|u| {
+            let cgroup = cgroups.get(u.unit.as_str());
+            let usage = |f: fn(&CgroupSample) -> Option<String>| {
+                cgroup.and_then(|c| f(c)).unwrap_or_else(|| "-".to_string())
+            };
+            let style = match u.active.as_str() {
+                "failed" => Style::default().fg(Color::Rgb(255, 60, 120)),
+                "active" => Style::default(),
+                "inactive" => Style::default().fg(Color::DarkGray),
+                // activating, deactivating, reloading...
+                _ => Style::default().fg(Color::Yellow),
+            };
+            Row::new(vec![
+                u.unit.clone(),
+                u.active.clone(),
+                u.sub.clone(),
+                usage(|c| Some(format!("{:.1}", c.cpu_usage))),
+                usage(|c| c.memory.map(format_mb)),
+                usage(|c| Some(format_rate(c.read_rate))),
+                usage(|c| Some(format_rate(c.write_rate))),
+                usage(|c| c.pids.map(|v| v.to_string())),
+                u.description.clone(),
+            ])
+            .style(style)
+        })
+        .collect();
+
+    let widths = [
+        Constraint::Length(32),
+        Constraint::Length(12),
+        Constraint::Length(10),
+        Constraint::Length(7),
+        Constraint::Length(11),
+        Constraint::Length(11),
+        Constraint::Length(11),
+        Constraint::Length(6),
+        Constraint::Min(20),
+    ];
+    let table = Table::new(rows, widths)
+        .header(header)
+        .block(block)
+        .highlight_style(
+            Style::default()
+                .bg(Color::DarkGray)
+                .fg(Color::Cyan)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol(">> ");
+
+    app.service_table_state.select(Some(app.service_index()));
+    f.render_stateful_widget(table, area, &mut app.service_table_state);
+}
+
+/// The cgroup of each unit by name, as `AppState::unit_cgroup` picks it.
+fn unit_cgroups(cgroups: &[CgroupSample]) -> HashMap<&str, &CgroupSample> {
+    let mut by_unit: HashMap<&str, &CgroupSample> = HashMap::new();
+    for c in cgroups {
+        let entry = by_unit.entry(c.name()).or_insert(c);
+        if c.depth() < entry.depth() {
+            *entry = c;
+        }
+    }
+    by_unit
+}
+
+/// Asks before restarting or stopping a unit.
+pub fn render_confirm(f: &mut Frame, app: &AppState) {
+    let Some((action, unit)) = &app.service_confirm else {
+        return;
+    };
+    let text = format!(" {} {}? [y/N] ", capitalize(action.verb()), unit);
+    let area = centered_rect(text.chars().count() as u16 + 4, 3, f.size());
+    f.render_widget(Clear, area);
+    f.render_widget(
+        Paragraph::new(Line::styled(
+            text,
+            Style::default().add_modifier(Modifier::BOLD),
+        ))
+        .block(build_block(" Confirm ")),
+        area,
+    );
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}